@@ -0,0 +1,19 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use stack::bench::WORKLOADS;
+use stack::{Executor, Mode};
+
+/// Measure each workload on the new executor, which isn't timed
+fn interpreter(c: &mut Criterion) {
+    for workload in WORKLOADS {
+        c.bench_function(workload.name, |b| {
+            b.iter_batched_ref(
+                || Executor::new(Mode::Script),
+                |executor| workload.run(executor),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+use crate::{Executor, Mode, StackError};
+use std::time::{Duration, Instant};
+
+/// Part of the interpreter which the workload measures
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Tokenize,
+    Evaluate,
+}
+
+/// Program measured by `cargo bench` and `stack bench-self`
+#[derive(Clone, Copy, Debug)]
+pub struct Workload {
+    pub name: &'static str,
+    pub phase: Phase,
+    pub code: &'static str,
+}
+
+/// Workloads of the benchmarks, each is small enough to run many times
+pub const WORKLOADS: &[Workload] = &[
+    Workload {
+        name: "tokenize",
+        phase: Phase::Tokenize,
+        code: include_str!("../examples/fizzbuzz.stk"),
+    },
+    Workload {
+        name: "loop-dispatch",
+        phase: Phase::Evaluate,
+        code: "0 (i) var (i 1 add (i) set!) (i 10000 less) while",
+    },
+    Workload {
+        name: "list-ops",
+        phase: Phase::Evaluate,
+        code: "0 10000 1 range (x) (x 2 mul) map (x) (x 3 mod 0 equal) filter sort len",
+    },
+    Workload {
+        name: "string-building",
+        phase: Phase::Evaluate,
+        code: "() (s) var 0 (i) var (s (ab) concat (s) set! i 1 add (i) set!) (i 2000 less) while",
+    },
+];
+
+impl Workload {
+    /// Run the workload once on the executor
+    pub fn run(&self, executor: &mut Executor) -> Result<(), StackError> {
+        match self.phase {
+            Phase::Tokenize => {
+                executor.analyze_syntax(self.code.to_string());
+                Ok(())
+            }
+            Phase::Evaluate => executor.evaluate_program(self.code.to_string()),
+        }
+    }
+
+    /// Get the mean time of the runs, each on the new executor which isn't timed
+    pub fn measure(&self, runs: usize) -> Result<Duration, StackError> {
+        let mut total = Duration::ZERO;
+        for _ in 0..runs {
+            let mut executor = Executor::new(Mode::Script);
+            let start = Instant::now();
+            self.run(&mut executor)?;
+            total += start.elapsed();
+        }
+        Ok(total / runs.max(1) as u32)
+    }
+}
@@ -0,0 +1,51 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Marker at the end of the bundled executable
+const MAGIC: &[u8; 8] = b"STKBNDL1";
+
+/// Make the executable that has the program embedded
+pub fn create(program: String, output: &Path) -> io::Result<()> {
+    let mut binary = fs::read(env::current_exe()?)?;
+    // Don't bundle twice, when it is made from a bundled executable
+    if let Some(length) = payload_length(&binary) {
+        binary.truncate(binary.len().saturating_sub(length + 16));
+    }
+
+    binary.extend_from_slice(program.as_bytes());
+    binary.extend_from_slice(&(program.len() as u64).to_le_bytes());
+    binary.extend_from_slice(MAGIC);
+    fs::write(output, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Get the program embedded in the running executable
+pub fn embedded() -> Option<String> {
+    let mut file = File::open(env::current_exe().ok()?).ok()?;
+    let mut trailer = [0u8; 16];
+    file.seek(SeekFrom::End(-16)).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let length = payload_length(&trailer)?;
+
+    let mut program = vec![0u8; length];
+    file.seek(SeekFrom::End(-16 - length as i64)).ok()?;
+    file.read_exact(&mut program).ok()?;
+    String::from_utf8(program).ok()
+}
+
+/// Get the length of the payload from the end of the data
+fn payload_length(data: &[u8]) -> Option<usize> {
+    let trailer = data.get(data.len().checked_sub(16)?..)?;
+    if &trailer[8..] != MAGIC {
+        return None;
+    }
+    Some(u64::from_le_bytes(trailer[..8].try_into().ok()?) as usize)
+}
@@ -0,0 +1,75 @@
+use crate::json::{from_json, stack_to_json, to_json};
+use crate::Type;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Commands whose results are kept in the cassette
+pub const COMMANDS: &[&str] = &["request", "request-response", "input", "read-line"];
+
+/// Results of the external interactions kept in the JSON file, to replay them in the tests
+#[derive(Clone, Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    replaying: bool,
+    interactions: Vec<Value>, // Command, arguments and result in the order they ran
+    used: Vec<bool>,          // Replayed interactions aren't used again
+}
+
+impl Cassette {
+    /// Start recording into the file, discarding the recorded interactions
+    pub fn record(path: &Path) -> Cassette {
+        Cassette {
+            path: path.to_owned(),
+            replaying: false,
+            interactions: Vec::new(),
+            used: Vec::new(),
+        }
+    }
+
+    /// Open the file to replay, or record into it on the first run when it isn't made yet
+    pub fn replay(path: &Path) -> io::Result<Cassette> {
+        let interactions: Vec<Value> = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Cassette::record(path)),
+            Err(e) => return Err(e),
+        };
+        Ok(Cassette {
+            path: path.to_owned(),
+            replaying: true,
+            used: vec![false; interactions.len()],
+            interactions,
+        })
+    }
+
+    /// Judge are the results taken from the file instead of running the commands
+    pub fn is_replaying(&self) -> bool {
+        self.replaying
+    }
+
+    /// Get the first unused result of the same command and arguments
+    pub fn take(&mut self, command: &str, args: &[Type]) -> Option<Type> {
+        let args = stack_to_json(args);
+        let index = (0..self.interactions.len()).find(|&i| {
+            let interaction = &self.interactions[i];
+            !self.used[i] && interaction["command"] == command && interaction["args"] == args
+        })?;
+        self.used[index] = true;
+        Some(from_json(&self.interactions[index]["result"]))
+    }
+
+    /// Add the result of the command, and save the file
+    pub fn add(&mut self, command: &str, args: &[Type], result: &Type) -> io::Result<()> {
+        self.interactions.push(json!({
+            "command": command,
+            "args": stack_to_json(args),
+            "result": to_json(result),
+        }));
+        self.used.push(true);
+        fs::write(
+            &self.path,
+            serde_json::to_string_pretty(&self.interactions)?,
+        )
+    }
+}
@@ -0,0 +1,81 @@
+/// Problem found in the script without running it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub line: usize,   // Starting from 1
+    pub column: usize, // Starting from 1, counted by characters
+    pub message: String,
+}
+
+/// Check that the strings, lists, objects and comments of the script are closed
+pub fn check(code: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut opened: Vec<(char, usize, usize)> = Vec::new(); // Brackets not closed yet
+    let mut comment: Option<(usize, usize)> = None; // Start of the comment
+    let mut escape = false;
+    let (mut line, mut column) = (1, 0);
+
+    // Follow the same rules as the tokenizer of the interpreter
+    for c in code.chars() {
+        if c == '\n' {
+            (line, column) = (line + 1, 0);
+            escape = false;
+            continue;
+        }
+        column += 1;
+        if escape {
+            escape = false;
+            continue;
+        }
+        let in_string = opened.iter().any(|(c, ..)| *c == '(');
+        match c {
+            '\\' => escape = true,
+            '#' if comment.is_none() => comment = Some((line, column)),
+            '#' => comment = None,
+            _ if comment.is_some() => {}
+            '(' => opened.push(('(', line, column)),
+            '[' | '{' if !in_string => opened.push((c, line, column)),
+            ')' | ']' | '}' if c == ')' || !in_string => {
+                let open = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match opened.pop() {
+                    Some((x, ..)) if x == open => {}
+                    Some((x, ..)) => problems.push(Problem {
+                        line,
+                        column,
+                        message: format!("`{c}` doesn't match `{x}`"),
+                    }),
+                    None => problems.push(Problem {
+                        line,
+                        column,
+                        message: format!("unexpected `{c}`"),
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((line, column)) = comment {
+        problems.push(Problem {
+            line,
+            column,
+            message: "the comment is not closed".to_string(),
+        });
+    }
+    for (c, line, column) in opened {
+        let message = match c {
+            '(' => "the string is not closed",
+            '[' => "the list is not closed",
+            _ => "the object is not closed",
+        };
+        problems.push(Problem {
+            line,
+            column,
+            message: message.to_string(),
+        });
+    }
+    problems
+}
@@ -0,0 +1,19 @@
+use crate::{Executor, Mode, Symbol};
+
+/// Header to identify the compiled program
+const MAGIC: &str = "STKC1\n";
+
+/// Compile the source code into the token form
+pub fn compile(code: String) -> String {
+    let mut executor = Executor::new(Mode::Script);
+    let syntax = executor.analyze_syntax(code);
+    let syntax = executor.expand_macros(syntax);
+    let syntax: Vec<&str> = syntax.iter().map(|token| &**token).collect();
+    format!("{MAGIC}{}", serde_json::Value::from(syntax))
+}
+
+/// Load tokens from the compiled program, if it is
+pub fn load(contents: &str) -> Option<Vec<Symbol>> {
+    let syntax: Vec<String> = serde_json::from_str(contents.strip_prefix(MAGIC)?).ok()?;
+    Some(syntax.into_iter().map(Symbol::from).collect())
+}
@@ -0,0 +1,50 @@
+use stack::Executor;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Get the absolute path, which is kept even if the script changes the directory
+pub fn absolute(path: &str) -> PathBuf {
+    match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => PathBuf::from(path),
+    }
+}
+
+/// Detach the process from the terminal, and write its PID
+#[cfg(unix)]
+pub fn detach(pid_file: &Path) -> Result<(), String> {
+    let dir = env::current_dir().map_err(|e| e.to_string())?;
+    daemonize::Daemonize::new()
+        .pid_file(pid_file)
+        .working_directory(dir)
+        .start()
+        .map_err(|e| e.to_string())
+}
+
+/// Detach the process from the terminal, and write its PID
+#[cfg(not(unix))]
+pub fn detach(_pid_file: &Path) -> Result<(), String> {
+    Err("daemon mode is not supported on this platform".to_string())
+}
+
+/// Write the PID of this process
+pub fn write_pid(pid_file: &Path) -> io::Result<()> {
+    fs::write(pid_file, format!("{}\n", process::id()))
+}
+
+/// Stop the script at the next token by the signal, and immediately by the second one
+pub fn handle_signals(executor: &Executor) -> Result<(), String> {
+    let shutdown = executor.shutdown_handle();
+    let requested = AtomicBool::new(false);
+    ctrlc::set_handler(move || {
+        if requested.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+        shutdown();
+    })
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,148 @@
+use crate::Type;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, Utc};
+
+const UNITS: [(char, f64); 4] = [('D', 86400.0), ('H', 3600.0), ('M', 60.0), ('S', 1.0)];
+
+/// Get the time from the unix epoch, or the ISO 8601 or RFC 2822 string
+pub fn from_value(value: &Type) -> Option<DateTime<FixedOffset>> {
+    match value {
+        Type::Number(seconds) => {
+            let millis = (seconds * 1000.0).round() as i64;
+            DateTime::from_timestamp_millis(millis).map(|time| time.fixed_offset())
+        }
+        Type::String(text) => parse(text),
+        _ => None,
+    }
+}
+
+/// Parse the ISO 8601 or RFC 2822 string, which is UTC without the offset
+pub fn parse(text: &str) -> Option<DateTime<FixedOffset>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc2822(text) {
+        return Some(time);
+    }
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .or_else(|| {
+        let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+        date.and_hms_opt(0, 0, 0)
+    })
+    .map(|time| time.and_utc().fixed_offset())
+}
+
+/// Get the seconds since the unix epoch
+pub fn to_seconds(time: &DateTime<FixedOffset>) -> f64 {
+    time.timestamp_millis() as f64 / 1000.0
+}
+
+/// Format the time by `iso`, `rfc2822` or the strftime pattern like `%Y-%m-%d`
+pub fn format(time: &DateTime<FixedOffset>, format: &str) -> Option<String> {
+    match format {
+        "iso" | "iso8601" | "rfc3339" => Some(iso(time)),
+        "rfc2822" => Some(time.to_rfc2822()),
+        pattern => {
+            let items: Vec<Item> = StrftimeItems::new(pattern).collect();
+            if items.contains(&Item::Error) {
+                return None;
+            }
+            Some(time.format_with_items(items.into_iter()).to_string())
+        }
+    }
+}
+
+/// Format the time as ISO 8601, with `Z` in UTC
+pub fn iso(time: &DateTime<FixedOffset>) -> String {
+    if time.offset().local_minus_utc() == 0 {
+        time.with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    } else {
+        time.to_rfc3339_opts(SecondsFormat::AutoSi, false)
+    }
+}
+
+/// Add the seconds to the time
+pub fn add(time: &DateTime<FixedOffset>, seconds: f64) -> Option<DateTime<FixedOffset>> {
+    let delta = TimeDelta::try_milliseconds((seconds * 1000.0).round() as i64)?;
+    time.checked_add_signed(delta)
+}
+
+/// Parse the ISO 8601 duration like `P1DT2H30M` into the seconds
+///
+/// The years and the months are not supported, because their length differs.
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text),
+    };
+    let text = text.strip_prefix('P')?;
+    let (date, time) = text.split_once('T').unwrap_or((text, ""));
+    if text.is_empty() || text.ends_with('T') {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for c in date.chars() {
+        match c {
+            '0'..='9' | '.' => number.push(c),
+            'W' => seconds += number.parse::<f64>().ok()? * 604800.0,
+            'D' => seconds += number.parse::<f64>().ok()? * 86400.0,
+            _ => return None,
+        }
+        if c.is_alphabetic() {
+            number.clear();
+        }
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    for c in time.chars() {
+        match c {
+            '0'..='9' | '.' => number.push(c),
+            'H' | 'M' | 'S' => {
+                let (_, unit) = UNITS.iter().find(|(name, _)| *name == c)?;
+                seconds += number.parse::<f64>().ok()? * unit;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+    number.is_empty().then_some(sign * seconds)
+}
+
+/// Format the seconds as ISO 8601 duration like `P1DT2H30M`
+pub fn format_duration(seconds: f64) -> String {
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let mut rest = seconds.abs();
+    let mut result = format!("{sign}P");
+    for (name, unit) in UNITS {
+        if name == 'H' {
+            result.push('T');
+        }
+        let count = if name == 'S' {
+            rest
+        } else {
+            (rest / unit).floor()
+        };
+        rest -= count * unit;
+        // Rounding off the error of the subtraction
+        let count = (count * 1000.0).round() / 1000.0;
+        if count > 0.0 {
+            result += &format!("{count}{name}");
+        }
+    }
+    match result.trim_end_matches('T') {
+        "P" | "-P" => "PT0S".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
@@ -0,0 +1,71 @@
+use crate::i18n::translate;
+use std::env;
+
+/// Category of the error
+#[derive(Clone, Copy, Debug)]
+pub enum Severity {
+    User,     // Mistake in the script
+    Internal, // Failure of the interpreter or the host environment
+    Warning,  // Suspicious but valid code in the script
+}
+
+/// When to colorize the output
+#[derive(Clone, Copy, Debug)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    Auto,
+}
+
+impl ColorChoice {
+    /// Parse from the command-line value
+    pub fn parse(value: &str) -> Option<ColorChoice> {
+        match value {
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            "auto" => Some(ColorChoice::Auto),
+            _ => None,
+        }
+    }
+
+    /// Judge is color enabled
+    pub fn enabled(self, interactive: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => interactive && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+/// Format the error message with its origin, in the current language
+pub fn format(
+    severity: Severity,
+    message: &str,
+    origin: Option<(&str, usize)>,
+    color: bool,
+) -> String {
+    let (label, style) = match severity {
+        Severity::User => ("Error!", "\x1b[1;31m"),
+        Severity::Internal => ("Internal error!", "\x1b[1;35m"),
+        Severity::Warning => ("Warning!", "\x1b[1;33m"),
+    };
+    let (label, message) = (translate(label), translate(message));
+    let label = if color {
+        format!("{style}{label}\x1b[0m")
+    } else {
+        label
+    };
+
+    match origin {
+        Some((command, token)) => {
+            let origin = translate(&format!("at `{command}` (token {token})"));
+            if color {
+                format!("{label} {message} \x1b[2m{origin}\x1b[0m\n")
+            } else {
+                format!("{label} {message} {origin}\n")
+            }
+        }
+        None => format!("{label} {message}\n"),
+    }
+}
@@ -0,0 +1,163 @@
+use crate::registry::{self, CommandInfo, COMMANDS};
+
+/// Format of the reference pages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    /// Parse from the command-line value
+    pub fn parse(value: &str) -> Option<Format> {
+        match value.to_lowercase().as_str() {
+            "md" | "markdown" => Some(Format::Markdown),
+            "htm" | "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    /// Extension of the page files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+/// Make the reference pages from the command registry, as pairs of the file name and the content
+///
+/// The index links the categories, and each category has the page of its commands.
+pub fn pages(format: Format) -> Vec<(String, String)> {
+    let categories = categories();
+    let mut pages = vec![(
+        format!("index.{}", format.extension()),
+        index(format, &categories),
+    )];
+    for category in categories {
+        let commands: Vec<&CommandInfo> = COMMANDS
+            .iter()
+            .filter(|command| command.category == category)
+            .collect();
+        let name = format!("{}.{}", slug(category), format.extension());
+        pages.push((name, page(format, category, &commands)));
+    }
+    pages
+}
+
+/// Describe the values taken from the stack, like `( a b -- ... )`
+pub fn stack_effect(command: &CommandInfo) -> String {
+    let args: String = (b'a'..)
+        .take(command.arity)
+        .map(|name| format!("{} ", name as char))
+        .collect();
+    format!("( {args}-- ... )")
+}
+
+/// Get the categories in the order of the registry
+fn categories() -> Vec<&'static str> {
+    let mut categories: Vec<&str> = Vec::new();
+    for command in COMMANDS {
+        if !categories.contains(&command.category) {
+            categories.push(command.category);
+        }
+    }
+    categories
+}
+
+/// Make the file name of the category, like `object-oriented-system`
+fn slug(category: &str) -> String {
+    let words: Vec<String> = category
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join("-")
+}
+
+/// Make the page listing the categories
+fn index(format: Format, categories: &[&str]) -> String {
+    let count = |category: &str| {
+        COMMANDS
+            .iter()
+            .filter(|command| command.category == category)
+            .count()
+    };
+    let title = "Command Reference";
+    let intro = "Generated from the command registry of the interpreter.";
+    let ext = format.extension();
+    match format {
+        Format::Markdown => {
+            let mut result = format!("# {title}\n\n{intro}\n\n");
+            for category in categories {
+                let link = format!("[{category}]({}.{ext})", slug(category));
+                result += &format!("- {link} ({} commands)\n", count(category));
+            }
+            result
+        }
+        Format::Html => {
+            let mut body = format!("<h1>{title}</h1>\n<p>{}</p>\n<ul>\n", escape(intro));
+            for category in categories {
+                body += &format!(
+                    "<li><a href=\"{}.{ext}\">{}</a> ({} commands)</li>\n",
+                    slug(category),
+                    escape(category),
+                    count(category)
+                );
+            }
+            body += "</ul>\n";
+            html(title, &body)
+        }
+    }
+}
+
+/// Make the page of the commands in the category
+fn page(format: Format, category: &str, commands: &[&CommandInfo]) -> String {
+    let index = format!("index.{}", format.extension());
+    match format {
+        Format::Markdown => {
+            let mut result = format!("# {category}\n\n[Command Reference]({index})\n");
+            for command in commands {
+                result += &format!("\n## `{}`\n\n", command.name);
+                result += &format!("`{}`\n\n{}\n", stack_effect(command), command.doc);
+                if let Some(code) = registry::example(command.name) {
+                    result += &format!("\n```\n{code}\n```\n");
+                }
+            }
+            result
+        }
+        Format::Html => {
+            let mut body = format!(
+                "<h1>{}</h1>\n<p><a href=\"{index}\">Command Reference</a></p>\n",
+                escape(category)
+            );
+            for command in commands {
+                let name = escape(command.name);
+                body += &format!("<h2 id=\"{name}\"><code>{name}</code></h2>\n");
+                body += &format!("<p><code>{}</code></p>\n", stack_effect(command));
+                body += &format!("<p>{}</p>\n", escape(command.doc));
+                if let Some(code) = registry::example(command.name) {
+                    body += &format!("<pre><code>{}</code></pre>\n", escape(code));
+                }
+            }
+            html(category, &body)
+        }
+    }
+}
+
+/// Wrap the body in the HTML document
+fn html(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+/// Escape the text to embed in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
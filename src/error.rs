@@ -0,0 +1,38 @@
+use std::fmt;
+
+/// Failure that stops the evaluation of program
+#[derive(Clone, Debug, PartialEq)]
+pub enum StackError {
+    /// Error raised by the command in strict mode
+    Runtime {
+        command: String,
+        token: usize, // Index of the token from 1 in the code being evaluated
+        message: String,
+    },
+    /// Request to exit the process with the status
+    Exit(i32),
+    /// Request to leave the innermost loop
+    Break,
+    /// Request to leave the function with the error on the stack
+    Propagate,
+    /// Request to leave the try block with the new error on the stack
+    Raise,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackError::Runtime {
+                command,
+                token,
+                message,
+            } => write!(f, "{message} at `{command}` (token {token})"),
+            StackError::Exit(status) => write!(f, "exit with status {status}"),
+            StackError::Break => write!(f, "break outside of loop"),
+            StackError::Propagate => write!(f, "error is propagated to the top level"),
+            StackError::Raise => write!(f, "error is raised outside of try"),
+        }
+    }
+}
+
+impl std::error::Error for StackError {}
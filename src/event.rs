@@ -0,0 +1,135 @@
+use crate::Type;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Event delivered to the callbacks
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub name: String,  // Name to find the callbacks
+    pub payload: Type, // Value pushed before the callbacks
+}
+
+/// Queue of the events shared between threads
+#[derive(Debug, Default)]
+pub struct EventLoop {
+    state: Mutex<State>,
+    ready: Condvar,
+    closed: AtomicBool, // Is the process shutting down
+}
+
+#[derive(Debug, Default)]
+struct State {
+    events: VecDeque<Event>,       // Events ready to deliver
+    timers: Vec<(Instant, Event)>, // Events waiting for the time
+    sources: usize,                // Running threads which may emit events
+    stopped: bool,                 // Is stop requested
+}
+
+/// Running source of the events, which releases the loop when it is dropped
+#[derive(Debug)]
+pub struct Source(Arc<EventLoop>);
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+impl EventLoop {
+    /// Add the event, after the delay if specified
+    pub fn emit(&self, event: Event, delay: Option<Duration>) {
+        if let Ok(mut state) = self.state.lock() {
+            match delay {
+                Some(delay) => state.timers.push((Instant::now() + delay, event)),
+                None => state.events.push_back(event),
+            }
+            self.ready.notify_all();
+        }
+    }
+
+    /// Request to stop the loop
+    pub fn stop(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stopped = true;
+            self.ready.notify_all();
+        }
+    }
+
+    /// Stop the loop for good, when the process is shutting down
+    pub fn shutdown(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // Hold the lock so that the waiting loop doesn't miss it
+        let _state = self.state.lock();
+        self.ready.notify_all();
+    }
+
+    /// Judge is the process shutting down
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Keep the loop alive while the source is running
+    pub fn hold(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.sources += 1;
+        }
+    }
+
+    /// Tell the source has finished
+    pub fn release(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.sources = state.sources.saturating_sub(1);
+            self.ready.notify_all();
+        }
+    }
+
+    /// Hold the loop until the returned guard is dropped, even by the panic of the source
+    pub fn source(self: &Arc<Self>) -> Source {
+        self.hold();
+        Source(Arc::clone(self))
+    }
+
+    /// Clear the stop request before running the loop
+    pub fn start(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stopped = false;
+        }
+    }
+
+    /// Wait for the next event, or None when stopped or nothing can happen anymore
+    pub fn wait(&self) -> Option<Event> {
+        let mut state = self.state.lock().ok()?;
+        loop {
+            if state.stopped || self.is_closed() {
+                return None;
+            }
+
+            // Move the expired timers to the ready events
+            let now = Instant::now();
+            let (expired, waiting): (Vec<_>, Vec<_>) =
+                state.timers.drain(..).partition(|(time, _)| *time <= now);
+            state.timers = waiting;
+            let mut expired = expired;
+            expired.sort_by_key(|(time, _)| *time);
+            state
+                .events
+                .extend(expired.into_iter().map(|(_, event)| event));
+
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+
+            let next_timer = state.timers.iter().map(|(time, _)| *time).min();
+            state = match next_timer {
+                Some(time) => {
+                    let timeout = time.saturating_duration_since(now);
+                    self.ready.wait_timeout(state, timeout).ok()?.0
+                }
+                None if state.sources > 0 => self.ready.wait(state).ok()?,
+                None => return None,
+            };
+        }
+    }
+}
@@ -0,0 +1,29 @@
+/// Example program embedded in the executable
+pub struct Example {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub code: &'static str,
+}
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "fizzbuzz",
+        about: "Loops and conditions with range, for and if",
+        code: include_str!("../examples/fizzbuzz.stk"),
+    },
+    Example {
+        name: "fetch",
+        about: "Downloads a web page and handles the error value",
+        code: include_str!("../examples/fetch.stk"),
+    },
+    Example {
+        name: "rename",
+        about: "Makes files in a new folder and renames them in a batch",
+        code: include_str!("../examples/rename.stk"),
+    },
+];
+
+/// Find the example by the name
+pub fn find(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
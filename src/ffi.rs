@@ -0,0 +1,187 @@
+use crate::Type;
+use libffi::middle::{Arg, Cif, CodePtr, Type as FfiType};
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::Arc;
+
+/// C type written in the signature, like `f64(f64, str)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CType {
+    Void,
+    I32,
+    I64,
+    U32,
+    U64,
+    F32,
+    F64,
+    Str,   // Null terminated string
+    Bytes, // Pointer to the buffer made from the list of numbers
+}
+
+impl CType {
+    /// Parse the type name
+    fn parse(name: &str) -> Result<CType, String> {
+        Ok(match name {
+            "void" => CType::Void,
+            "i32" | "int" => CType::I32,
+            "i64" | "long" => CType::I64,
+            "u32" => CType::U32,
+            "u64" | "usize" => CType::U64,
+            "f32" | "float" => CType::F32,
+            "f64" | "double" => CType::F64,
+            "str" => CType::Str,
+            "bytes" => CType::Bytes,
+            _ => return Err(format!("unknown C type \"{name}\"")),
+        })
+    }
+
+    /// Get the type for libffi
+    fn ffi_type(self) -> FfiType {
+        match self {
+            CType::Void => FfiType::void(),
+            CType::I32 => FfiType::i32(),
+            CType::I64 => FfiType::i64(),
+            CType::U32 => FfiType::u32(),
+            CType::U64 => FfiType::u64(),
+            CType::F32 => FfiType::f32(),
+            CType::F64 => FfiType::f64(),
+            CType::Str | CType::Bytes => FfiType::pointer(),
+        }
+    }
+}
+
+/// Argument converted for C, which must live during the call
+enum Value {
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Pointer(*const c_void),
+}
+
+/// Load the shared library
+pub fn load(path: &Path) -> Result<Arc<Library>, String> {
+    // SAFETY: loading runs the initializer of the library, which the user trusts
+    unsafe { Library::new(path) }
+        .map(Arc::new)
+        .map_err(|e| e.to_string())
+}
+
+/// Call the symbol found in the libraries, with the arguments converted by the signature
+pub fn call(
+    libraries: &[Arc<Library>],
+    symbol: &str,
+    signature: &str,
+    args: Vec<Type>,
+) -> Result<Type, String> {
+    let (result, params) = parse_signature(signature)?;
+    if params.len() != args.len() {
+        return Err(format!(
+            "the signature takes {} arguments but got {}",
+            params.len(),
+            args.len()
+        ));
+    }
+
+    // SAFETY: the symbol is only called as the function pointer
+    let function = libraries
+        .iter()
+        .rev()
+        .find_map(|library| unsafe {
+            library
+                .get::<*const c_void>(symbol.as_bytes())
+                .ok()
+                .map(|symbol| *symbol)
+        })
+        .ok_or(format!("the symbol `{symbol}` is not found"))?;
+
+    // Keep the buffers alive while the pointers to them are used
+    let mut strings = Vec::new();
+    let mut buffers = Vec::new();
+    let mut values = Vec::new();
+    for (param, mut arg) in params.iter().zip(args) {
+        values.push(match param {
+            CType::I32 => Value::I32(arg.get_number() as i32),
+            CType::I64 => Value::I64(arg.get_number() as i64),
+            CType::U32 => Value::U32(arg.get_number() as u32),
+            CType::U64 => Value::U64(arg.get_number() as u64),
+            CType::F32 => Value::F32(arg.get_number() as f32),
+            CType::F64 => Value::F64(arg.get_number()),
+            CType::Str => {
+                let string = CString::new(arg.get_string()).map_err(|e| e.to_string())?;
+                let pointer = string.as_ptr() as *const c_void;
+                strings.push(string);
+                Value::Pointer(pointer)
+            }
+            CType::Bytes => {
+                let mut buffer: Vec<u8> = arg
+                    .get_list()
+                    .iter_mut()
+                    .map(|x| x.get_number() as u8)
+                    .collect();
+                let pointer = buffer.as_mut_ptr() as *const c_void;
+                buffers.push(buffer);
+                Value::Pointer(pointer)
+            }
+            CType::Void => return Err("the argument can't be void".to_string()),
+        });
+    }
+    let args: Vec<Arg> = values
+        .iter()
+        .map(|value| match value {
+            Value::I32(x) => Arg::new(x),
+            Value::I64(x) => Arg::new(x),
+            Value::U32(x) => Arg::new(x),
+            Value::U64(x) => Arg::new(x),
+            Value::F32(x) => Arg::new(x),
+            Value::F64(x) => Arg::new(x),
+            Value::Pointer(x) => Arg::new(x),
+        })
+        .collect();
+
+    let cif = Cif::new(params.iter().map(|x| x.ffi_type()), result.ffi_type());
+    let code = CodePtr(function as *mut c_void);
+    // SAFETY: the user promises that the signature matches the function
+    unsafe {
+        Ok(match result {
+            CType::Void => {
+                cif.call::<()>(code, &args);
+                Type::List(Vec::new())
+            }
+            CType::I32 => Type::Number(cif.call::<i32>(code, &args) as f64),
+            CType::I64 => Type::Number(cif.call::<i64>(code, &args) as f64),
+            CType::U32 => Type::Number(cif.call::<u32>(code, &args) as f64),
+            CType::U64 => Type::Number(cif.call::<u64>(code, &args) as f64),
+            CType::F32 => Type::Number(cif.call::<f32>(code, &args) as f64),
+            CType::F64 => Type::Number(cif.call::<f64>(code, &args)),
+            CType::Str => {
+                let pointer = cif.call::<*const c_char>(code, &args);
+                if pointer.is_null() {
+                    return Err(format!("`{symbol}` returned null"));
+                }
+                Type::String(CStr::from_ptr(pointer).to_string_lossy().into_owned())
+            }
+            CType::Bytes => return Err("bytes can't be returned, use the argument".to_string()),
+        })
+    }
+}
+
+/// Parse the signature into the result and the parameters
+fn parse_signature(signature: &str) -> Result<(CType, Vec<CType>), String> {
+    let signature: String = signature.chars().filter(|c| !c.is_whitespace()).collect();
+    let (result, params) = signature
+        .strip_suffix(')')
+        .and_then(|x| x.split_once('('))
+        .ok_or(format!("invalid signature \"{signature}\""))?;
+    let params = match params {
+        "" | "void" => Vec::new(),
+        params => params
+            .split(',')
+            .map(CType::parse)
+            .collect::<Result<_, _>>()?,
+    };
+    Ok((CType::parse(result)?, params))
+}
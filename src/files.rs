@@ -0,0 +1,106 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Get all of the files under the folder, not following the links
+pub fn walk(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut folders = vec![dir.to_owned()];
+    while let Some(folder) = folders.pop() {
+        for entry in fs::read_dir(&folder)? {
+            let entry = entry?;
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                folders.push(entry.path());
+            } else if kind.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Get the SHA-256 of the file as hex
+pub fn checksum(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Find the groups of the files with the same contents under the folder
+///
+/// Only the files of the same size are hashed, and the empty files are skipped.
+pub fn duplicates(dir: &Path) -> io::Result<Vec<Vec<PathBuf>>> {
+    let mut sizes: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for path in walk(dir)? {
+        let size = fs::metadata(&path)?.len();
+        if size > 0 {
+            sizes.entry(size).or_default().push(path);
+        }
+    }
+
+    let mut groups = Vec::new();
+    for paths in sizes.into_values().filter(|paths| paths.len() > 1) {
+        let mut sums: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            sums.entry(checksum(&path)?).or_default().push(path);
+        }
+        groups.extend(sums.into_values().filter(|paths| paths.len() > 1));
+    }
+    // The files are sorted in the groups by the walk
+    groups.sort();
+    Ok(groups)
+}
+
+/// Mirror the files of the folder into the other, copying the new and changed ones
+///
+/// The files only in the destination are deleted with `delete`, and nothing is changed
+/// with `dry_run`. The changes are returned like `add a.txt`, `update b.txt` and `delete c.txt`.
+pub fn sync(src: &Path, dst: &Path, delete: bool, dry_run: bool) -> io::Result<Vec<String>> {
+    let mut changes = Vec::new();
+    for path in walk(src)? {
+        let relative = path.strip_prefix(src).unwrap_or(&path);
+        let target = dst.join(relative);
+        let action = match fs::metadata(&target) {
+            Ok(meta) if !is_changed(&fs::metadata(&path)?, &meta) => continue,
+            Ok(_) => "update",
+            Err(_) => "add",
+        };
+        if !dry_run {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&path, &target)?;
+        }
+        changes.push(format!("{action} {}", relative.display()));
+    }
+
+    if delete && dst.is_dir() {
+        for path in walk(dst)? {
+            let relative = path.strip_prefix(dst).unwrap_or(&path);
+            if src.join(relative).is_file() {
+                continue;
+            }
+            if !dry_run {
+                fs::remove_file(&path)?;
+            }
+            changes.push(format!("delete {}", relative.display()));
+        }
+    }
+    Ok(changes)
+}
+
+/// Judge is the copy different from the file, by the size or the older modification
+fn is_changed(file: &fs::Metadata, copy: &fs::Metadata) -> bool {
+    match (file.modified(), copy.modified()) {
+        (Ok(modified), Ok(copied)) => file.len() != copy.len() || modified > copied,
+        _ => true,
+    }
+}
@@ -0,0 +1,64 @@
+const INDENT: &str = "    ";
+
+/// Format the script, indenting the lines by the nest of the brackets
+///
+/// Strings are indented as well, because they are mostly the code blocks.
+/// Only whitespace at the start and end of the lines and the blank lines are changed.
+pub fn format(code: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0usize; // Brackets opened before the line
+    let mut string = 0usize; // Nest of the strings, where lists are not counted
+    let mut comment = false; // Is the line starting in the comment
+    let mut blank = false; // Is there a blank line to keep
+
+    for line in code.lines() {
+        let text = line.trim();
+        if text.is_empty() && !comment {
+            blank = !result.is_empty();
+            continue;
+        }
+        if blank {
+            result.push('\n');
+            blank = false;
+        }
+
+        if comment {
+            result += line.trim_end();
+        } else {
+            // The closing brackets at the start belong to the outer level
+            let closing = text
+                .chars()
+                .take_while(|c| matches!(c, ')' | ']' | '}'))
+                .count();
+            result += &INDENT.repeat(depth.saturating_sub(closing));
+            result += text;
+        }
+        result.push('\n');
+
+        // Follow the same rules as the tokenizer of the interpreter
+        let mut escape = false;
+        for c in line.chars() {
+            if escape {
+                escape = false;
+                continue;
+            }
+            match c {
+                '\\' => escape = true,
+                '#' => comment = !comment,
+                _ if comment => {}
+                '(' => {
+                    string += 1;
+                    depth += 1;
+                }
+                ')' => {
+                    string = string.saturating_sub(1);
+                    depth = depth.saturating_sub(1);
+                }
+                '[' | '{' if string == 0 => depth += 1,
+                ']' | '}' if string == 0 => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    result
+}
@@ -1,21 +1,43 @@
-use crate::{get_file_contents, input, Executor, Mode, Type};
+use crate::event::Event;
+#[cfg(feature = "ffi")]
+use crate::ffi;
+use crate::json;
+#[cfg(feature = "python")]
+use crate::python;
+use crate::{
+    datetime, escape_string, files, get_file_contents, input, ipc, kv, locale, registry,
+    set_precision, spreadsheet, ErrorValue, Executor, Mode, StackError, Symbol, Type,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+#[cfg(feature = "clipboard")]
 use clipboard::{ClipboardContext, ClipboardProvider};
+use indexmap::IndexMap;
 use rand::seq::SliceRandom;
+use rand::RngCore;
 use regex::Regex;
-use rodio::{OutputStream, Sink, Source};
-use rusty_audio::Audio;
+#[cfg(feature = "audio")]
+use rodio::{Decoder, OutputStream, Sink, Source};
+use similar::TextDiff;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::thread;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+#[cfg(feature = "audio")]
+use std::io::BufReader;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{env, fs};
+#[cfg(feature = "sysinfo")]
 use sys_info::{cpu_num, cpu_speed, hostname, mem_info, os_release, os_type};
 
-pub fn execute_command(executor: &mut Executor, command: String) {
-    match command.as_str() {
+/// Body of the generator made by `stdin-lines`, which yields until the end of the input
+const STDIN_LINES: &str = "read-line copy (yield) swap is-error unless";
+
+pub fn execute_command(executor: &mut Executor, command: &str) -> Result<(), StackError> {
+    match command {
         // Commands of calculation
 
         // Addition
@@ -84,6 +106,48 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::Number(number.tan()))
         }
 
+        // Add the numbers of the lists elementwise, where the number is broadcast to the list
+        "v-add" => {
+            let b = executor.pop_stack();
+            let a = executor.pop_stack();
+            match elementwise(a, b, |a, b| a + b) {
+                Ok(result) => executor.stack.push(result),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("length-mismatch"));
+                }
+            }
+        }
+
+        // Multiply the numbers of the lists elementwise, or scale the list by the number
+        "v-mul" | "scale" => {
+            let b = executor.pop_stack();
+            let a = executor.pop_stack();
+            match elementwise(a, b, |a, b| a * b) {
+                Ok(result) => executor.stack.push(result),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("length-mismatch"));
+                }
+            }
+        }
+
+        // Dot product of the lists
+        "dot" => {
+            let b = executor.pop_stack();
+            let a = executor.pop_stack();
+            match elementwise(a, b, |a, b| a * b) {
+                Ok(mut result) => {
+                    let sum = result.get_list().iter_mut().map(Type::get_number).sum();
+                    executor.stack.push(Type::Number(sum));
+                }
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("length-mismatch"));
+                }
+            }
+        }
+
         // Logical operations of AND
         "and" => {
             let b = executor.pop_stack().get_bool();
@@ -98,6 +162,28 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::Bool(a || b));
         }
 
+        // Logical AND evaluating code only if the value is true
+        "and-then" => {
+            let code = executor.pop_stack().get_string();
+            let a = executor.pop_stack().get_bool();
+            let result = a && {
+                executor.evaluate_program(code)?;
+                executor.pop_stack().get_bool()
+            };
+            executor.stack.push(Type::Bool(result));
+        }
+
+        // Logical OR evaluating code only if the value is false
+        "or-else" => {
+            let code = executor.pop_stack().get_string();
+            let a = executor.pop_stack().get_bool();
+            let result = a || {
+                executor.evaluate_program(code)?;
+                executor.pop_stack().get_bool()
+            };
+            executor.stack.push(Type::Bool(result));
+        }
+
         // Logical operations of NOT
         "not" => {
             let b = executor.pop_stack().get_bool();
@@ -111,6 +197,14 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::Bool(a == b));
         }
 
+        // Judge is it equal within the tolerance
+        "approx-equal" => {
+            let epsilon = executor.pop_stack().get_number();
+            let b = executor.pop_stack().get_number();
+            let a = executor.pop_stack().get_number();
+            executor.stack.push(Type::Bool((a - b).abs() <= epsilon));
+        }
+
         // Judge is it less
         "less" => {
             let b = executor.pop_stack().get_number();
@@ -121,8 +215,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         // Get random value from list
         "rand" => {
             let list = executor.pop_stack().get_list();
-            let result = match list.choose(&mut rand::thread_rng()) {
-                Some(i) => i.to_owned(),
+            let result = match with_rng(executor, |rng| list.choose(rng).cloned()) {
+                Some(i) => i,
                 None => Type::List(list),
             };
             executor.stack.push(result);
@@ -131,7 +225,7 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         // Shuffle list by random
         "shuffle" => {
             let mut list = executor.pop_stack().get_list();
-            list.shuffle(&mut rand::thread_rng());
+            with_rng(executor, |rng| list.shuffle(rng));
             executor.stack.push(Type::List(list));
         }
 
@@ -141,22 +235,25 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "repeat" => {
             let count = executor.pop_stack().get_number(); // Count
             let text = executor.pop_stack().get_string(); // String
+            if text.len().checked_mul(count as usize).is_none() {
+                executor.log_error("the repeated string is too long".to_string());
+                executor.stack.push(Type::error("repeat"));
+                return Ok(());
+            }
             executor
                 .stack
                 .push(Type::String(text.repeat(count as usize)));
         }
 
         // Get unicode character form number
-        "decode" => {
+        "decode" | "code-char" => {
             let code = executor.pop_stack().get_number();
             let result = char::from_u32(code as u32);
             match result {
                 Some(c) => executor.stack.push(Type::String(c.to_string())),
                 None => {
-                    executor.log_print("Error! failed of number decoding\n".to_string());
-                    executor
-                        .stack
-                        .push(Type::Error("number-decoding".to_string()));
+                    executor.log_error("failed of number decoding".to_string());
+                    executor.stack.push(Type::error("number-decoding"));
                 }
             }
         }
@@ -169,13 +266,71 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                     .stack
                     .push(Type::Number((first_char as u32) as f64));
             } else {
-                executor.log_print("Error! failed of string encoding\n".to_string());
-                executor
-                    .stack
-                    .push(Type::Error("string-encoding".to_string()));
+                executor.log_error("failed of string encoding".to_string());
+                executor.stack.push(Type::error("string-encoding"));
+            }
+        }
+
+        // Split the code into the tokens as the interpreter does
+        "parse" => {
+            let code = executor.pop_stack().get_string();
+            let tokens = executor.analyze_syntax(code).into_iter();
+            let tokens = tokens.map(|token| Type::String(token.to_string()));
+            executor.stack.push(Type::List(tokens.collect()));
+        }
+
+        // Get the kind of the token, how the interpreter evaluates it
+        "token-type" => {
+            let token = executor.pop_stack().get_string();
+            let kind = token_type(executor, &token);
+            executor.stack.push(Type::String(kind.to_string()));
+        }
+
+        // Get the code of the character
+        "char-code" => {
+            let string = executor.pop_stack().get_string();
+            let mut chars = string.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => executor.stack.push(Type::Number(c as u32 as f64)),
+                _ => {
+                    executor.log_error(format!("\"{string}\" is not a character"));
+                    executor.stack.push(Type::error("char"));
+                }
             }
         }
 
+        // Split the string into the characters
+        "chars" => {
+            let string = executor.pop_stack().get_string();
+            let chars = string.chars().map(|c| Type::String(c.to_string()));
+            executor.stack.push(Type::List(chars.collect()));
+        }
+
+        // Judge are all the characters of the kind, the empty string is not
+        "is-digit" | "is-alpha" | "is-space" | "is-upper" => {
+            let string = executor.pop_stack().get_string();
+            let kind = match command {
+                "is-digit" => char::is_ascii_digit,
+                "is-alpha" => |c: &char| c.is_alphabetic(),
+                "is-space" => |c: &char| c.is_whitespace(),
+                _ => |c: &char| c.is_uppercase(),
+            };
+            let result = !string.is_empty() && string.chars().all(|c| kind(&c));
+            executor.stack.push(Type::Bool(result));
+        }
+
+        // Get the code which pushes the same value, `quote` is for building code
+        "to-literal" | "quote" => {
+            let value = executor.pop_stack();
+            executor.stack.push(Type::String(value.literal()));
+        }
+
+        // Escape the brackets and comments to embed the text in the string literal
+        "escape-string" => {
+            let text = executor.pop_stack().get_string();
+            executor.stack.push(Type::String(escape_string(&text)));
+        }
+
         // Concatenate the string
         "concat" => {
             let b = executor.pop_stack().get_string();
@@ -183,6 +338,45 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::String(a + &b));
         }
 
+        // Get the differences of the strings as unified diff, empty if they are the same
+        "diff" => {
+            let new = executor.pop_stack().get_string();
+            let old = executor.pop_stack().get_string();
+            executor
+                .stack
+                .push(Type::String(unified_diff(&old, &new, "old", "new")));
+        }
+
+        // Make the string buffer to accumulate the text without copying
+        "sbuf-new" => {
+            let id = Type::Number(executor.buffers.len() as f64);
+            executor.buffers.push(String::new());
+            let handle = Type::Object("sbuf".to_string(), IndexMap::from([("id".to_string(), id)]));
+            executor.stack.push(handle);
+        }
+
+        // Add the text at the end of the string buffer
+        "sbuf-push" => {
+            let text = executor.pop_stack().get_string();
+            let handle = executor.pop_stack();
+            match buffer_id(executor, &handle) {
+                Some(id) => executor.buffers[id] += &text,
+                None => not_buffer(executor),
+            }
+        }
+
+        // Get the text accumulated in the string buffer
+        "sbuf-to-string" => {
+            let handle = executor.pop_stack();
+            match buffer_id(executor, &handle) {
+                Some(id) => {
+                    let text = executor.buffers[id].clone();
+                    executor.stack.push(Type::String(text));
+                }
+                None => not_buffer(executor),
+            }
+        }
+
         // Replacing string
         "replace" => {
             let after = executor.pop_stack().get_string();
@@ -243,9 +437,9 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let pattern: Regex = match Regex::new(pattern.as_str()) {
                 Ok(i) => i,
                 Err(e) => {
-                    executor.log_print(format!("Error! {}\n", e.to_string().replace("Error", "")));
-                    executor.stack.push(Type::Error("regex".to_string()));
-                    return;
+                    executor.log_error(e.to_string().replace("Error", ""));
+                    executor.stack.push(Type::error("regex"));
+                    return Ok(());
                 }
             };
 
@@ -256,21 +450,51 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::List(list));
         }
 
+        // Parse the number written in the locale style
+        "parse-locale-number" => {
+            let decimal = executor.pop_stack().get_string();
+            let text = executor.pop_stack().get_string();
+            let decimal = decimal.chars().next().unwrap_or('.');
+            match locale::parse_number(&text, decimal) {
+                Some(number) => executor.stack.push(Type::Number(number)),
+                None => {
+                    executor.log_error(format!("\"{text}\" is not a number"));
+                    executor.stack.push(Type::error("parse-locale-number"));
+                }
+            }
+        }
+
+        // Format the amount of money with the currency symbol
+        "format-currency" => {
+            let decimal = executor.pop_stack().get_string();
+            let symbol = executor.pop_stack().get_string();
+            let amount = executor.pop_stack().get_number();
+            let decimal = decimal.chars().next().unwrap_or('.');
+            executor.stack.push(Type::String(locale::format_currency(
+                amount, &symbol, decimal,
+            )));
+        }
+
         // Commands of I/O
 
         // Write string in the file
         "write-file" => {
-            let mut file = match File::create(Path::new(&executor.pop_stack().get_string())) {
+            let path = executor.pop_stack().get_string();
+            if executor.dry_run(format!("write-file {path}")) {
+                executor.pop_stack();
+                return Ok(());
+            }
+            let mut file = match File::create(Path::new(&path)) {
                 Ok(file) => file,
                 Err(e) => {
-                    executor.log_print(format!("Error! {e}\n"));
-                    executor.stack.push(Type::Error("create-file".to_string()));
-                    return;
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("create-file"));
+                    return Ok(());
                 }
             };
             if let Err(e) = file.write_all(executor.pop_stack().get_string().as_bytes()) {
-                executor.log_print(format!("Error! {}\n", e));
-                executor.stack.push(Type::Error("write-file".to_string()));
+                executor.log_internal_error(e.to_string());
+                executor.stack.push(Type::error("write-file"));
             }
         }
 
@@ -280,8 +504,39 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             match get_file_contents(&name) {
                 Ok(s) => executor.stack.push(Type::String(s)),
                 Err(e) => {
-                    executor.log_print(format!("Error! {}\n", e));
-                    executor.stack.push(Type::Error("read-file".to_string()));
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("read-file"));
+                }
+            };
+        }
+
+        // Get the differences of the files as unified diff, empty if they are the same
+        "diff-files" => {
+            let new = executor.pop_stack().get_string();
+            let old = executor.pop_stack().get_string();
+            match (
+                get_file_contents(Path::new(&old)),
+                get_file_contents(Path::new(&new)),
+            ) {
+                (Ok(a), Ok(b)) => executor
+                    .stack
+                    .push(Type::String(unified_diff(&a, &b, &old, &new))),
+                (Err(e), _) | (_, Err(e)) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("diff-files"));
+                }
+            }
+        }
+
+        // Read rows of the sheet in the spreadsheet
+        "xlsx-read" => {
+            let sheet = executor.pop_stack().get_string();
+            let name = Path::new(&executor.pop_stack().get_string()).to_owned();
+            match spreadsheet::read(&name, &sheet) {
+                Ok(rows) => executor.stack.push(Type::List(rows)),
+                Err(e) => {
+                    executor.log_internal_error(e);
+                    executor.stack.push(Type::error("xlsx-read"));
                 }
             };
         }
@@ -292,6 +547,37 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::String(input(prompt.as_str())));
         }
 
+        // Read the line of the standard input, or error at the end
+        "read-line" => {
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                // The end is expected in the usual flow, so it isn't reported
+                Ok(0) => executor.stack.push(Type::error("eof")),
+                Ok(_) => {
+                    let line = line.trim_end_matches(['\n', '\r']).to_string();
+                    executor.stack.push(Type::String(line));
+                }
+                Err(e) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("read-line"));
+                }
+            }
+        }
+
+        // Get the generator of the lines of the standard input, reading them one by one
+        "stdin-lines" => {
+            let generator = IndexMap::from([
+                ("body".to_string(), Type::String(STDIN_LINES.to_string())),
+                (
+                    "state".to_string(),
+                    Type::Object("state".to_string(), IndexMap::new()),
+                ),
+            ]);
+            executor
+                .stack
+                .push(Type::Object("generator".to_string(), generator));
+        }
+
         // Standard output
         "print" => {
             let a = executor.pop_stack().get_string();
@@ -300,10 +586,10 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let a = a.replace("\\t", "\t");
             let a = a.replace("\\r", "\r");
 
-            if let Mode::Debug = executor.mode {
-                println!("[Output]: {a}");
+            if let (Mode::Debug, true) = (&executor.mode, executor.interactive) {
+                println!("{}{a}", executor.options.output_prefix);
             } else {
-                print!("{a}");
+                executor.write_output(&a);
             }
         }
 
@@ -315,10 +601,10 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let a = a.replace("\\t", "\t");
             let a = a.replace("\\r", "\r");
 
-            if let Mode::Debug = executor.mode {
-                println!("[Output]: {a}");
+            if let (Mode::Debug, true) = (&executor.mode, executor.interactive) {
+                println!("{}{a}", executor.options.output_prefix);
             } else {
-                println!("{a}");
+                executor.write_output(&format!("{a}\n"));
             }
         }
 
@@ -332,54 +618,50 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         )),
 
         // Play sound from frequency
+        #[cfg(feature = "audio")]
         "play-sound" => {
-            fn play_sine_wave(frequency: f64, duration_secs: f64) {
-                let sample_rate = 44100f64;
-
-                let num_samples = (duration_secs * sample_rate) as usize;
-                let samples: Vec<f32> = (0..num_samples)
-                    .map(|t| {
-                        let t = t as f64 / sample_rate;
-                        (t * frequency * 2.0 * std::f64::consts::PI).sin() as f32
-                    })
-                    .collect();
-
-                let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-                let sink = Sink::try_new(&stream_handle).unwrap();
-
-                for _ in samples {
-                    sink.append(
-                        rodio::source::SineWave::new(frequency as f32)
-                            .take_duration(Duration::from_secs_f64(duration_secs)),
-                    );
-                }
+            fn play_sine_wave(frequency: f64, duration: Duration) -> Result<(), String> {
+                let (_stream, stream_handle) =
+                    OutputStream::try_default().map_err(|e| e.to_string())?;
+                let sink = Sink::try_new(&stream_handle).map_err(|e| e.to_string())?;
 
+                sink.append(rodio::source::SineWave::new(frequency as f32).take_duration(duration));
                 sink.play();
-                std::thread::sleep(Duration::from_secs_f64(duration_secs));
+                std::thread::sleep(duration);
+                Ok(())
             }
 
-            let duration_secs = executor.pop_stack().get_number();
+            let duration = executor.pop_stack().get_number();
             let frequency = executor.pop_stack().get_number();
 
-            play_sine_wave(frequency, duration_secs);
+            let result =
+                to_duration(duration).and_then(|duration| play_sine_wave(frequency, duration));
+            if let Err(e) = result {
+                executor.log_internal_error(e);
+                executor.stack.push(Type::error("play-sound"));
+            }
         }
 
         // Play the music file
+        #[cfg(feature = "audio")]
         "play-file" => {
-            let path = executor.pop_stack().get_string();
-            let sound_file_path = Path::new(&path);
-
-            let res_sound_file = File::open(sound_file_path);
+            fn play_file(path: &str) -> Result<(), String> {
+                let file = File::open(path).map_err(|e| e.to_string())?;
+                let source = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+                let (_stream, stream_handle) =
+                    OutputStream::try_default().map_err(|e| e.to_string())?;
+                let sink = Sink::try_new(&stream_handle).map_err(|e| e.to_string())?;
+
+                sink.append(source);
+                sink.sleep_until_end();
+                Ok(())
+            }
 
-            if let Err(e) = res_sound_file {
-                executor.log_print(format!("Error! {}\n", e));
-                executor.stack.push(Type::Error("play-file".to_string()));
+            let path = executor.pop_stack().get_string();
+            if let Err(e) = play_file(&path) {
+                executor.log_internal_error(e);
+                executor.stack.push(Type::error("play-file"));
             } else {
-                let mut audio_device = Audio::new();
-                audio_device.add("sound", path.clone());
-                audio_device.play("sound");
-                audio_device.wait();
-
                 executor.stack.push(Type::String(path));
             }
         }
@@ -388,10 +670,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "cls" | "clear" => {
             let result = clearscreen::clear();
             if result.is_err() {
-                println!("Error! Failed to clear screen");
-                executor
-                    .stack
-                    .push(Type::Error(String::from("failed-to-clear-screen")));
+                executor.log_internal_error("Failed to clear screen".to_string());
+                executor.stack.push(Type::error("failed-to-clear-screen"));
             }
         }
 
@@ -400,30 +680,150 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         // Evaluate string as program
         "eval" => {
             let code = executor.pop_stack().get_string();
-            executor.evaluate_program(code)
+            call(executor, code)?;
+        }
+
+        // Make the function of the parameters and the body
+        "func" => {
+            let body = executor.pop_stack().get_string();
+            let params = executor.pop_stack().get_list();
+            let params = params.into_iter().map(|mut x| x.get_string()).collect();
+            executor.stack.push(Type::Function(params, body));
         }
 
+        // Call the function in its own local scope
+        "call" => match executor.pop_stack() {
+            Type::Function(params, body) => call_function(executor, params, body)?,
+            _ => {
+                executor.log_error("the value is not a function".to_string());
+                executor.stack.push(Type::error("not-function"));
+            }
+        },
+
         // Conditional branch
         "if" => {
             let condition = executor.pop_stack().get_bool(); // Condition
             let code_else = executor.pop_stack().get_string(); // Code of else
             let code_if = executor.pop_stack().get_string(); // Code of If
             if condition {
-                executor.evaluate_program(code_if)
+                executor.evaluate_program(code_if)?;
             } else {
-                executor.evaluate_program(code_else)
+                executor.evaluate_program(code_else)?;
             };
         }
 
+        // Evaluate code only if the condition is true
+        "when" => {
+            let condition = executor.pop_stack().get_bool();
+            let code = executor.pop_stack().get_string();
+            if condition {
+                executor.evaluate_program(code)?;
+            }
+        }
+
+        // Evaluate code only if the condition is false
+        "unless" => {
+            let condition = executor.pop_stack().get_bool();
+            let code = executor.pop_stack().get_string();
+            if !condition {
+                executor.evaluate_program(code)?;
+            }
+        }
+
+        // Choose one of the values by the condition
+        "select" => {
+            let condition = executor.pop_stack().get_bool();
+            let b = executor.pop_stack();
+            let a = executor.pop_stack();
+            executor.stack.push(if condition { a } else { b });
+        }
+
         // Loop while condition is true
         "while" => {
             let cond = executor.pop_stack().get_string();
             let code = executor.pop_stack().get_string();
             while {
-                executor.evaluate_program(cond.clone());
+                executor.evaluate_program(cond.clone())?;
                 executor.pop_stack().get_bool()
             } {
-                executor.evaluate_program(code.clone());
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
+            }
+        }
+
+        // Loop until condition is true
+        "until" => {
+            let cond = executor.pop_stack().get_string();
+            let code = executor.pop_stack().get_string();
+            while {
+                executor.evaluate_program(cond.clone())?;
+                !executor.pop_stack().get_bool()
+            } {
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
+            }
+        }
+
+        // Loop forever until break
+        "loop" => {
+            let code = executor.pop_stack().get_string();
+            while iterate(executor, code.clone())? {}
+        }
+
+        // Leave the innermost loop
+        "break" => return Err(StackError::Break),
+
+        // Check the condition, which is error when it is false
+        "assert" => {
+            let message = executor.pop_stack().get_string();
+            if !executor.pop_stack().get_bool() {
+                executor.log_error(format!("assertion failed: {message}"));
+                executor.stack.push(Type::error("assert"));
+            }
+        }
+
+        // Check the data type of value, which is error when it is different
+        "ensure-type" => {
+            let expected = executor.pop_stack().get_string();
+            let value = executor.pop_stack();
+            let actual = type_name(&value);
+            // Any object is accepted as "object" too
+            if actual == expected || (expected == "object" && matches!(value, Type::Object(..))) {
+                executor.stack.push(value);
+            } else {
+                executor.log_error(format!("expected {expected} but got {actual}"));
+                executor.stack.push(Type::error("ensure-type"));
+            }
+        }
+
+        // Evaluate code again while it results in error, waiting longer every time
+        "retry" => {
+            let delay = executor.pop_stack().get_number();
+            let attempts = executor.pop_stack().get_number().max(1.0) as usize;
+            let code = executor.pop_stack().get_string();
+            if let Err(e) = to_duration(delay) {
+                executor.log_error(e);
+                executor.stack.push(Type::error("retry"));
+                return Ok(());
+            }
+
+            for attempt in 1..=attempts {
+                executor.evaluate_program(code.clone())?;
+                let result = executor.pop_stack();
+                if !matches!(result, Type::Error(_)) || attempt == attempts {
+                    executor.stack.push(result);
+                    break;
+                }
+                match to_duration(delay * 2f64.powi(attempt as i32 - 1)) {
+                    Ok(backoff) => sleep(backoff),
+                    Err(e) => {
+                        executor.log_error(e);
+                        executor.stack.push(Type::error("retry"));
+                        break;
+                    }
+                }
             }
         }
 
@@ -431,15 +831,196 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "thread" => {
             let code = executor.pop_stack().get_string();
             let mut executor = executor.clone();
-            thread::spawn(move || executor.evaluate_program(code));
+            // The event loop waits for the events from the thread, until it ends or panics
+            let source = executor.events.source();
+            thread::spawn(move || {
+                let _source = source;
+                if let Err(StackError::Exit(status)) = executor.evaluate_program(code) {
+                    std::process::exit(status);
+                }
+            });
+        }
+
+        // Evaluate code apart from the stack and variables, under the same restrictions
+        "isolate" => {
+            let code = executor.pop_stack().get_string();
+            isolate(executor, code, Vec::new());
+        }
+
+        // Evaluate code apart from the stack and variables, with copy of the given variables
+        "isolate-with" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_list();
+            isolate(executor, code, vars);
+        }
+
+        // Evaluate code all or nothing, rolling back the stack and variables on error
+        "transact" => {
+            let code = executor.pop_stack().get_string();
+            transact(executor, code)?;
         }
 
         // Exit a process
         "exit" => {
-            let status = executor.pop_stack().get_number();
-            std::process::exit(status as i32);
+            // The status is optional, and the error value means failure
+            let status = match executor.stack.pop() {
+                Some(Type::Error(_)) => 1,
+                Some(mut value) => value.get_number() as i32,
+                None => 0,
+            };
+            return Err(StackError::Exit(status));
+        }
+
+        // Register the callback of event
+        "on-event" => {
+            let code = executor.pop_stack().get_string();
+            let name = executor.pop_stack().get_string();
+            executor.handlers.entry(name).or_default().push(code);
+        }
+
+        // Register the block run before or after each token, with the token on the stack
+        "on-token" => {
+            let code = executor.pop_stack().get_string();
+            let timing = executor.pop_stack().get_string();
+            match timing.as_str() {
+                "pre" => executor.hooks.pre_blocks.push(code),
+                "post" => executor.hooks.post_blocks.push(code),
+                _ => {
+                    executor.log_error(format!("unknown hook timing \"{timing}\""));
+                    executor.stack.push(Type::error("on-token"));
+                }
+            }
+        }
+
+        // Emit the event with the value
+        "emit-event" => {
+            let name = executor.pop_stack().get_string();
+            let payload = executor.pop_stack();
+            executor.events.emit(Event { name, payload }, None);
+        }
+
+        // Emit the event with the value after seconds
+        "emit-after" => {
+            let seconds = executor.pop_stack().get_number();
+            let name = executor.pop_stack().get_string();
+            let payload = executor.pop_stack();
+            match to_duration(seconds.max(0.0)) {
+                Ok(delay) => executor.events.emit(Event { name, payload }, Some(delay)),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("emit-after"));
+                }
+            }
+        }
+
+        // Deliver the events to the callbacks until stopped or idle
+        "run-loop" => {
+            executor.events.start();
+            while let Some(event) = executor.events.wait() {
+                let handlers = executor.handlers.get(&event.name).cloned();
+                for code in handlers.unwrap_or_default() {
+                    executor.stack.push(event.payload.clone());
+                    executor.evaluate_program(code)?;
+                }
+            }
+        }
+
+        // Stop the event loop
+        "stop-loop" => executor.events.stop(),
+
+        // Commands of error handling
+
+        // Leave the function with the error, if the top of the stack is error
+        "propagate" => {
+            if let Some(Type::Error(_)) = executor.stack.last() {
+                return Err(StackError::Propagate);
+            }
+        }
+
+        // Evaluate code, leaving it at the first new error to be handled by catch
+        "try" => {
+            let code = executor.pop_stack().get_string();
+            let depth = executor.stack.len();
+            executor.trying += 1;
+            let result = executor.evaluate_program(code);
+            executor.trying -= 1;
+            executor.caught = None;
+            match result {
+                Err(StackError::Raise | StackError::Propagate) => {
+                    let error = executor.stack.pop();
+                    // The values left by the code are dropped, as it didn't complete
+                    executor.stack.truncate(depth);
+                    executor.caught = error;
+                }
+                result => result?,
+            }
+        }
+
+        // Evaluate code with the error on the stack, if the last try block caught it
+        "catch" => {
+            let code = executor.pop_stack().get_string();
+            if let Some(error) = executor.caught.take() {
+                executor.stack.push(error);
+                executor.evaluate_program(code)?;
+            }
+        }
+
+        // Make the error value with the code and the message
+        "error" => {
+            let message = executor.pop_stack().get_string();
+            let code = executor.pop_stack().get_string();
+            executor.stack.push(Type::Error(ErrorValue {
+                code,
+                message,
+                payload: None,
+                origin: Some((executor.command.to_string(), executor.token)),
+            }));
+        }
+
+        // Attach the value to the error
+        "with-payload" => {
+            let payload = executor.pop_stack();
+            match executor.pop_stack() {
+                Type::Error(mut err) => {
+                    err.payload = Some(Box::new(payload));
+                    executor.stack.push(Type::Error(err));
+                }
+                _ => not_error(executor),
+            }
         }
 
+        // Get the code of the error
+        "error-code" => match executor.pop_stack() {
+            Type::Error(err) => executor.stack.push(Type::String(err.code)),
+            _ => not_error(executor),
+        },
+
+        // Get the message of the error
+        "error-message" => match executor.pop_stack() {
+            Type::Error(err) => executor.stack.push(Type::String(err.message)),
+            _ => not_error(executor),
+        },
+
+        // Get the value attached to the error
+        "error-payload" => match executor.pop_stack() {
+            Type::Error(err) => match err.payload {
+                Some(payload) => executor.stack.push(*payload),
+                None => executor.stack.push(Type::List(Vec::new())),
+            },
+            _ => not_error(executor),
+        },
+
+        // Get the command and the index of its token which raised the error
+        "error-origin" => match executor.pop_stack() {
+            Type::Error(err) => executor.stack.push(Type::List(match err.origin {
+                Some((command, token)) => {
+                    vec![Type::String(command), Type::Number(token as f64)]
+                }
+                None => Vec::new(),
+            })),
+            _ => not_error(executor),
+        },
+
         // Commands of list processing
 
         // Get list value by index
@@ -449,10 +1030,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             if list.len() > index {
                 executor.stack.push(list[index].clone());
             } else {
-                executor.log_print("Error! Index specification is out of range\n".to_string());
-                executor
-                    .stack
-                    .push(Type::Error("index-out-range".to_string()));
+                executor.log_error("Index specification is out of range".to_string());
+                executor.stack.push(Type::error("index-out-range"));
             }
         }
 
@@ -465,10 +1044,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                 list[index] = value;
                 executor.stack.push(Type::List(list));
             } else {
-                executor.log_print("Error! Index specification is out of range\n".to_string());
-                executor
-                    .stack
-                    .push(Type::Error("index-out-range".to_string()));
+                executor.log_error("Index specification is out of range".to_string());
+                executor.stack.push(Type::error("index-out-range"));
             }
         }
 
@@ -480,10 +1057,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                 list.remove(index);
                 executor.stack.push(Type::List(list));
             } else {
-                executor.log_print("Error! Index specification is out of range\n".to_string());
-                executor
-                    .stack
-                    .push(Type::Error("index-out-range".to_string()));
+                executor.log_error("Index specification is out of range".to_string());
+                executor.stack.push(Type::error("index-out-range"));
             }
         }
 
@@ -500,8 +1075,13 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let data = executor.pop_stack();
             let index = executor.pop_stack().get_number();
             let mut list = executor.pop_stack().get_list();
-            list.insert(index as usize, data);
-            executor.stack.push(Type::List(list));
+            if list.len() >= index as usize {
+                list.insert(index as usize, data);
+                executor.stack.push(Type::List(list));
+            } else {
+                executor.log_error("Index specification is out of range".to_string());
+                executor.stack.push(Type::error("index-out-range"));
+            }
         }
 
         // Get index of the list
@@ -512,29 +1092,60 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             for (index, item) in list.iter().enumerate() {
                 if target == item.clone().get_string() {
                     executor.stack.push(Type::Number(index as f64));
-                    return;
+                    return Ok(());
                 }
             }
-            executor.log_print(String::from("Error! item not found in the list\n"));
-            executor
-                .stack
-                .push(Type::Error(String::from("item-not-found")));
+            executor.log_error(String::from("item not found in the list"));
+            executor.stack.push(Type::error("item-not-found"));
         }
 
         // Sorting in the list
         "sort" => {
-            let mut list: Vec<String> = executor
-                .pop_stack()
-                .get_list()
-                .iter()
-                .map(|x| x.to_owned().get_string())
-                .collect();
-            list.sort();
-            executor.stack.push(Type::List(
-                list.iter()
-                    .map(|x| Type::String(x.to_string()))
-                    .collect::<Vec<_>>(),
-            ));
+            let mut list = executor.pop_stack().get_list();
+            list.sort_by(compare);
+            executor.stack.push(Type::List(list));
+        }
+
+        // Get the smallest value in the list
+        "min-of" => {
+            let list = executor.pop_stack().get_list();
+            match list.into_iter().min_by(compare) {
+                Some(value) => executor.stack.push(value),
+                None => empty_list(executor),
+            }
+        }
+
+        // Get the largest value in the list
+        "max-of" => {
+            let list = executor.pop_stack().get_list();
+            match list.into_iter().max_by(compare) {
+                Some(value) => executor.stack.push(value),
+                None => empty_list(executor),
+            }
+        }
+
+        // Search the index of value in the sorted list
+        "bsearch" => {
+            let value = executor.pop_stack();
+            let list = executor.pop_stack().get_list();
+
+            match list.binary_search_by(|x| compare(x, &value)) {
+                Ok(index) => executor.stack.push(Type::Number(index as f64)),
+                Err(_) => {
+                    executor.log_error(String::from("item not found in the list"));
+                    executor.stack.push(Type::error("item-not-found"));
+                }
+            }
+        }
+
+        // Insert the value keeping the list sorted
+        "insert-sorted" => {
+            let value = executor.pop_stack();
+            let mut list = executor.pop_stack().get_list();
+
+            let index = list.partition_point(|x| compare(x, &value) != Ordering::Greater);
+            list.insert(index, value);
+            executor.stack.push(Type::List(list));
         }
 
         // reverse in the list
@@ -548,16 +1159,26 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "for" => {
             let code = executor.pop_stack().get_string();
             let vars = executor.pop_stack().get_string();
-            let list = executor.pop_stack().get_list();
+            let mut list = executor.pop_stack();
+
+            // Consume the generator lazily
+            if is_generator(&list) {
+                while let Some((generator, x)) = resume(executor, list)? {
+                    list = generator;
+                    executor.bind(vars.clone(), x);
+                    if !iterate(executor, code.clone())? {
+                        break;
+                    }
+                }
+                return Ok(());
+            }
 
-            list.iter().for_each(|x| {
-                executor
-                    .memory
-                    .entry(vars.clone())
-                    .and_modify(|value| *value = x.clone())
-                    .or_insert(x.clone());
-                executor.evaluate_program(code.clone());
-            });
+            for x in list.get_list() {
+                executor.bind(vars.clone(), x);
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
+            }
         }
 
         // Generate a range
@@ -566,14 +1187,18 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let max = executor.pop_stack().get_number();
             let min = executor.pop_stack().get_number();
 
-            let mut range: Vec<Type> = Vec::new();
-            let mut i = min;
-
-            while i < max {
-                range.push(Type::Number(i));
-                i += step;
+            // The negative step counts down, and the max is excluded in both directions
+            let count = ((max - min) / step).ceil().max(0.0);
+            if step == 0.0 || !count.is_finite() {
+                executor.log_error(format!("invalid step {step} of range"));
+                executor.stack.push(Type::error("range"));
+                return Ok(());
             }
 
+            // Multiplying the step doesn't accumulate the error of the float addition
+            let range: Vec<Type> = (0..count as usize)
+                .map(|i| Type::Number(min + i as f64 * step))
+                .collect();
             executor.stack.push(Type::List(range));
         }
 
@@ -583,6 +1208,61 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::Number(data.len() as f64));
         }
 
+        // Commands of persistent list, whose copies share the same items
+
+        // Make persistent list from the list
+        "plist" => {
+            let list = executor.pop_stack().get_plist();
+            executor.stack.push(Type::PList(list));
+        }
+
+        // Append value in the persistent list
+        "plist-push" => {
+            let data = executor.pop_stack();
+            let mut list = executor.pop_stack().get_plist();
+            list.push_back(data);
+            executor.stack.push(Type::PList(list));
+        }
+
+        // Get persistent list value by index
+        "plist-get" => {
+            let index = executor.pop_stack().get_number() as usize;
+            let list = executor.pop_stack().get_plist();
+            match list.get(index) {
+                Some(value) => executor.stack.push(value.clone()),
+                None => {
+                    executor.log_error("Index specification is out of range".to_string());
+                    executor.stack.push(Type::error("index-out-range"));
+                }
+            }
+        }
+
+        // Set persistent list value by index
+        "plist-set" => {
+            let value = executor.pop_stack();
+            let index = executor.pop_stack().get_number() as usize;
+            let mut list = executor.pop_stack().get_plist();
+            if list.len() > index {
+                list.set(index, value);
+                executor.stack.push(Type::PList(list));
+            } else {
+                executor.log_error("Index specification is out of range".to_string());
+                executor.stack.push(Type::error("index-out-range"));
+            }
+        }
+
+        // Get length of persistent list
+        "plist-len" => {
+            let list = executor.pop_stack().get_plist();
+            executor.stack.push(Type::Number(list.len() as f64));
+        }
+
+        // Make normal list from the persistent list
+        "plist-to-list" => {
+            let list = executor.pop_stack().get_list();
+            executor.stack.push(Type::List(list));
+        }
+
         // Commands of functional programming
 
         // Mapping a list
@@ -593,13 +1273,10 @@ pub fn execute_command(executor: &mut Executor, command: String) {
 
             let mut result_list = Vec::new();
             for x in list.iter() {
-                executor
-                    .memory
-                    .entry(vars.clone())
-                    .and_modify(|value| *value = x.clone())
-                    .or_insert(x.clone());
-
-                executor.evaluate_program(code.clone());
+                executor.bind(vars.clone(), x.clone());
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
                 result_list.push(executor.pop_stack());
             }
 
@@ -615,13 +1292,10 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let mut result_list = Vec::new();
 
             for x in list.iter() {
-                executor
-                    .memory
-                    .entry(vars.clone())
-                    .and_modify(|value| *value = x.clone())
-                    .or_insert(x.clone());
-
-                executor.evaluate_program(code.clone());
+                executor.bind(vars.clone(), x.clone());
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
                 if executor.pop_stack().get_bool() {
                     result_list.push(x.clone());
                 }
@@ -630,6 +1304,125 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::List(result_list));
         }
 
+        // Get the first item that satisfies the condition
+        "find-if" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_string();
+            let list = executor.pop_stack().get_list();
+
+            match find_if(executor, &list, vars, code)? {
+                Some(index) => executor.stack.push(list[index].clone()),
+                None => {
+                    executor.log_error(String::from("item not found in the list"));
+                    executor.stack.push(Type::error("item-not-found"));
+                }
+            }
+        }
+
+        // Get the index of the first item that satisfies the condition
+        "position-if" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_string();
+            let list = executor.pop_stack().get_list();
+
+            match find_if(executor, &list, vars, code)? {
+                Some(index) => executor.stack.push(Type::Number(index as f64)),
+                None => {
+                    executor.log_error(String::from("item not found in the list"));
+                    executor.stack.push(Type::error("item-not-found"));
+                }
+            }
+        }
+
+        // Judge is there any item that satisfies the condition
+        "any" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_string();
+            let list = executor.pop_stack().get_list();
+
+            let found = find_if(executor, &list, vars, code)?;
+            executor.stack.push(Type::Bool(found.is_some()));
+        }
+
+        // Judge do all items satisfy the condition
+        "every" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_string();
+            let list = executor.pop_stack().get_list();
+
+            let mut result = true;
+            for x in list.iter() {
+                executor.bind(vars.clone(), x.clone());
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
+                if !executor.pop_stack().get_bool() {
+                    result = false;
+                    break;
+                }
+            }
+            executor.stack.push(Type::Bool(result));
+        }
+
+        // Count the items that satisfy the condition
+        "count-if" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_string();
+            let list = executor.pop_stack().get_list();
+
+            let mut count = 0;
+            for x in list.iter() {
+                executor.bind(vars.clone(), x.clone());
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
+                if executor.pop_stack().get_bool() {
+                    count += 1;
+                }
+            }
+            executor.stack.push(Type::Number(count as f64));
+        }
+
+        // Group the items by the key, into the map of key to list
+        "group-by" => {
+            let code = executor.pop_stack().get_string();
+            let vars = executor.pop_stack().get_string();
+            let list = executor.pop_stack().get_list();
+
+            let mut groups: IndexMap<String, Type> = IndexMap::new();
+            for x in list.iter() {
+                executor.bind(vars.clone(), x.clone());
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
+                let key = executor.pop_stack().get_string();
+
+                // Keep the groups in the order they first appear
+                if let Type::List(group) =
+                    groups.entry(key).or_insert_with(|| Type::List(Vec::new()))
+                {
+                    group.push(x.clone());
+                }
+            }
+            executor
+                .stack
+                .push(Type::Object("group".to_string(), groups));
+        }
+
+        // Count the occurrences of each value, into the map of value to count
+        "frequencies" => {
+            let list = executor.pop_stack().get_list();
+
+            let mut counts: IndexMap<String, Type> = IndexMap::new();
+            for mut x in list {
+                let count = counts.entry(x.get_string()).or_insert(Type::Number(0.0));
+                *count = Type::Number(count.get_number() + 1.0);
+            }
+            executor
+                .stack
+                .push(Type::Object("frequencies".to_string(), counts));
+        }
+
         // Generate value from list
         "reduce" => {
             let code = executor.pop_stack().get_string();
@@ -638,39 +1431,70 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let acc = executor.pop_stack().get_string();
             let list = executor.pop_stack().get_list();
 
-            executor
-                .memory
-                .entry(acc.clone())
-                .and_modify(|value| *value = init.clone())
-                .or_insert(init);
-
-            for x in list.iter() {
-                executor
-                    .memory
-                    .entry(now.clone())
-                    .and_modify(|value| *value = x.clone())
-                    .or_insert(x.clone());
-
-                executor.evaluate_program(code.clone());
+            executor.bind(acc.clone(), init);
+            for x in list {
+                executor.bind(now.clone(), x);
+                if !iterate(executor, code.clone())? {
+                    break;
+                }
                 let result = executor.pop_stack();
-
-                executor
-                    .memory
-                    .entry(acc.clone())
-                    .and_modify(|value| *value = result.clone())
-                    .or_insert(result);
+                executor.bind(acc.clone(), result);
             }
 
-            let result = executor.memory.get(&acc);
+            let result = executor.variable(&acc).cloned();
             executor
                 .stack
-                .push(result.unwrap_or(&Type::String("".to_string())).clone());
+                .push(result.unwrap_or(Type::String("".to_string())));
+            executor.bind(acc, Type::String("".to_string()));
+        }
 
-            executor
-                .memory
-                .entry(acc.clone())
-                .and_modify(|value| *value = Type::String("".to_string()))
-                .or_insert(Type::String("".to_string()));
+        // Commands of generator
+
+        // Make the generator from the initial code and the step code, which runs from its start
+        // on every resume; only the variables are kept between the steps
+        "step-generator" => {
+            let body = executor.pop_stack().get_string();
+            let init = executor.pop_stack().get_string();
+
+            // Variables are captured at this time
+            let mut child = executor.child();
+            child.memory = executor.memory.clone();
+            child.evaluate_program(init)?;
+
+            executor.stack.push(Type::Object(
+                "generator".to_string(),
+                IndexMap::from([
+                    ("body".to_string(), Type::String(body)),
+                    ("state".to_string(), state_object(child.memory)),
+                ]),
+            ));
+        }
+
+        // Give the value of the current step, the step runs on to its end and the last one is taken
+        "yield" => {
+            let value = executor.pop_stack();
+            executor.yielded = Some(value);
+        }
+
+        // Get the next value of generator
+        "next" => {
+            let generator = executor.pop_stack();
+            if !is_generator(&generator) {
+                executor.log_error("the value is not a generator".to_string());
+                executor.stack.push(Type::error("next"));
+                return Ok(());
+            }
+
+            match resume(executor, generator.clone())? {
+                Some((generator, value)) => {
+                    executor.stack.push(generator);
+                    executor.stack.push(value);
+                }
+                None => {
+                    executor.stack.push(generator);
+                    executor.stack.push(Type::error("generator-done"));
+                }
+            }
         }
 
         // Commands of memory manage
@@ -688,35 +1512,79 @@ pub fn execute_command(executor: &mut Executor, command: String) {
 
         // Get Stack as List
         "get-stack" => {
+            // The copy is taken before the push, so the list doesn't contain itself
             executor.stack.push(Type::List(executor.stack.clone()));
         }
 
+        // Write the stack into the file as JSON, without changing it
+        "dump-stack" => {
+            let path = executor.pop_stack().get_string();
+            if executor.dry_run(format!("dump-stack {path}")) {
+                return Ok(());
+            }
+            let json = json::stack_to_json(&executor.stack);
+            let text = serde_json::to_string_pretty(&json).unwrap_or_default();
+            if let Err(e) = fs::write(&path, text) {
+                executor.log_internal_error(format!("{path}: {e}"));
+                executor.stack.push(Type::error("dump-stack"));
+            }
+        }
+
         // Define variable at memory
         "var" => {
             let name = executor.pop_stack().get_string();
             let data = executor.pop_stack();
-            executor
-                .memory
-                .entry(name)
-                .and_modify(|value| *value = data.clone())
-                .or_insert(data);
+            // In the function, it is defined in the local scope out of the namespace
+            let defined = match executor.locals.last() {
+                Some(scope) => scope.contains_key(name.as_str()),
+                None => executor
+                    .memory
+                    .contains_key(executor.qualify(name.clone()).as_str()),
+            };
+            if defined {
+                executor.log_warning(format!(
+                    "the variable `{name}` is already defined, use `set!` to reassign it"
+                ));
+            }
+            executor.bind(name, data);
+            executor.show_variables()
+        }
+
+        // Reassign the defined variable
+        "set!" => {
+            let name = executor.pop_stack().get_string();
+            let data = executor.pop_stack();
+            match executor.variable_mut(&name) {
+                Some(value) => *value = data,
+                None => {
+                    executor.log_error(format!("the variable `{name}` is not defined"));
+                    executor.stack.push(Type::error("set!"));
+                }
+            }
             executor.show_variables()
         }
 
         // Get data type of value
         "type" => {
-            let result = match executor.pop_stack() {
-                Type::Number(_) => "number".to_string(),
-                Type::String(_) => "string".to_string(),
-                Type::Bool(_) => "bool".to_string(),
-                Type::List(_) => "list".to_string(),
-                Type::Error(_) => "error".to_string(),
-                Type::Object(name, _) => name.to_string(),
-            };
-
+            let result = type_name(&executor.pop_stack());
             executor.stack.push(Type::String(result));
         }
 
+        // Judge is the value of the data type
+        "is-number" | "is-string" | "is-bool" | "is-list" | "is-object" | "is-error" => {
+            let value = executor.pop_stack();
+            let result = matches!(
+                (command, value),
+                ("is-number", Type::Number(_))
+                    | ("is-string", Type::String(_))
+                    | ("is-bool", Type::Bool(_))
+                    | ("is-list", Type::List(_))
+                    | ("is-object", Type::Object(..))
+                    | ("is-error", Type::Error(_))
+            );
+            executor.stack.push(Type::Bool(result));
+        }
+
         // Explicit data type casting
         "cast" => {
             let types = executor.pop_stack().get_string();
@@ -726,56 +1594,621 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                 "string" => executor.stack.push(Type::String(value.get_string())),
                 "bool" => executor.stack.push(Type::Bool(value.get_bool())),
                 "list" => executor.stack.push(Type::List(value.get_list())),
-                "error" => executor.stack.push(Type::Error(value.get_string())),
+                "error" => executor.stack.push(Type::error(&value.get_string())),
                 _ => executor.stack.push(value),
             }
         }
 
-        // Get memory information
-        "mem" => {
-            let mut list: Vec<Type> = Vec::new();
-            for (name, _) in executor.memory.clone() {
-                list.push(Type::String(name))
+        // Get memory information
+        "mem" => {
+            let mut list: Vec<Type> = Vec::new();
+            for name in executor.memory.keys() {
+                list.push(Type::String(name.to_string()))
+            }
+            executor.stack.push(Type::List(list))
+        }
+
+        // Bind the list values to the variables pairwise, `...name` takes the rest
+        "unpack" => {
+            let names = executor.pop_stack().get_list();
+            let mut values = executor.pop_stack().get_list().into_iter();
+            for mut name in names {
+                let name = name.get_string();
+                let value = match name.strip_prefix("...") {
+                    Some(rest) => {
+                        let list = Type::List(values.by_ref().collect());
+                        executor.bind(rest.to_string(), list);
+                        continue;
+                    }
+                    None => values.next(),
+                };
+                match value {
+                    Some(value) => executor.bind(name, value),
+                    None => {
+                        executor.log_error(format!("no value to bind to `{name}`"));
+                        executor.stack.push(Type::error("unpack"));
+                        return Ok(());
+                    }
+                }
+            }
+            executor.show_variables()
+        }
+
+        // Bind the properties to the variables of the same names
+        "unpack-object" => {
+            let names = executor.pop_stack().get_list();
+            let (_, object) = executor.pop_stack().get_object();
+            for mut name in names {
+                let name = name.get_string();
+                match object.get(&name) {
+                    Some(value) => executor.bind(name.clone(), value.clone()),
+                    None => {
+                        executor.log_error(format!("the object has no property `{name}`"));
+                        executor.stack.push(Type::error("unpack-object"));
+                        return Ok(());
+                    }
+                }
+            }
+            executor.show_variables()
+        }
+
+        // Bind the list values if it has the shape of the pattern like `[head ...rest]`
+        "match-list" => {
+            let names: Vec<String> = match executor.pop_stack() {
+                Type::List(names) => names.into_iter().map(|mut x| x.get_string()).collect(),
+                mut pattern => {
+                    let pattern = pattern.get_string();
+                    let pattern = pattern.trim().trim_start_matches('[').trim_end_matches(']');
+                    pattern.split_whitespace().map(str::to_string).collect()
+                }
+            };
+            let values = match executor.pop_stack() {
+                mut value @ (Type::List(_) | Type::PList(_)) => value.get_list(),
+                _ => {
+                    executor.stack.push(Type::Bool(false));
+                    return Ok(());
+                }
+            };
+
+            // The rest takes the values between the names before and after it
+            let rest = names.iter().position(|name| name.starts_with("..."));
+            let fixed = names.len() - rest.map_or(0, |_| 1);
+            let matched = match rest {
+                Some(_) => values.len() >= fixed,
+                None => values.len() == fixed,
+            };
+            if matched {
+                let mut values = values.into_iter();
+                let taken = values.len() - fixed;
+                for name in names {
+                    let value = match name.strip_prefix("...") {
+                        Some(_) => Type::List(values.by_ref().take(taken).collect()),
+                        None => values.next().unwrap_or(Type::List(Vec::new())),
+                    };
+                    let name = name.trim_start_matches("...").to_string();
+                    if name != "_" && !name.is_empty() {
+                        executor.bind(name, value);
+                    }
+                }
+                executor.show_variables();
+            }
+            executor.stack.push(Type::Bool(matched));
+        }
+
+        // Evaluate code defining variables in the namespace
+        "in-namespace" => {
+            let code = executor.pop_stack().get_string();
+            let name = executor.pop_stack().get_string();
+            executor.namespaces.push(name);
+            let result = executor.evaluate_program(code);
+            executor.namespaces.pop();
+            result?;
+        }
+
+        // Import the definitions of the file into the namespace of its name, only once
+        "import" => {
+            let name = executor.pop_stack().get_string();
+            import(executor, &name)?;
+        }
+
+        // Define variable in the global memory
+        "global" => {
+            let name = executor.pop_stack().get_string();
+            let data = executor.pop_stack();
+            let name = executor.intern(&name);
+            executor.memory.insert(name, data);
+            executor.show_variables()
+        }
+
+        // Define variable in the innermost local scope
+        "local" => {
+            let name = executor.pop_stack().get_string();
+            let data = executor.pop_stack();
+            // The top level has no local scope, so it is the global
+            executor.bind(name, data);
+            executor.show_variables()
+        }
+
+        // Get list of the local variables
+        "vars-local" => {
+            let list = match executor.locals.last() {
+                Some(scope) => scope
+                    .keys()
+                    .map(|name| Type::String(name.to_string()))
+                    .collect(),
+                None => Vec::new(),
+            };
+            executor.stack.push(Type::List(list))
+        }
+
+        // Get list of the global variables
+        "vars-global" => {
+            let list = executor
+                .memory
+                .keys()
+                .map(|name| Type::String(name.to_string()))
+                .collect();
+            executor.stack.push(Type::List(list))
+        }
+
+        // Free up memory space of variable
+        "free" => {
+            let name = executor.pop_stack().get_string();
+            executor.memory.shift_remove(name.as_str());
+            executor.show_variables();
+        }
+
+        // Copy stack's top value
+        "copy" => {
+            let data = executor.pop_stack();
+            executor.stack.push(data.clone());
+            executor.stack.push(data);
+        }
+
+        // Swap stack's top 2 value
+        "swap" => {
+            let b = executor.pop_stack();
+            let a = executor.pop_stack();
+            executor.stack.push(b);
+            executor.stack.push(a);
+        }
+
+        // Rearrange the top of the stack by the pattern like `ab-bab`
+        "shuffle-stack" => {
+            let pattern = executor.pop_stack().get_string();
+            let (before, after) = match pattern.split_once('-') {
+                Some((before, after))
+                    if after.chars().all(|c| before.contains(c))
+                        && before
+                            .chars()
+                            .enumerate()
+                            .all(|(i, c)| before.find(c) == Some(i)) =>
+                {
+                    (before.chars().collect::<Vec<_>>(), after.to_string())
+                }
+                _ => {
+                    executor.log_error(format!("invalid shuffle pattern \"{pattern}\""));
+                    executor.stack.push(Type::error("shuffle-stack"));
+                    return Ok(());
+                }
+            };
+            if executor.stack.len() < before.len() {
+                executor.log_error("There are not enough values on the stack".to_string());
+                executor.stack.push(Type::error("shuffle-stack"));
+                return Ok(());
+            }
+
+            let values = executor
+                .stack
+                .split_off(executor.stack.len() - before.len());
+            for c in after.chars() {
+                if let Some(i) = before.iter().position(|x| *x == c) {
+                    executor.stack.push(values[i].clone());
+                }
+            }
+        }
+
+        // Pop the number of values into a list
+        "pack-n" => {
+            let count = executor.pop_stack().get_number().max(0.0) as usize;
+            if executor.stack.len() < count {
+                executor.log_error("There are not enough values on the stack".to_string());
+                executor.stack.push(Type::error("pack-n"));
+                return Ok(());
+            }
+            let values = executor.stack.split_off(executor.stack.len() - count);
+            executor.stack.push(Type::List(values));
+        }
+
+        // Push all elements of the list
+        "unpack-all" => {
+            let list = executor.pop_stack().get_list();
+            executor.stack.extend(list);
+        }
+
+        // Evaluate code beneath the top value, and push it back
+        "dip" => {
+            let code = executor.pop_stack().get_string();
+            let top = executor.pop_stack();
+            executor.evaluate_program(code)?;
+            executor.stack.push(top);
+        }
+
+        // Set the interpreter option
+        "set-option" => {
+            let name = executor.pop_stack().get_string();
+            let value = executor.pop_stack().get_string();
+            if !executor.options.set(&name, value) {
+                executor.log_error(format!("unknown option \"{name}\""));
+                executor.stack.push(Type::error("set-option"));
+            }
+        }
+
+        // Get the interpreter option
+        "get-option" => {
+            let name = executor.pop_stack().get_string();
+            match executor.options.get(&name) {
+                Some(value) => executor.stack.push(Type::String(value)),
+                None => {
+                    executor.log_error(format!("unknown option \"{name}\""));
+                    executor.stack.push(Type::error("get-option"));
+                }
+            }
+        }
+
+        // Set the digits after the decimal point to show numbers
+        "set-precision" => {
+            let digits = executor.pop_stack().get_number();
+            // The negative digits mean the shortest form
+            executor.precision = if digits < 0.0 {
+                None
+            } else {
+                Some(digits as usize)
+            };
+            set_precision(executor.precision);
+        }
+
+        // Commands of reflection
+
+        // Get list of the built-in commands
+        "commands" => executor.stack.push(Type::List(
+            registry::COMMANDS
+                .iter()
+                .map(|command| Type::String(command.name.to_string()))
+                .collect(),
+        )),
+
+        // Get category, arity and description of the command
+        "command-info" => {
+            let name = executor.pop_stack().get_string();
+            match registry::find(&name) {
+                Some(command) => {
+                    let mut object = IndexMap::new();
+                    object.insert("name".to_string(), Type::String(command.name.to_string()));
+                    object.insert(
+                        "category".to_string(),
+                        Type::String(command.category.to_string()),
+                    );
+                    object.insert("arity".to_string(), Type::Number(command.arity as f64));
+                    object.insert("doc".to_string(), Type::String(command.doc.to_string()));
+                    executor
+                        .stack
+                        .push(Type::Object("command-info".to_string(), object));
+                }
+                None => {
+                    executor.log_error(format!("unknown command \"{name}\""));
+                    executor.stack.push(Type::error("command-info"));
+                }
+            }
+        }
+
+        // Attach the documentation to the user function
+        "doc" => {
+            let text = executor.pop_stack().get_string();
+            let name = executor.pop_stack().get_string();
+            executor.docs.insert(name, text);
+        }
+
+        // Get the documentation of the user function or the command
+        "help" => {
+            let name = executor.pop_stack().get_string();
+            let text = executor
+                .docs
+                .get(&name)
+                .cloned()
+                .or_else(|| registry::find(&name).map(|command| command.doc.to_string()));
+            match text {
+                Some(text) => executor.stack.push(Type::String(text)),
+                None => {
+                    executor.log_error(format!("no documentation of \"{name}\""));
+                    executor.stack.push(Type::error("help"));
+                }
+            }
+        }
+
+        // Give the other name to the command
+        "alias" => {
+            let command = executor.pop_stack().get_string();
+            let name = executor.pop_stack().get_string();
+            // Follow the alias, so that the chain of aliases works
+            let command = executor.aliases.get(&command).cloned().unwrap_or(command);
+            executor.aliases.insert(name, command);
+        }
+
+        // Define the name replaced by the tokens of the template, before the evaluation
+        "macro" => {
+            let name = executor.pop_stack().get_string();
+            let template = executor.pop_stack().get_string();
+            executor.define_macro(name, template);
+        }
+
+        // Ask the user before the command runs
+        "confirm-before" => {
+            let command = executor.pop_stack().get_string();
+            executor.confirm.insert(command);
+        }
+
+        // Intercept the command by the code
+        "wrap-command" => {
+            let code = executor.pop_stack().get_string();
+            let command = executor.pop_stack().get_string();
+            executor.wrappers.insert(command, code);
+        }
+
+        // Call the original command in the wrapper
+        "call-original" => match executor.wrapping.last().cloned() {
+            Some(command) => execute_command(executor, &command)?,
+            None => {
+                executor.log_error("call-original is used outside of the wrapper".to_string());
+                executor.stack.push(Type::error("call-original"));
+            }
+        },
+
+        // Commands of key-value store
+
+        // Open the key-value store of the file
+        "kv-open" => {
+            let path = executor.pop_stack().get_string();
+            match kv::Store::open(Path::new(&path)) {
+                Ok(store) => executor.kv = Some(store),
+                Err(e) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("kv-open"));
+                }
+            }
+        }
+
+        // Get the value of the key in the store
+        "kv-get" => {
+            let key = executor.pop_stack().get_string();
+            match executor.kv.as_ref().map(|store| store.get(&key)) {
+                Some(Some(value)) => executor.stack.push(value),
+                Some(None) => {
+                    executor.log_error(format!("the key `{key}` is not found in the store"));
+                    executor.stack.push(Type::error("kv-get"));
+                }
+                None => kv_not_opened(executor),
+            }
+        }
+
+        // Set the value of the key in the store
+        "kv-set" => {
+            let key = executor.pop_stack().get_string();
+            let value = executor.pop_stack();
+            match executor.kv.as_mut().map(|store| store.set(key, &value)) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("kv-set"));
+                }
+                None => kv_not_opened(executor),
+            }
+        }
+
+        // Delete the key in the store
+        "kv-delete" => {
+            let key = executor.pop_stack().get_string();
+            match executor.kv.as_mut().map(|store| store.delete(&key)) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("kv-delete"));
+                }
+                None => kv_not_opened(executor),
+            }
+        }
+
+        // Get all of keys in the store
+        "kv-keys" => match executor.kv.as_ref().map(|store| store.keys()) {
+            Some(keys) => executor
+                .stack
+                .push(Type::List(keys.into_iter().map(Type::String).collect())),
+            None => kv_not_opened(executor),
+        },
+
+        // Commands of cache
+
+        // Cache the value for seconds
+        "cache-set" => {
+            let ttl = executor.pop_stack().get_number();
+            let key = executor.pop_stack().get_string();
+            let value = executor.pop_stack();
+            let expiration = match to_duration(ttl.max(0.0)) {
+                Ok(ttl) => Instant::now() + ttl,
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("cache-set"));
+                    return Ok(());
+                }
+            };
+
+            // Drop the expired values not to grow forever
+            let now = Instant::now();
+            executor.cache.retain(|_, (time, _)| *time > now);
+            executor.cache.insert(key, (expiration, value));
+        }
+
+        // Get the cached value, or error if it is missing or expired
+        "cache-get" => {
+            let key = executor.pop_stack().get_string();
+            match executor.cache.get(&key) {
+                Some((time, value)) if *time > Instant::now() => executor.stack.push(value.clone()),
+                // A miss is expected in the usual flow, so it isn't reported
+                _ => executor.stack.push(Type::error("cache-miss")),
+            }
+        }
+
+        // Commands of times
+
+        // Get now time as unix epoch
+        "now-time" => match &executor.deterministic {
+            Some(determinism) => executor.stack.push(Type::Number(determinism.now)),
+            None => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(time) => executor.stack.push(Type::Number(time.as_secs_f64())),
+                Err(e) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("now-time"));
+                }
+            },
+        },
+
+        // Get now time in UTC as ISO 8601
+        "now-utc" => executor.stack.push(Type::String(
+            now_utc(executor).to_rfc3339_opts(SecondsFormat::Secs, true),
+        )),
+
+        // Get now time in the IANA time zone as ISO 8601
+        "now-tz" => {
+            let zone = executor.pop_stack().get_string();
+            match zone.parse::<Tz>() {
+                Ok(zone) => executor.stack.push(Type::String(
+                    now_utc(executor)
+                        .with_timezone(&zone)
+                        .to_rfc3339_opts(SecondsFormat::Secs, true),
+                )),
+                Err(_) => {
+                    executor.log_error(format!("unknown time zone \"{zone}\""));
+                    executor.stack.push(Type::error("now-tz"));
+                }
+            }
+        }
+
+        // Parse the ISO 8601 or RFC 2822 time into unix epoch
+        "parse-time" => {
+            let text = executor.pop_stack().get_string();
+            match datetime::parse(&text) {
+                Some(time) => executor
+                    .stack
+                    .push(Type::Number(datetime::to_seconds(&time))),
+                None => not_time(executor, &text, "parse-time"),
+            }
+        }
+
+        // Format the time by `iso`, `rfc2822` or the strftime pattern
+        "format-time" => {
+            let format = executor.pop_stack().get_string();
+            let value = executor.pop_stack();
+            let Some(time) = datetime::from_value(&value) else {
+                not_time(executor, &value.display(), "format-time");
+                return Ok(());
+            };
+            match datetime::format(&time, &format) {
+                Some(text) => executor.stack.push(Type::String(text)),
+                None => {
+                    executor.log_error(format!("invalid time format \"{format}\""));
+                    executor.stack.push(Type::error("format-time"));
+                }
             }
-            executor.stack.push(Type::List(list))
         }
 
-        // Free up memory space of variable
-        "free" => {
-            let name = executor.pop_stack().get_string();
-            executor.memory.remove(name.as_str());
-            executor.show_variables();
+        // Add the duration to the time, which is kept as epoch or as string
+        "add-duration" => {
+            let duration = executor.pop_stack();
+            let value = executor.pop_stack();
+            let Some(time) = datetime::from_value(&value) else {
+                not_time(executor, &value.display(), "add-duration");
+                return Ok(());
+            };
+            let Some(seconds) = duration_of(&duration) else {
+                not_duration(executor, &duration.display(), "add-duration");
+                return Ok(());
+            };
+            match datetime::add(&time, seconds) {
+                Some(time) if matches!(value, Type::String(_)) => {
+                    executor.stack.push(Type::String(datetime::iso(&time)))
+                }
+                Some(time) => executor
+                    .stack
+                    .push(Type::Number(datetime::to_seconds(&time))),
+                None => {
+                    executor.log_error(format!("the duration {seconds} seconds is out of range"));
+                    executor.stack.push(Type::error("add-duration"));
+                }
+            }
         }
 
-        // Copy stack's top value
-        "copy" => {
-            let data = executor.pop_stack();
-            executor.stack.push(data.clone());
-            executor.stack.push(data);
+        // Get the seconds from the time to the other
+        "between" => {
+            let end = executor.pop_stack();
+            let start = executor.pop_stack();
+            match (datetime::from_value(&start), datetime::from_value(&end)) {
+                (Some(start), Some(end)) => executor.stack.push(Type::Number(
+                    datetime::to_seconds(&end) - datetime::to_seconds(&start),
+                )),
+                (None, _) => not_time(executor, &start.display(), "between"),
+                (_, None) => not_time(executor, &end.display(), "between"),
+            }
         }
 
-        // Swap stack's top 2 value
-        "swap" => {
-            let b = executor.pop_stack();
-            let a = executor.pop_stack();
-            executor.stack.push(b);
-            executor.stack.push(a);
+        // Parse the ISO 8601 duration like `PT1H30M` into seconds
+        "parse-duration" => {
+            let text = executor.pop_stack().get_string();
+            match datetime::parse_duration(&text) {
+                Some(seconds) => executor.stack.push(Type::Number(seconds)),
+                None => not_duration(executor, &text, "parse-duration"),
+            }
         }
 
-        // Commands of times
+        // Format the seconds as ISO 8601 duration
+        "format-duration" => {
+            let seconds = executor.pop_stack().get_number();
+            executor
+                .stack
+                .push(Type::String(datetime::format_duration(seconds)));
+        }
 
-        // Get now time as unix epoch
-        "now-time" => {
-            executor.stack.push(Type::Number(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs_f64(),
-            ));
+        // Start the stopwatch of the name
+        "stopwatch-start" => {
+            let name = executor.pop_stack().get_string();
+            executor.stopwatches.insert(name, Instant::now());
+        }
+
+        // Get the seconds since the stopwatch started
+        "stopwatch-elapsed" => {
+            let name = executor.pop_stack().get_string();
+            match executor.stopwatches.get(&name) {
+                // The time doesn't pass in the deterministic mode
+                Some(_) if executor.deterministic.is_some() => {
+                    executor.stack.push(Type::Number(0.0))
+                }
+                Some(start) => executor
+                    .stack
+                    .push(Type::Number(start.elapsed().as_secs_f64())),
+                None => {
+                    executor.log_error(format!("the stopwatch `{name}` is not started"));
+                    executor.stack.push(Type::error("stopwatch"));
+                }
+            }
         }
 
         // Sleep fixed time
-        "sleep" => sleep(Duration::from_secs_f64(executor.pop_stack().get_number())),
+        "sleep" => match to_duration(executor.pop_stack().get_number()) {
+            Ok(duration) => sleep(duration),
+            Err(e) => {
+                executor.log_error(e);
+                executor.stack.push(Type::error("sleep"));
+            }
+        },
 
         // Commands of object oriented system
 
@@ -783,14 +2216,14 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "instance" => {
             let data = executor.pop_stack().get_list();
             let mut class = executor.pop_stack().get_list();
-            let mut object: HashMap<String, Type> = HashMap::new();
+            let mut object: IndexMap<String, Type> = IndexMap::new();
 
             let name = if !class.is_empty() {
                 class[0].get_string()
             } else {
-                executor.log_print("Error! the type name is not found.".to_string());
-                executor.stack.push(Type::Error("instance-name".to_string()));
-                return;
+                executor.log_error("the type name is not found".to_string());
+                executor.stack.push(Type::error("instance-name"));
+                return Ok(());
             };
 
             let mut index = 0;
@@ -800,10 +2233,10 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                     let element = match data.get(index) {
                         Some(value) => value,
                         None => {
-                            executor.log_print("Error! initial data is shortage\n".to_string());
+                            executor.log_error("initial data is shortage".to_string());
                             executor.stack
-                                .push(Type::Error("instance-shortage".to_string()));
-                            return;
+                                .push(Type::error("instance-shortage"));
+                            return Ok(());
                         }
                     };
                     object.insert(
@@ -815,8 +2248,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                     let item = item.get_list();
                     object.insert(item[0].clone().get_string(), item[1].clone());
                 } else {
-                    executor.log_print("Error! the class data structure is wrong.".to_string());
-                    executor.stack.push(Type::Error("instance-default".to_string()));
+                    executor.log_error("the class data structure is wrong".to_string());
+                    executor.stack.push(Type::error("instance-default"));
                 }
             }
 
@@ -830,7 +2263,7 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(
                 object
                     .get(name.as_str())
-                    .unwrap_or(&Type::Error("property".to_string()))
+                    .unwrap_or(&Type::error("property"))
                     .clone(),
             )
         }
@@ -841,7 +2274,7 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             let (name, value) = executor.pop_stack().get_object();
             let data = Type::Object(name, value.clone());
             executor.memory
-                .entry("self".to_string())
+                .entry(Symbol::from("self"))
                 .and_modify(|value| *value = data.clone())
                 .or_insert(data);
 
@@ -850,7 +2283,7 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                 None => "".to_string(),
             };
 
-            executor.evaluate_program(program);
+            call(executor, program)?;
         }
 
         // Modify the property of object
@@ -880,15 +2313,117 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         // Commands of external cooperation processing
 
         // Send the http request
+        #[cfg(feature = "network")]
         "request" => {
             let url = executor.pop_stack().get_string();
-            match reqwest::blocking::get(url) {
+            match executor.request.get(&url) {
                 Ok(i) => executor
                     .stack
                     .push(Type::String(i.text().unwrap_or("".to_string()))),
                 Err(e) => {
-                    executor.log_print(format!("Error! {e}\n"));
-                    executor.stack.push(Type::Error("request".to_string()))
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("request"))
+                }
+            }
+        }
+
+        // Send the http request, and get the status, the final url and the body
+        #[cfg(feature = "network")]
+        "request-response" => {
+            let url = executor.pop_stack().get_string();
+            match executor.request.get(&url) {
+                Ok(response) => {
+                    let mut object = IndexMap::new();
+                    object.insert(
+                        "status".to_string(),
+                        Type::Number(response.status().as_u16() as f64),
+                    );
+                    object.insert("url".to_string(), Type::String(response.url().to_string()));
+                    object.insert(
+                        "body".to_string(),
+                        Type::String(response.text().unwrap_or("".to_string())),
+                    );
+                    executor
+                        .stack
+                        .push(Type::Object("response".to_string(), object));
+                }
+                Err(e) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("request-response"))
+                }
+            }
+        }
+
+        // Set the setting of the http request
+        #[cfg(feature = "network")]
+        "request-config" => {
+            let name = executor.pop_stack().get_string();
+            let value = executor.pop_stack().get_string();
+            if !executor.request.set(&name, value.clone()) {
+                executor.log_error(format!("invalid request setting \"{name}\" = \"{value}\""));
+                executor.stack.push(Type::error("request-config"));
+            }
+        }
+
+        // Send the bearer token with the following http requests
+        #[cfg(feature = "network")]
+        "http-auth-bearer" => {
+            let token = executor.pop_stack().get_string();
+            // The empty token stops sending it
+            executor.request.bearer = if token.is_empty() { None } else { Some(token) };
+        }
+
+        // Get the access token by the OAuth2 client credentials
+        #[cfg(feature = "network")]
+        "oauth-token" => {
+            let secret = executor.pop_stack().get_string();
+            let id = executor.pop_stack().get_string();
+            let endpoint = executor.pop_stack().get_string();
+            match executor.request.oauth_token(&endpoint, &id, &secret) {
+                Ok(token) => executor.stack.push(Type::String(token)),
+                Err(e) => {
+                    executor.log_internal_error(e);
+                    executor.stack.push(Type::error("oauth-token"))
+                }
+            }
+        }
+
+        // Send the GraphQL query with the variables, and get the data
+        #[cfg(feature = "network")]
+        "graphql" => {
+            let (_, variables) = executor.pop_stack().get_object();
+            let query = executor.pop_stack().get_string();
+            let endpoint = executor.pop_stack().get_string();
+
+            let body = serde_json::json!({
+                "query": query,
+                "variables": json::properties_to_json(&variables),
+            });
+            let response = executor
+                .request
+                .post_json(&endpoint, &body)
+                .and_then(|response| response.text())
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    serde_json::from_str::<serde_json::Value>(&text).map_err(|e| e.to_string())
+                });
+
+            match response {
+                Ok(response) => match response.get("errors") {
+                    Some(serde_json::Value::Array(errors)) if !errors.is_empty() => {
+                        for error in errors {
+                            let message = error.get("message").and_then(|x| x.as_str());
+                            executor.log_error(message.unwrap_or("unknown error").to_string());
+                        }
+                        executor.stack.push(Type::error("graphql"))
+                    }
+                    _ => executor.stack.push(json::from_json(
+                        response.get("data").unwrap_or(&serde_json::Value::Null),
+                    )),
+                },
+                Err(e) => {
+                    executor.log_internal_error(e);
+                    executor.stack.push(Type::error("graphql"))
                 }
             }
         }
@@ -897,8 +2432,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "open" => {
             let name = executor.pop_stack().get_string();
             if let Err(e) = opener::open(name.clone()) {
-                executor.log_print(format!("Error! {e}\n"));
-                executor.stack.push(Type::Error("open".to_string()));
+                executor.log_internal_error(e.to_string());
+                executor.stack.push(Type::error("open"));
             } else {
                 executor.stack.push(Type::String(name))
             }
@@ -908,8 +2443,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "cd" => {
             let name = executor.pop_stack().get_string();
             if let Err(err) = std::env::set_current_dir(name.clone()) {
-                executor.log_print(format!("Error! {}\n", err));
-                executor.stack.push(Type::Error("cd".to_string()));
+                executor.log_internal_error(err.to_string());
+                executor.stack.push(Type::error("cd"));
             } else {
                 executor.stack.push(Type::String(name))
             }
@@ -928,8 +2463,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "mkdir" => {
             let name = executor.pop_stack().get_string();
             if let Err(e) = fs::create_dir(name.clone()) {
-                executor.log_print(format!("Error! {e}\n"));
-                executor.stack.push(Type::Error("mkdir".to_string()));
+                executor.log_internal_error(e.to_string());
+                executor.stack.push(Type::error("mkdir"));
             } else {
                 executor.stack.push(Type::String(name))
             }
@@ -938,16 +2473,18 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         // Remove item
         "rm" => {
             let name = executor.pop_stack().get_string();
-            if Path::new(name.as_str()).is_dir() {
+            if executor.dry_run(format!("rm {name}")) {
+                executor.stack.push(Type::String(name))
+            } else if Path::new(name.as_str()).is_dir() {
                 if let Err(e) = fs::remove_dir(name.clone()) {
-                    executor.log_print(format!("Error! {e}\n"));
-                    executor.stack.push(Type::Error("rm".to_string()));
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("rm"));
                 } else {
                     executor.stack.push(Type::String(name))
                 }
             } else if let Err(e) = fs::remove_file(name.clone()) {
-                executor.log_print(format!("Error! {e}\n"));
-                executor.stack.push(Type::Error("rm".to_string()));
+                executor.log_internal_error(e.to_string());
+                executor.stack.push(Type::error("rm"));
             } else {
                 executor.stack.push(Type::String(name))
             }
@@ -957,9 +2494,11 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "rename" => {
             let to = executor.pop_stack().get_string();
             let from = executor.pop_stack().get_string();
-            if let Err(e) = fs::rename(from, to.clone()) {
-                executor.log_print(format!("Error! {e}\n"));
-                executor.stack.push(Type::Error("rename".to_string()));
+            if executor.dry_run(format!("rename {from} {to}")) {
+                executor.stack.push(Type::String(to))
+            } else if let Err(e) = fs::rename(from, to.clone()) {
+                executor.log_internal_error(e.to_string());
+                executor.stack.push(Type::error("rename"));
             } else {
                 executor.stack.push(Type::String(to))
             }
@@ -969,12 +2508,17 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "cp" => {
             let to = executor.pop_stack().get_string();
             let from = executor.pop_stack().get_string();
+            if executor.dry_run(format!("cp {from} {to}")) {
+                let size = fs::metadata(&from).map_or(0, |x| x.len());
+                executor.stack.push(Type::Number(size as f64));
+                return Ok(());
+            }
 
             match fs::copy(from, to) {
                 Ok(i) => executor.stack.push(Type::Number(i as f64)),
                 Err(e) => {
-                    executor.log_print(format!("Error! {e}\n"));
-                    executor.stack.push(Type::Error("cp".to_string()))
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("cp"))
                 }
             }
         }
@@ -983,8 +2527,8 @@ pub fn execute_command(executor: &mut Executor, command: String) {
         "size-file" => match fs::metadata(executor.pop_stack().get_string()) {
             Ok(i) => executor.stack.push(Type::Number(i.len() as f64)),
             Err(e) => {
-                executor.log_print(format!("Error! {e}\n"));
-                executor.stack.push(Type::Error("size-file".to_string()))
+                executor.log_internal_error(e.to_string());
+                executor.stack.push(Type::error("size-file"))
             }
         },
 
@@ -1010,7 +2554,48 @@ pub fn execute_command(executor: &mut Executor, command: String) {
             executor.stack.push(Type::Bool(path.is_dir()));
         }
 
+        // Find the groups of the duplicate files under the folder
+        "dedupe-scan" => {
+            let dir = executor.pop_stack().get_string();
+            match files::duplicates(Path::new(&dir)) {
+                Ok(groups) => {
+                    let groups = groups
+                        .into_iter()
+                        .map(|paths| {
+                            let paths = paths.iter().map(|path| path.to_string_lossy());
+                            Type::List(paths.map(|x| Type::String(x.to_string())).collect())
+                        })
+                        .collect();
+                    executor.stack.push(Type::List(groups));
+                }
+                Err(e) => {
+                    executor.log_internal_error(format!("{dir}: {e}"));
+                    executor.stack.push(Type::error("dedupe-scan"));
+                }
+            }
+        }
+
+        // Mirror the files of the folder into the other, and get the list of the changes
+        "sync-dirs" => {
+            let (_, options) = executor.pop_stack().get_object();
+            let dst = executor.pop_stack().get_string();
+            let src = executor.pop_stack().get_string();
+            let option = |name: &str| options.get(name).is_some_and(|x| x.clone().get_bool());
+            let dry_run = option("dry-run") || executor.dry_run(format!("sync-dirs {src} {dst}"));
+            match files::sync(Path::new(&src), Path::new(&dst), option("delete"), dry_run) {
+                Ok(changes) => {
+                    let changes = changes.into_iter().map(Type::String).collect();
+                    executor.stack.push(Type::List(changes));
+                }
+                Err(e) => {
+                    executor.log_internal_error(format!("{src}: {e}"));
+                    executor.stack.push(Type::error("sync-dirs"));
+                }
+            }
+        }
+
         // Get system information
+        #[cfg(feature = "sysinfo")]
         "sys-info" => {
             let option = executor.pop_stack().get_string();
             executor.stack.push(match option.as_str() {
@@ -1021,60 +2606,630 @@ pub fn execute_command(executor: &mut Executor, command: String) {
                 "host-name" => Type::String(hostname().unwrap_or("".to_string())),
                 "mem-size" => match mem_info() {
                     Ok(info) => Type::Number(info.total as f64),
-                    Err(_) => Type::Error("sys-info".to_string()),
+                    Err(_) => Type::error("sys-info"),
                 },
                 "mem-used" => match mem_info() {
                     Ok(info) => Type::Number((info.total - info.free) as f64),
-                    Err(_) => Type::Error("sys-info".to_string()),
+                    Err(_) => Type::error("sys-info"),
                 },
-                _ => Type::Error("sys-info".to_string()),
+                _ => Type::error("sys-info"),
             })
         }
 
         // Set value in the clipboard
+        #[cfg(feature = "clipboard")]
         "set-clipboard" => {
             let mut ctx: ClipboardContext;
             if let Ok(i) = ClipboardProvider::new() {
                 ctx = i
             } else {
-                executor
-                    .stack
-                    .push(Type::Error("set-clipboard".to_string()));
-                return;
+                executor.stack.push(Type::error("set-clipboard"));
+                return Ok(());
             };
 
             let value = executor.pop_stack().get_string();
             if ctx.set_contents(value.clone()).is_ok() {
                 executor.stack.push(Type::String(value));
             } else {
-                executor
-                    .stack
-                    .push(Type::Error("set-clipboard".to_string()))
+                executor.stack.push(Type::error("set-clipboard"))
             };
         }
 
         // Get value in the clipboard
+        #[cfg(feature = "clipboard")]
         "get-clipboard" => {
             let mut ctx: ClipboardContext;
             if let Ok(i) = ClipboardProvider::new() {
                 ctx = i
             } else {
-                executor
-                    .stack
-                    .push(Type::Error("get-clipboard".to_string()));
-                return;
+                executor.stack.push(Type::error("get-clipboard"));
+                return Ok(());
             };
 
             if let Ok(contents) = ctx.get_contents() {
                 executor.stack.push(Type::String(contents));
             } else {
-                executor
-                    .stack
-                    .push(Type::Error("get-clipboard".to_string()))
+                executor.stack.push(Type::error("get-clipboard"))
+            }
+        }
+
+        // Wait for the other process to connect by IPC
+        "ipc-listen" => {
+            let name = executor.pop_stack().get_string();
+            match ipc::listen(&name) {
+                Ok(connection) => {
+                    executor.add_connection(name.clone(), connection);
+                    executor.stack.push(Type::String(name))
+                }
+                Err(e) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("ipc-listen"))
+                }
+            }
+        }
+
+        // Connect to the other process by IPC
+        "ipc-connect" => {
+            let name = executor.pop_stack().get_string();
+            match ipc::connect(&name) {
+                Ok(connection) => {
+                    executor.add_connection(name.clone(), connection);
+                    executor.stack.push(Type::String(name))
+                }
+                Err(e) => {
+                    executor.log_internal_error(e.to_string());
+                    executor.stack.push(Type::error("ipc-connect"))
+                }
+            }
+        }
+
+        // Send the message by IPC
+        "ipc-send" => {
+            let name = executor.pop_stack().get_string();
+            let message = executor.pop_stack().get_string();
+            let result = match executor.connection(&name) {
+                Some(connection) => match connection.lock() {
+                    Ok(mut connection) => {
+                        ipc::send(&mut connection, &message).map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(e.to_string()),
+                },
+                None => Err(format!("connection \"{name}\" is not found")),
+            };
+            if let Err(e) = result {
+                executor.log_error(e);
+                executor.stack.push(Type::error("ipc-send"))
+            }
+        }
+
+        // Receive the message by IPC
+        "ipc-recv" => {
+            let name = executor.pop_stack().get_string();
+            let result = match executor.connection(&name) {
+                Some(connection) => match connection.lock() {
+                    Ok(mut connection) => ipc::recv(&mut connection).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                },
+                None => Err(format!("connection \"{name}\" is not found")),
+            };
+            match result {
+                Ok(message) => executor.stack.push(Type::String(message)),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("ipc-recv"))
+                }
+            }
+        }
+
+        // Load the commands from the plugin library
+        "load-plugin" => {
+            let path = executor.pop_stack().get_string();
+            if let Err(e) = executor.load_plugin(Path::new(&path)) {
+                executor.log_internal_error(e);
+                executor.stack.push(Type::error("load-plugin"));
+            }
+        }
+
+        // Evaluate the Python expression
+        #[cfg(feature = "python")]
+        "py-eval" => {
+            let expr = executor.pop_stack().get_string();
+            match python::eval(&expr) {
+                Ok(value) => executor.stack.push(value),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("py-eval"));
+                }
+            }
+        }
+
+        // Call the Python function with the list of arguments
+        #[cfg(feature = "python")]
+        "py-call" => {
+            let name = executor.pop_stack().get_string();
+            let args = executor.pop_stack().get_list();
+            match python::call(&name, &args) {
+                Ok(value) => executor.stack.push(value),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("py-call"));
+                }
+            }
+        }
+
+        // Load the shared library for the foreign functions
+        #[cfg(feature = "ffi")]
+        "ffi-load" => {
+            let path = executor.pop_stack().get_string();
+            match ffi::load(Path::new(&path)) {
+                Ok(library) => executor.libraries.push(library),
+                Err(e) => {
+                    executor.log_internal_error(e);
+                    executor.stack.push(Type::error("ffi-load"));
+                }
+            }
+        }
+
+        // Call the foreign function by the signature like `f64(f64, str)`
+        #[cfg(feature = "ffi")]
+        "ffi-call" => {
+            let args = executor.pop_stack().get_list();
+            let signature = executor.pop_stack().get_string();
+            let symbol = executor.pop_stack().get_string();
+            match ffi::call(&executor.libraries, &symbol, &signature, args) {
+                Ok(value) => executor.stack.push(value),
+                Err(e) => {
+                    executor.log_error(e);
+                    executor.stack.push(Type::error("ffi-call"));
+                }
+            }
+        }
+
+        // Commands left out of this build by the cargo features
+        #[cfg(not(feature = "audio"))]
+        "play-sound" | "play-file" => unavailable(executor, command),
+        #[cfg(not(feature = "network"))]
+        "request" | "request-response" | "request-config" | "http-auth-bearer" | "oauth-token"
+        | "graphql" => unavailable(executor, command),
+        #[cfg(not(feature = "sysinfo"))]
+        "sys-info" => unavailable(executor, command),
+        #[cfg(not(feature = "clipboard"))]
+        "set-clipboard" | "get-clipboard" => unavailable(executor, command),
+        #[cfg(not(feature = "python"))]
+        "py-eval" | "py-call" => unavailable(executor, command),
+        #[cfg(not(feature = "ffi"))]
+        "ffi-load" | "ffi-call" => unavailable(executor, command),
+
+        // Commands loaded from the plugins
+        name if executor.plugins.contains_key(name) => {
+            let plugin = executor.plugins[name].clone();
+            let mut args: Vec<Type> = (0..plugin.arity).map(|_| executor.pop_stack()).collect();
+            args.reverse();
+            match plugin.call(args) {
+                Ok(value) => executor.stack.push(value),
+                Err(e) => {
+                    executor.log_internal_error(e);
+                    executor.stack.push(Type::error(name));
+                }
             }
         }
 
         // If it is not recognized as a command, use it as a string.
-        _ => executor.stack.push(Type::String(command)),
+        _ => executor.stack.push(Type::String(command.to_string())),
+    }
+    Ok(())
+}
+
+/// Make the unified diff of the texts by lines, with the names in the header
+fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_name, new_name)
+        .to_string()
+}
+
+/// Get the seconds of the duration, given as number or ISO 8601 string
+fn duration_of(value: &Type) -> Option<f64> {
+    match value {
+        Type::Number(seconds) => Some(*seconds),
+        Type::String(text) => datetime::parse_duration(text),
+        _ => None,
+    }
+}
+
+/// Report the value which can't be read as time
+fn not_time(executor: &mut Executor, value: &str, command: &str) {
+    executor.log_error(format!("\"{value}\" is not a time"));
+    executor.stack.push(Type::error(command));
+}
+
+/// Report the value which can't be read as duration
+fn not_duration(executor: &mut Executor, value: &str, command: &str) {
+    executor.log_error(format!("\"{value}\" is not a duration"));
+    executor.stack.push(Type::error(command));
+}
+
+/// Use the random generator, which is seeded in the deterministic mode
+fn with_rng<T>(executor: &mut Executor, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    match &mut executor.deterministic {
+        Some(determinism) => f(&mut determinism.rng),
+        None => f(&mut rand::thread_rng()),
+    }
+}
+
+/// Get the current time, which is frozen in the deterministic mode
+fn now_utc(executor: &Executor) -> DateTime<Utc> {
+    match &executor.deterministic {
+        Some(determinism) => {
+            let millis = (determinism.now * 1000.0) as i64;
+            DateTime::from_timestamp_millis(millis).unwrap_or_default()
+        }
+        None => Utc::now(),
+    }
+}
+
+/// Convert the seconds into the duration which can be added to the current time
+pub(crate) fn to_duration(seconds: f64) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| Instant::now().checked_add(*duration).is_some())
+        .ok_or(format!("the duration {seconds} seconds is out of range"))
+}
+
+/// Report that the command is disabled by the cargo features
+#[cfg(not(all(
+    feature = "audio",
+    feature = "clipboard",
+    feature = "ffi",
+    feature = "network",
+    feature = "python",
+    feature = "sysinfo"
+)))]
+fn unavailable(executor: &mut Executor, command: &str) {
+    executor.log_error(format!(
+        "command \"{command}\" is not available in this build"
+    ));
+    executor.stack.push(Type::error("unavailable"));
+}
+
+/// Report that the value isn't error
+fn not_error(executor: &mut Executor) {
+    executor.log_error("the value is not error".to_string());
+    executor.stack.push(Type::error("not-error"));
+}
+
+/// Apply the operation to the numbers of the lists pairwise, broadcasting the number to the list
+fn elementwise(a: Type, b: Type, op: impl Fn(f64, f64) -> f64) -> Result<Type, String> {
+    let numbers = |mut value: Type| match value {
+        Type::List(_) | Type::PList(_) => Ok(value
+            .get_list()
+            .iter_mut()
+            .map(Type::get_number)
+            .collect::<Vec<f64>>()),
+        _ => Err(value.get_number()),
+    };
+    let result: Vec<f64> = match (numbers(a), numbers(b)) {
+        (Ok(a), Ok(b)) if a.len() != b.len() => {
+            return Err(format!(
+                "the lengths of the lists {} and {} differ",
+                a.len(),
+                b.len()
+            ))
+        }
+        (Ok(a), Ok(b)) => a.iter().zip(&b).map(|(a, b)| op(*a, *b)).collect(),
+        (Ok(a), Err(b)) => a.iter().map(|a| op(*a, b)).collect(),
+        (Err(a), Ok(b)) => b.iter().map(|b| op(a, *b)).collect(),
+        (Err(a), Err(b)) => return Ok(Type::Number(op(a, b))),
+    };
+    Ok(Type::List(result.into_iter().map(Type::Number).collect()))
+}
+
+/// Get the name of data type
+fn type_name(value: &Type) -> String {
+    match value {
+        Type::Number(_) => "number".to_string(),
+        Type::String(_) => "string".to_string(),
+        Type::Bool(_) => "bool".to_string(),
+        Type::List(_) => "list".to_string(),
+        Type::PList(_) => "plist".to_string(),
+        Type::Error(_) => "error".to_string(),
+        Type::Object(name, _) => format!("object:{name}"),
+        Type::Function(..) => "function".to_string(),
+    }
+}
+
+/// Evaluate the file like `lib/math.stk` in the namespace `math`, unless it is imported
+///
+/// The `.stk` extension may be omitted, and the values left on the stack are dropped.
+fn import(executor: &mut Executor, name: &str) -> Result<(), StackError> {
+    let mut path = PathBuf::from(name);
+    if path.extension().is_none() {
+        path.set_extension("stk");
+    }
+    // The same file by the other path is the same module
+    let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    if executor.modules.contains(&key) {
+        return Ok(());
+    }
+    let code = match fs::read_to_string(&path) {
+        Ok(code) => code,
+        Err(e) => {
+            executor.log_internal_error(e.to_string());
+            executor.stack.push(Type::error("import"));
+            return Ok(());
+        }
+    };
+    let namespace = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    // Marked before the evaluation, so that the circular imports stop
+    executor.modules.insert(key);
+    let depth = executor.stack.len();
+    executor.namespaces.push(namespace.to_string());
+    let result = executor.evaluate_program(code);
+    executor.namespaces.pop();
+    executor.stack.truncate(depth);
+    result
+}
+
+/// Evaluate code as function, which is left by the propagated error
+fn call(executor: &mut Executor, code: String) -> Result<(), StackError> {
+    match executor.evaluate_program(code) {
+        Err(StackError::Propagate) => Ok(()),
+        result => result,
+    }
+}
+
+/// Evaluate the function body in a new local scope, where the parameters are bound
+///
+/// The scope is discarded after the call, so the caller's variables are left as they were.
+fn call_function(
+    executor: &mut Executor,
+    params: Vec<String>,
+    body: String,
+) -> Result<(), StackError> {
+    let mut scope = HashMap::new();
+    // The last parameter is on the top of the stack
+    for name in params.into_iter().rev() {
+        let value = executor.pop_stack();
+        scope.insert(executor.intern(&name), value);
+    }
+    executor.locals.push(scope);
+    let result = call(executor, body);
+    executor.locals.pop();
+    result
+}
+
+/// Evaluate the body of loop, and judge is it continued
+fn iterate(executor: &mut Executor, code: String) -> Result<bool, StackError> {
+    match executor.evaluate_program(code) {
+        Ok(()) => Ok(true),
+        Err(StackError::Break) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Evaluate code in the child executor and push its stack as list
+fn isolate(executor: &mut Executor, code: String, vars: Vec<Type>) {
+    let mut child = executor.child();
+    for mut name in vars {
+        let name = name.get_string();
+        if let Some((name, value)) = executor.memory.get_key_value(name.as_str()) {
+            child.memory.insert(name.clone(), value.clone());
+        }
+    }
+
+    match child.evaluate_program(code) {
+        Ok(()) => executor.stack.push(Type::List(child.stack)),
+        Err(e) => {
+            executor.log_error(e.to_string());
+            executor.stack.push(Type::error("isolate"));
+        }
+    }
+}
+
+/// Evaluate code, and restore the state before it if the code resulted in error
+fn transact(executor: &mut Executor, code: String) -> Result<(), StackError> {
+    let stack = executor.stack.clone();
+    let memory = executor.memory.clone();
+    let locals = executor.locals.clone();
+    let count = |stack: &[Type]| stack.iter().filter(|x| matches!(x, Type::Error(_))).count();
+    let errors = count(&stack);
+
+    let result = executor.evaluate_program(code);
+    let failed = match &result {
+        Ok(()) => count(&executor.stack) > errors,
+        Err(StackError::Exit(_) | StackError::Break) => false,
+        Err(_) => true,
+    };
+    if failed {
+        // Leave the error of the code to tell what failed
+        let error = executor
+            .stack
+            .iter()
+            .rev()
+            .find(|x| matches!(x, Type::Error(_)))
+            .cloned();
+        executor.stack = stack;
+        executor.memory = memory;
+        executor.locals = locals;
+        executor.stack.extend(error);
     }
+    result
+}
+
+/// Judge is it generator
+fn is_generator(value: &Type) -> bool {
+    matches!(value, Type::Object(name, _) if name == "generator")
+}
+
+/// Run the generator's step code from its start, and get the updated generator and yielded value
+fn resume(executor: &mut Executor, generator: Type) -> Result<Option<(Type, Type)>, StackError> {
+    let (name, mut generator) = generator.get_object();
+    let body = generator
+        .get("body")
+        .map(|x| x.to_owned().get_string())
+        .unwrap_or_default();
+    let (_, state) = generator
+        .get("state")
+        .map(|x| x.get_object())
+        .unwrap_or_default();
+
+    let mut child = executor.child();
+    let memory = state
+        .into_iter()
+        .map(|(name, value)| (child.intern(&name), value))
+        .collect();
+    child.memory = memory;
+    child.evaluate_program(body)?;
+
+    // The generator is done, when the body didn't yield
+    let value = match child.yielded.take() {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    generator.insert("state".to_string(), state_object(child.memory));
+    Ok(Some((Type::Object(name, generator), value)))
+}
+
+/// Keep the variables of the generator as the object
+fn state_object(memory: IndexMap<Symbol, Type>) -> Type {
+    let properties = memory
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    Type::Object("state".to_string(), properties)
+}
+
+/// Find the index of the first item that satisfies the condition
+fn find_if(
+    executor: &mut Executor,
+    list: &[Type],
+    vars: String,
+    code: String,
+) -> Result<Option<usize>, StackError> {
+    for (index, x) in list.iter().enumerate() {
+        executor.bind(vars.clone(), x.clone());
+        if !iterate(executor, code.clone())? {
+            break;
+        }
+        if executor.pop_stack().get_bool() {
+            return Ok(Some(index));
+        }
+    }
+    Ok(None)
+}
+
+/// Compare the values in the total order across the types
+///
+/// Numbers < strings < bools < lists < objects < functions < errors. The values of the same
+/// type are compared by their contents, where lists are compared item by item, objects by the
+/// name and then the properties in order, functions by the code, and errors by the code.
+fn compare(a: &Type, b: &Type) -> Ordering {
+    let rank = |value: &Type| match value {
+        Type::Number(_) => 0,
+        Type::String(_) => 1,
+        Type::Bool(_) => 2,
+        Type::List(_) | Type::PList(_) => 3,
+        Type::Object(..) => 4,
+        Type::Function(..) => 5,
+        Type::Error(_) => 6,
+    };
+    match (a, b) {
+        (Type::Number(a), Type::Number(b)) => a.total_cmp(b),
+        (Type::String(a), Type::String(b)) => a.cmp(b),
+        (Type::Bool(a), Type::Bool(b)) => a.cmp(b),
+        (Type::List(a), Type::List(b)) => compare_items(a.iter(), b.iter()),
+        (Type::List(a), Type::PList(b)) => compare_items(a.iter(), b.iter()),
+        (Type::PList(a), Type::List(b)) => compare_items(a.iter(), b.iter()),
+        (Type::PList(a), Type::PList(b)) => compare_items(a.iter(), b.iter()),
+        (Type::Object(a_name, a), Type::Object(b_name, b)) => a_name.cmp(b_name).then_with(|| {
+            for ((a_key, a_value), (b_key, b_value)) in a.iter().zip(b) {
+                let order = a_key.cmp(b_key).then_with(|| compare(a_value, b_value));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            a.len().cmp(&b.len())
+        }),
+        (Type::Function(..), Type::Function(..)) => a.literal().cmp(&b.literal()),
+        (Type::Error(a), Type::Error(b)) => a.code.cmp(&b.code),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Compare the lists item by item, the shorter is smaller if one is the start of the other
+fn compare_items<'a>(
+    mut a: impl Iterator<Item = &'a Type>,
+    mut b: impl Iterator<Item = &'a Type>,
+) -> Ordering {
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) => match compare(x, y) {
+                Ordering::Equal => continue,
+                order => return order,
+            },
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+        }
+    }
+}
+
+/// Report that the list has no value to pick
+fn empty_list(executor: &mut Executor) {
+    executor.log_error("the list is empty".to_string());
+    executor.stack.push(Type::error("empty-list"));
+}
+
+/// Classify the token in the same order as the evaluation
+fn token_type(executor: &Executor, token: &str) -> &'static str {
+    let (first, last) = match (token.chars().next(), token.chars().last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return "command",
+    };
+    if token.parse::<f64>().is_ok() {
+        "number"
+    } else if token == "true" || token == "false" {
+        "bool"
+    } else if first == '(' && last == ')' {
+        "string"
+    } else if first == '[' && last == ']' {
+        "list"
+    } else if first == '{' && last == '}' {
+        "object"
+    } else if token.starts_with("error:") {
+        "error"
+    } else if executor.variable(token).is_some() || executor.property_path(token).is_some() {
+        "variable"
+    } else if first == '#' && last == '#' {
+        "comment"
+    } else {
+        "command"
+    }
+}
+
+/// Get the index of the string buffer by its handle
+fn buffer_id(executor: &Executor, handle: &Type) -> Option<usize> {
+    match handle {
+        Type::Object(name, object) if name == "sbuf" => {
+            let id = object.get("id")?.clone().get_number() as usize;
+            (id < executor.buffers.len()).then_some(id)
+        }
+        _ => None,
+    }
+}
+
+/// Report that the value isn't string buffer
+fn not_buffer(executor: &mut Executor) {
+    executor.log_error("the value is not a string buffer".to_string());
+    executor.stack.push(Type::error("sbuf"));
+}
+
+/// Report that the key-value store isn't opened
+fn kv_not_opened(executor: &mut Executor) {
+    executor.log_error("the key-value store is not opened".to_string());
+    executor.stack.push(Type::error("kv-store"));
 }
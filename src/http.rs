@@ -0,0 +1,108 @@
+use crate::functions::to_duration;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Settings of the HTTP requests
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub user_agent: String,        // Value of the User-Agent header
+    pub timeout: Option<Duration>, // Time limit of the whole request
+    pub redirects: usize,          // Maximum number of the redirects to follow
+    pub bearer: Option<String>,    // Token sent in the Authorization header
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            user_agent: format!("stack/{}", env!("CARGO_PKG_VERSION")),
+            timeout: Some(Duration::from_secs(30)),
+            redirects: 10,
+            bearer: None,
+        }
+    }
+}
+
+impl Config {
+    /// Set the setting by the name, false if the name or the value is wrong
+    pub fn set(&mut self, name: &str, value: String) -> bool {
+        match name {
+            "user-agent" => self.user_agent = value,
+            // Zero seconds means no time limit
+            "timeout" => match value.parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => match to_duration(seconds) {
+                    Ok(timeout) => self.timeout = Some(timeout),
+                    Err(_) => return false,
+                },
+                Ok(_) => self.timeout = None,
+                Err(_) => return false,
+            },
+            "redirects" => match value.parse() {
+                Ok(redirects) => self.redirects = redirects,
+                Err(_) => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Build the client with the settings
+    pub fn client(&self) -> reqwest::Result<Client> {
+        let redirect = match self.redirects {
+            0 => Policy::none(),
+            n => Policy::limited(n),
+        };
+        Client::builder()
+            .user_agent(self.user_agent.clone())
+            .timeout(self.timeout)
+            .redirect(redirect)
+            .build()
+    }
+
+    /// Send the GET request, the compressed response is decoded
+    pub fn get(&self, url: &str) -> reqwest::Result<Response> {
+        self.send(self.client()?.get(url))
+    }
+
+    /// Send the POST request with the JSON body
+    pub fn post_json(&self, url: &str, body: &Value) -> reqwest::Result<Response> {
+        let request = self
+            .client()?
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        self.send(request)
+    }
+
+    /// Send the request with the authorization
+    fn send(&self, mut request: RequestBuilder) -> reqwest::Result<Response> {
+        if let Some(token) = &self.bearer {
+            request = request.bearer_auth(token);
+        }
+        request.send()
+    }
+
+    /// Get the access token by the OAuth2 client credentials grant
+    pub fn oauth_token(&self, endpoint: &str, id: &str, secret: &str) -> Result<String, String> {
+        let response = self
+            .client()
+            .and_then(|client| {
+                client
+                    .post(endpoint)
+                    .basic_auth(id, Some(secret))
+                    .form(&[("grant_type", "client_credentials")])
+                    .send()
+            })
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| e.to_string())?;
+
+        let body: Value = serde_json::from_str(&response).map_err(|e| e.to_string())?;
+        match body.get("access_token") {
+            Some(Value::String(token)) => Ok(token.to_string()),
+            _ => Err("the response has no access token".to_string()),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Language of the interpreter messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lang {
+    English,
+    Japanese,
+}
+
+static LANG: AtomicU8 = AtomicU8::new(0);
+
+impl Lang {
+    /// Parse from the command-line value or the environment variable
+    pub fn parse(value: &str) -> Option<Lang> {
+        match value.to_lowercase().as_str() {
+            "en" | "english" => Some(Lang::English),
+            "ja" | "jp" | "japanese" => Some(Lang::Japanese),
+            _ => None,
+        }
+    }
+}
+
+/// Set the language of all messages
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+/// Get the language of the messages
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::Japanese,
+        _ => Lang::English,
+    }
+}
+
+/// Messages in English and their translations, `{}` is the embedded value
+const JAPANESE: &[(&str, &str)] = &[
+    ("Error!", "エラー!"),
+    ("Internal error!", "内部エラー!"),
+    ("Warning!", "警告!"),
+    ("at `{}` (token {})", "(`{}` トークン {})"),
+    ("{} at `{}` (token {})", "{} (`{}` トークン {})"),
+    (
+        "There are not enough values on the stack. returns default value",
+        "スタックの値が足りません。既定値を返します",
+    ),
+    (
+        "There are not enough values on the stack",
+        "スタックの値が足りません",
+    ),
+    (
+        "Index specification is out of range",
+        "インデックスが範囲外です",
+    ),
+    ("item not found in the list", "リストに要素が見つかりません"),
+    ("failed of number decoding", "数値のデコードに失敗しました"),
+    (
+        "failed of string encoding",
+        "文字列のエンコードに失敗しました",
+    ),
+    ("initial data is shortage", "初期データが足りません"),
+    ("Failed to clear screen", "画面のクリアに失敗しました"),
+    (
+        "the class data structure is wrong",
+        "クラスのデータ構造が不正です",
+    ),
+    ("the type name is not found", "型名が見つかりません"),
+    (
+        "the value is not a generator",
+        "値がジェネレーターではありません",
+    ),
+    ("the value is not error", "値がエラーではありません"),
+    (
+        "the key-value store is not opened",
+        "キーバリューストアが開かれていません",
+    ),
+    (
+        "call-original is used outside of the wrapper",
+        "call-original がラッパーの外で使われました",
+    ),
+    ("unknown error", "不明なエラー"),
+    ("\"{}\" is not a number", "\"{}\" は数値ではありません"),
+    ("assertion failed: {}", "アサーションに失敗しました: {}"),
+    ("expected {} but got {}", "{} を期待しましたが {} でした"),
+    (
+        "the variable `{}` is not defined",
+        "変数 `{}` は定義されていません",
+    ),
+    (
+        "the variable `{}` is already defined, use `set!` to reassign it",
+        "変数 `{}` は定義済みです。再代入には `set!` を使ってください",
+    ),
+    ("no value to bind to `{}`", "`{}` に束縛する値がありません"),
+    (
+        "the object has no property `{}`",
+        "オブジェクトにプロパティ `{}` がありません",
+    ),
+    (
+        "invalid shuffle pattern \"{}\"",
+        "不正なシャッフルパターン \"{}\"",
+    ),
+    ("unknown option \"{}\"", "不明なオプション \"{}\""),
+    ("unknown command \"{}\"", "不明なコマンド \"{}\""),
+    (
+        "no documentation of \"{}\"",
+        "\"{}\" のドキュメントがありません",
+    ),
+    (
+        "the key `{}` is not found in the store",
+        "キー `{}` はストアにありません",
+    ),
+    (
+        "invalid request setting \"{}\" = \"{}\"",
+        "不正なリクエスト設定 \"{}\" = \"{}\"",
+    ),
+    (
+        "connection \"{}\" is not found",
+        "接続 \"{}\" が見つかりません",
+    ),
+    ("unknown example \"{}\"", "不明なサンプル \"{}\""),
+    ("the command panicked: {}", "コマンドがパニックしました: {}"),
+    (
+        "the repeated string is too long",
+        "繰り返した文字列が長すぎます",
+    ),
+    (
+        "the duration {} seconds is out of range",
+        "{} 秒は範囲外の長さです",
+    ),
+    (
+        "command \"{}\" is not available in this build",
+        "コマンド \"{}\" はこのビルドでは使えません",
+    ),
+    (
+        "the symbol `{}` is not found",
+        "シンボル `{}` が見つかりません",
+    ),
+    (
+        "the signature takes {} arguments but got {}",
+        "シグネチャの引数は {} 個ですが {} 個が渡されました",
+    ),
+    ("unknown C type \"{}\"", "不明な C の型 \"{}\""),
+    ("invalid signature \"{}\"", "不正なシグネチャ \"{}\""),
+    (
+        "the object literal needs pairs of key and value",
+        "オブジェクトリテラルにはキーと値の組が必要です",
+    ),
+    ("unknown time zone \"{}\"", "不明なタイムゾーン \"{}\""),
+    (
+        "the stopwatch `{}` is not started",
+        "ストップウォッチ `{}` は開始されていません",
+    ),
+    ("unexpected `{}`", "予期しない `{}`"),
+    ("`{}` doesn't match `{}`", "`{}` は `{}` と対応していません"),
+    ("the string is not closed", "文字列が閉じられていません"),
+    ("the list is not closed", "リストが閉じられていません"),
+    (
+        "the object is not closed",
+        "オブジェクトが閉じられていません",
+    ),
+    ("the comment is not closed", "コメントが閉じられていません"),
+    (
+        "unknown hook timing \"{}\"",
+        "不明なフックのタイミング \"{}\"",
+    ),
+    ("nothing to undo", "取り消す評価がありません"),
+    ("unknown REPL command \"{}\"", "不明な REPL コマンド \"{}\""),
+    (
+        "the value is not a string buffer",
+        "値が文字列バッファではありません",
+    ),
+    ("the list is empty", "リストが空です"),
+    ("\"{}\" is not a character", "\"{}\" は文字ではありません"),
+    (
+        "the macro `{}` is expanded too deeply",
+        "マクロ `{}` の展開が深すぎます",
+    ),
+    (
+        "Run `{}` on \"{}\"? [y/N] ",
+        "`{}` を \"{}\" に実行しますか? [y/N] ",
+    ),
+    (
+        "`{}` is cancelled by the user",
+        "`{}` はユーザーにより取り消されました",
+    ),
+    (
+        "`{}` needs --allow-{} for \"{}\"",
+        "`{}` には --allow-{} が必要です (\"{}\")",
+    ),
+    ("`{}` needs --allow-{}", "`{}` には --allow-{} が必要です"),
+    (
+        "no command matches \"{}\"",
+        "\"{}\" に一致するコマンドがありません",
+    ),
+    (
+        "`{}` is not allowed in the deterministic mode",
+        "`{}` は決定的モードでは使えません",
+    ),
+    (
+        "no recorded result of `{}` in the cassette",
+        "カセットに `{}` の記録された結果がありません",
+    ),
+    ("\"{}\" is not a time", "\"{}\" は時刻ではありません"),
+    ("\"{}\" is not a duration", "\"{}\" は期間ではありません"),
+    ("invalid time format \"{}\"", "不正な時刻の書式 \"{}\""),
+    ("the value is not a function", "値が関数ではありません"),
+    ("invalid step {} of range", "範囲の不正なステップ {}"),
+    (
+        "the lengths of the lists {} and {} differ",
+        "リストの長さ {} と {} が異なります",
+    ),
+    ("exit with status {}", "ステータス {} で終了"),
+    ("break outside of loop", "ループの外で break が使われました"),
+    (
+        "error is propagated to the top level",
+        "エラーがトップレベルまで伝播しました",
+    ),
+    (
+        "error is raised outside of try",
+        "try の外でエラーが発生しました",
+    ),
+];
+
+/// Translate the message into the current language
+pub fn translate(message: &str) -> String {
+    translate_into(lang(), message)
+}
+
+/// Translate the message into the language, unknown messages are kept as is
+pub fn translate_into(lang: Lang, message: &str) -> String {
+    let catalog = match lang {
+        Lang::English => return message.to_string(),
+        Lang::Japanese => JAPANESE,
+    };
+    for (english, translated) in catalog {
+        if let Some(values) = capture(english, message) {
+            // The embedded values may be messages too
+            let mut result = String::new();
+            let mut values = values.into_iter();
+            for (i, part) in translated.split("{}").enumerate() {
+                if i > 0 {
+                    result += &values
+                        .next()
+                        .map(|value| translate_into(lang, value))
+                        .unwrap_or_default();
+                }
+                result += part;
+            }
+            return result;
+        }
+    }
+    message.to_string()
+}
+
+/// Get the embedded values if the message matches the template
+fn capture<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let parts: Vec<&str> = template.split("{}").collect();
+    let mut rest = message.strip_prefix(parts[0])?;
+    let mut values = Vec::new();
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        let end = if i == parts.len() - 1 {
+            // The last part must be at the end
+            rest.strip_suffix(part).map(str::len)?
+        } else {
+            rest.find(part)?
+        };
+        values.push(&rest[..end]);
+        rest = &rest[end + part.len()..];
+    }
+    if parts.len() == 1 && !rest.is_empty() {
+        return None;
+    }
+    Some(values)
+}
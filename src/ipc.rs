@@ -0,0 +1,39 @@
+use interprocess::local_socket::{prelude::*, GenericNamespaced, ListenerOptions, Stream};
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Connection between the processes, messages are separated by line
+pub type Connection = BufReader<Stream>;
+
+/// Wait for the other process to connect with the name
+pub fn listen(name: &str) -> io::Result<Connection> {
+    let listener = ListenerOptions::new()
+        .name(name.to_ns_name::<GenericNamespaced>()?)
+        .create_sync()?;
+    Ok(BufReader::new(listener.accept()?))
+}
+
+/// Connect to the process listening with the name
+pub fn connect(name: &str) -> io::Result<Connection> {
+    let stream = Stream::connect(name.to_ns_name::<GenericNamespaced>()?)?;
+    Ok(BufReader::new(stream))
+}
+
+/// Send a message
+pub fn send(connection: &mut Connection, message: &str) -> io::Result<()> {
+    let stream = connection.get_mut();
+    stream.write_all(message.replace('\n', "\\n").as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+/// Receive a message
+pub fn recv(connection: &mut Connection) -> io::Result<String> {
+    let mut line = String::new();
+    if connection.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection is closed",
+        ));
+    }
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
@@ -0,0 +1,96 @@
+use crate::Type;
+use indexmap::IndexMap;
+use serde_json::{Map, Number, Value};
+
+/// Type name of the object which JSON null is read as
+pub const NULL: &str = "null";
+
+/// Convert the value to JSON
+pub fn to_json(value: &Type) -> Value {
+    match value {
+        Type::Number(i) => Number::from_f64(*i).map_or(Value::Null, Value::Number),
+        Type::String(s) => Value::String(s.to_string()),
+        Type::Bool(b) => Value::Bool(*b),
+        Type::List(list) => Value::Array(list.iter().map(to_json).collect()),
+        Type::PList(list) => Value::Array(list.iter().map(to_json).collect()),
+        Type::Error(err) => {
+            let mut map = Map::new();
+            map.insert("error".to_string(), Value::String(err.code.to_string()));
+            Value::Object(map)
+        }
+        Type::Object(name, object) => {
+            let properties = object
+                .iter()
+                .map(|(key, value)| (key.to_string(), to_json(value)))
+                .collect();
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String(name.to_string()));
+            map.insert("properties".to_string(), Value::Object(properties));
+            Value::Object(map)
+        }
+        Type::Function(params, body) => {
+            let mut map = Map::new();
+            let params = params
+                .iter()
+                .map(|x| Value::String(x.to_string()))
+                .collect();
+            map.insert("params".to_string(), Value::Array(params));
+            map.insert("body".to_string(), Value::String(body.to_string()));
+            Value::Object(map)
+        }
+    }
+}
+
+/// Convert the value to JSON as the web APIs take it, where the objects are plain at any depth
+#[cfg(feature = "network")]
+pub fn to_plain_json(value: &Type) -> Value {
+    match value {
+        Type::List(list) => Value::Array(list.iter().map(to_plain_json).collect()),
+        Type::PList(list) => Value::Array(list.iter().map(to_plain_json).collect()),
+        Type::Object(name, object) if name == NULL && object.is_empty() => Value::Null,
+        Type::Object(_, object) => properties_to_json(object),
+        _ => to_json(value),
+    }
+}
+
+/// Convert the object's properties to the plain JSON object
+#[cfg(feature = "network")]
+pub fn properties_to_json(object: &IndexMap<String, Type>) -> Value {
+    Value::Object(
+        object
+            .iter()
+            .map(|(key, value)| (key.to_string(), to_plain_json(value)))
+            .collect(),
+    )
+}
+
+/// Convert the JSON to value
+pub fn from_json(value: &Value) -> Type {
+    match value {
+        // The null is the empty object of its own type, as NaN and infinity are written as it too
+        Value::Null => Type::Object(NULL.to_string(), IndexMap::new()),
+        Value::Bool(b) => Type::Bool(*b),
+        Value::Number(i) => Type::Number(i.as_f64().unwrap_or(f64::NAN)),
+        Value::String(s) => Type::String(s.to_string()),
+        Value::Array(list) => Type::List(list.iter().map(from_json).collect()),
+        Value::Object(map) => match (map.get("error"), map.get("type"), map.get("properties")) {
+            (Some(Value::String(err)), None, None) if map.len() == 1 => Type::error(err),
+            (None, Some(Value::String(name)), Some(Value::Object(object))) if map.len() == 2 => {
+                Type::Object(name.to_string(), properties(object))
+            }
+            _ => Type::Object("object".to_string(), properties(map)),
+        },
+    }
+}
+
+/// Convert the JSON object to properties
+fn properties(map: &Map<String, Value>) -> IndexMap<String, Type> {
+    map.iter()
+        .map(|(key, value)| (key.to_string(), from_json(value)))
+        .collect()
+}
+
+/// Convert the stack to JSON
+pub fn stack_to_json(stack: &[Type]) -> Value {
+    Value::Array(stack.iter().map(to_json).collect())
+}
@@ -0,0 +1,429 @@
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use stack::{check, protocol, registry, Executor, Type};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, SocketRecv, SocketSend, ZmqMessage};
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// Message of the Jupyter messaging protocol
+struct Message {
+    ids: Vec<Vec<u8>>, // Routing identities of the client
+    header: Value,
+    content: Value,
+}
+
+/// Kernel keeping the executor between the cells
+struct Kernel {
+    key: Vec<u8>,    // Key of the message signature, empty if not signed
+    session: String, // Session ID of the kernel
+    version: String, // Version of the interpreter
+    executor: Executor,
+    count: usize,     // Execution count shown on the cells
+    iopub: PubSocket, // Broadcast of the outputs and the status
+}
+
+/// Run the kernel with the connection file given by Jupyter
+pub fn run(connection: &Path, executor: Executor, version: &str) -> Result<(), String> {
+    let text = fs::read_to_string(connection).map_err(|e| e.to_string())?;
+    let config: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let endpoint = |port: &str| -> Result<String, String> {
+        let transport = config["transport"].as_str().unwrap_or("tcp");
+        let ip = config["ip"].as_str().unwrap_or("127.0.0.1");
+        match config[port].as_u64() {
+            Some(port) => Ok(format!("{transport}://{ip}:{port}")),
+            None => Err(format!("{port} is not in the connection file")),
+        }
+    };
+    let (shell, control) = (endpoint("shell_port")?, endpoint("control_port")?);
+    let (iopub, stdin, hb) = (
+        endpoint("iopub_port")?,
+        endpoint("stdin_port")?,
+        endpoint("hb_port")?,
+    );
+
+    // Answer the heartbeat even while the cell is running
+    thread::spawn(move || {
+        runtime()?.block_on(async {
+            let mut socket = RepSocket::new();
+            socket.bind(&hb).await.map_err(|e| e.to_string())?;
+            while let Ok(message) = socket.recv().await {
+                socket.send(message).await.map_err(|e| e.to_string())?;
+            }
+            Ok::<(), String>(())
+        })
+    });
+
+    runtime()?.block_on(async {
+        let mut shell_socket = RouterSocket::new();
+        let mut control_socket = RouterSocket::new();
+        let mut stdin_socket = RouterSocket::new();
+        let mut iopub_socket = PubSocket::new();
+        for (socket, endpoint) in [
+            (&mut shell_socket, &shell),
+            (&mut control_socket, &control),
+            (&mut stdin_socket, &stdin),
+        ] {
+            socket.bind(endpoint).await.map_err(|e| e.to_string())?;
+        }
+        iopub_socket.bind(&iopub).await.map_err(|e| e.to_string())?;
+
+        let mut kernel = Kernel {
+            key: config["key"]
+                .as_str()
+                .unwrap_or_default()
+                .as_bytes()
+                .to_vec(),
+            session: uuid::Uuid::new_v4().to_string(),
+            version: version.to_string(),
+            executor,
+            count: 0,
+            iopub: iopub_socket,
+        };
+        loop {
+            let (socket, message) = tokio::select! {
+                message = shell_socket.recv() => (&mut shell_socket, message),
+                message = control_socket.recv() => (&mut control_socket, message),
+            };
+            let message = match message
+                .map_err(|e| e.to_string())
+                .and_then(|x| kernel.parse(x))
+            {
+                Ok(message) => message,
+                Err(err) => {
+                    eprintln!("[Jupyter] {err}");
+                    continue;
+                }
+            };
+            if !kernel.handle(socket, message).await? {
+                return Ok(());
+            }
+        }
+    })
+}
+
+/// Make the runtime of the sockets
+fn runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Write the kernel spec, so that Jupyter can find the kernel
+pub fn install() -> Result<PathBuf, String> {
+    let program = env::current_exe().map_err(|e| e.to_string())?;
+    let folder = data_dir()
+        .ok_or("the data folder of Jupyter is not found")?
+        .join("kernels")
+        .join("stack");
+    let spec = json!({
+        "argv": [program, "jupyter-kernel", "{connection_file}"],
+        "display_name": "Stack",
+        "language": "stack",
+    });
+    fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+    let path = folder.join("kernel.json");
+    fs::write(&path, spec.to_string()).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Get the user's data folder of Jupyter
+fn data_dir() -> Option<PathBuf> {
+    if let Ok(path) = env::var("JUPYTER_DATA_DIR") {
+        return Some(PathBuf::from(path));
+    }
+    if cfg!(windows) {
+        return env::var("APPDATA")
+            .ok()
+            .map(|x| Path::new(&x).join("jupyter"));
+    }
+    let home = PathBuf::from(env::var("HOME").ok()?);
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library").join("Jupyter"))
+    } else {
+        Some(home.join(".local").join("share").join("jupyter"))
+    }
+}
+
+impl Kernel {
+    /// Split the frames into the message, checking the signature
+    fn parse(&self, message: ZmqMessage) -> Result<Message, String> {
+        let frames: Vec<Vec<u8>> = message.into_vec().iter().map(|x| x.to_vec()).collect();
+        let delimiter = frames
+            .iter()
+            .position(|x| x == DELIMITER)
+            .ok_or("the message has no delimiter")?;
+        let parts = frames
+            .get(delimiter + 2..delimiter + 6)
+            .ok_or("the message is short")?;
+        if !self.key.is_empty() && frames[delimiter + 1] != self.sign(parts).as_bytes() {
+            return Err("the signature of the message is wrong".to_string());
+        }
+        let decode = |x: &[u8]| serde_json::from_slice(x).map_err(|e| e.to_string());
+        Ok(Message {
+            ids: frames[..delimiter].to_vec(),
+            header: decode(&parts[0])?,
+            content: decode(&parts[3])?,
+        })
+    }
+
+    /// Get the HMAC signature of the message parts in hex
+    fn sign(&self, parts: &[Vec<u8>]) -> String {
+        let mut mac = match Hmac::<Sha256>::new_from_slice(&self.key) {
+            Ok(mac) => mac,
+            Err(_) => return String::new(),
+        };
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|x| format!("{x:02x}"))
+            .collect()
+    }
+
+    /// Encode the message replying to the parent into the frames
+    fn encode(&self, ids: &[Vec<u8>], parent: &Message, kind: &str, content: Value) -> ZmqMessage {
+        let header = json!({
+            "msg_id": uuid::Uuid::new_v4().to_string(),
+            "session": self.session,
+            "username": "stack",
+            "date": chrono::Utc::now().to_rfc3339(),
+            "msg_type": kind,
+            "version": PROTOCOL_VERSION,
+        });
+        let parts: Vec<Vec<u8>> = [header, parent.header.clone(), json!({}), content]
+            .iter()
+            .map(|x| x.to_string().into_bytes())
+            .collect();
+        let signature = if self.key.is_empty() {
+            String::new()
+        } else {
+            self.sign(&parts)
+        };
+
+        let mut message = ZmqMessage::from(DELIMITER.to_vec());
+        for id in ids.iter().rev() {
+            message.push_front(id.clone().into());
+        }
+        message.push_back(signature.into_bytes().into());
+        for part in parts {
+            message.push_back(part.into());
+        }
+        message
+    }
+
+    /// Reply to the client on the socket
+    async fn reply(
+        &self,
+        socket: &mut RouterSocket,
+        parent: &Message,
+        kind: &str,
+        content: Value,
+    ) -> Result<(), String> {
+        let message = self.encode(&parent.ids, parent, kind, content);
+        socket.send(message).await.map_err(|e| e.to_string())
+    }
+
+    /// Broadcast to every frontend
+    async fn publish(
+        &mut self,
+        parent: &Message,
+        kind: &str,
+        content: Value,
+    ) -> Result<(), String> {
+        let message = self.encode(&[kind.as_bytes().to_vec()], parent, kind, content);
+        self.iopub.send(message).await.map_err(|e| e.to_string())
+    }
+
+    /// Handle the request, false if the kernel should stop
+    async fn handle(
+        &mut self,
+        socket: &mut RouterSocket,
+        message: Message,
+    ) -> Result<bool, String> {
+        let kind = message.header["msg_type"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let reply_kind = kind.replace("_request", "_reply");
+        self.publish(&message, "status", json!({"execution_state": "busy"}))
+            .await?;
+
+        let mut running = true;
+        let content = match kind.as_str() {
+            "kernel_info_request" => json!({
+                "status": "ok",
+                "protocol_version": PROTOCOL_VERSION,
+                "implementation": "stack",
+                "implementation_version": self.version,
+                "language_info": {
+                    "name": "stack",
+                    "version": self.version,
+                    "mimetype": "text/x-stack",
+                    "file_extension": ".stk",
+                },
+                "banner": format!("Stack Programming Language\nVersion {}", self.version),
+                "help_links": [],
+            }),
+            "execute_request" => {
+                let (content, exited) = self.execute(&message).await?;
+                running = !exited;
+                content
+            }
+            "is_complete_request" => {
+                let code = message.content["code"].as_str().unwrap_or_default();
+                let problems = check::check(code);
+                let status = if problems.is_empty() {
+                    "complete"
+                } else if problems
+                    .iter()
+                    .all(|x| x.message.ends_with("is not closed"))
+                {
+                    "incomplete"
+                } else {
+                    "invalid"
+                };
+                json!({"status": status, "indent": ""})
+            }
+            "complete_request" => self.complete(&message),
+            "comm_info_request" => json!({"status": "ok", "comms": {}}),
+            "interrupt_request" => json!({"status": "ok"}),
+            "shutdown_request" => {
+                running = false;
+                json!({"status": "ok", "restart": message.content["restart"]})
+            }
+            _ => {
+                self.publish(&message, "status", json!({"execution_state": "idle"}))
+                    .await?;
+                return Ok(true);
+            }
+        };
+        self.reply(socket, &message, &reply_kind, content).await?;
+        self.publish(&message, "status", json!({"execution_state": "idle"}))
+            .await?;
+        Ok(running)
+    }
+
+    /// Run the code of the cell, and publish its outputs
+    async fn execute(&mut self, message: &Message) -> Result<(Value, bool), String> {
+        let code = message.content["code"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let silent = message.content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.count += 1;
+            let input = json!({"code": code, "execution_count": self.count});
+            self.publish(message, "execute_input", input).await?;
+        }
+
+        let response = protocol::evaluate(&mut self.executor, code);
+        if !silent {
+            if !response.stdout.is_empty() {
+                let stream = json!({"name": "stdout", "text": response.stdout});
+                self.publish(message, "stream", stream).await?;
+            }
+            if !response.errors.is_empty() {
+                let stream = json!({"name": "stderr", "text": response.errors.join("\n") + "\n"});
+                self.publish(message, "stream", stream).await?;
+            }
+            if !response.stack.is_empty() {
+                let result = json!({
+                    "execution_count": self.count,
+                    "data": display(&response.stack),
+                    "metadata": {},
+                });
+                self.publish(message, "execute_result", result).await?;
+            }
+        }
+        let content = json!({
+            "status": "ok",
+            "execution_count": self.count,
+            "user_expressions": {},
+        });
+        Ok((content, response.exit.is_some()))
+    }
+
+    /// Complete the command or variable name before the cursor
+    fn complete(&self, message: &Message) -> Value {
+        let code = message.content["code"].as_str().unwrap_or_default();
+        let cursor = message.content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+        let before: Vec<char> = code.chars().take(cursor).collect();
+        let start = before
+            .iter()
+            .rposition(|c| c.is_whitespace() || "()[]{}".contains(*c))
+            .map_or(0, |x| x + 1);
+        let word: String = before[start..].iter().collect();
+
+        let mut matches: Vec<String> = registry::COMMANDS
+            .iter()
+            .map(|x| x.name.to_string())
+            .chain(self.executor.memory.keys().map(|x| x.to_string()))
+            .filter(|name| !word.is_empty() && name.starts_with(&word))
+            .collect();
+        matches.sort();
+        matches.dedup();
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": start,
+            "cursor_end": before.len(),
+            "metadata": {},
+        })
+    }
+}
+
+/// Show the stack as the plain text and the HTML
+fn display(stack: &[Type]) -> Value {
+    let text: Vec<String> = stack.iter().map(|x| x.display()).collect();
+    let html: Vec<String> = stack.iter().map(html).collect();
+    json!({
+        "text/plain": text.join(" | "),
+        "text/html": html.join(" | "),
+    })
+}
+
+/// Render the value in HTML, lists and objects as the tables
+fn html(value: &Type) -> String {
+    match value {
+        Type::List(list) => {
+            let rows: String = list
+                .iter()
+                .enumerate()
+                .map(|(i, x)| format!("<tr><th>{i}</th><td>{}</td></tr>", html(x)))
+                .collect();
+            format!("<table>{rows}</table>")
+        }
+        Type::Object(name, object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            let rows: String = keys
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "<tr><th>{}</th><td>{}</td></tr>",
+                        escape(key),
+                        html(&object[key])
+                    )
+                })
+                .collect();
+            format!("<table><caption>{}</caption>{rows}</table>", escape(name))
+        }
+        value => format!("<code>{}</code>", escape(&value.display())),
+    }
+}
+
+/// Escape the text for HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
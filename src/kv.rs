@@ -0,0 +1,60 @@
+use crate::json::{from_json, to_json};
+use crate::Type;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Key-value store persisted in the JSON file
+#[derive(Clone, Debug)]
+pub struct Store {
+    path: PathBuf,
+    data: Map<String, Value>,
+}
+
+impl Store {
+    /// Open the store, which is empty until the file is made
+    pub fn open(path: &Path) -> io::Result<Store> {
+        let data = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Map::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Store {
+            path: path.to_owned(),
+            data,
+        })
+    }
+
+    /// Get the value of the key
+    pub fn get(&self, key: &str) -> Option<Type> {
+        self.data.get(key).map(from_json)
+    }
+
+    /// Set the value of the key, and save the file
+    pub fn set(&mut self, key: String, value: &Type) -> io::Result<()> {
+        self.data.insert(key, to_json(value));
+        self.save()
+    }
+
+    /// Delete the key, and save the file
+    pub fn delete(&mut self, key: &str) -> io::Result<()> {
+        if self.data.remove(key).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Get all of the keys
+    pub fn keys(&self) -> Vec<String> {
+        self.data.keys().cloned().collect()
+    }
+
+    /// Write the whole store to the file
+    fn save(&self) -> io::Result<()> {
+        // Replace the file at once, so that it isn't broken when stopped in writing
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(&self.data)?)?;
+        fs::rename(temporary, &self.path)
+    }
+}
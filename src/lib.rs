@@ -0,0 +1,1552 @@
+use diagnostics::{ColorChoice, Severity};
+pub use error::StackError;
+use im::Vector;
+use indexmap::IndexMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, Error, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+pub mod bench;
+pub mod cassette;
+pub mod check;
+pub mod compile;
+mod datetime;
+pub mod diagnostics;
+pub mod doc;
+mod error;
+mod event;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod files;
+pub mod fmt;
+mod functions;
+#[cfg(feature = "network")]
+mod http;
+pub mod i18n;
+mod ipc;
+mod json;
+mod kv;
+mod locale;
+pub mod permission;
+mod plugin;
+pub mod protocol;
+#[cfg(feature = "python")]
+mod python;
+pub mod registry;
+mod spreadsheet;
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)] // The tests compare the results with the expected values
+mod test;
+
+/// Read string of the file
+pub fn get_file_contents(name: &Path) -> Result<String, Error> {
+    let mut f = File::open(name)?;
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Get standard input
+pub fn input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().ok();
+    let mut result = String::new();
+    io::stdin().read_line(&mut result).ok();
+    result.trim().to_string()
+}
+
+/// Execution Mode
+#[derive(Clone, Debug)]
+pub enum Mode {
+    Script, // Script execution
+    Debug,  // Debug execution
+}
+
+thread_local! {
+    /// Precision of the executor running in this thread, as the values are shown without it
+    static PRECISION: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Set the digits to show numbers in this thread, None is the shortest form
+fn set_precision(digits: Option<usize>) {
+    PRECISION.with(|precision| precision.set(digits))
+}
+
+/// Get the digits to show numbers in this thread
+fn precision() -> Option<usize> {
+    PRECISION.with(|precision| precision.get())
+}
+
+/// Show the number by the precision
+fn format_number(number: f64) -> String {
+    match precision() {
+        Some(digits) if number.is_finite() => {
+            let text = format!("{number:.digits$}");
+            // Drop the trailing zeros, so that 0.30000000000000004 is shown as 0.3
+            let text = if text.contains('.') {
+                text.trim_end_matches('0').trim_end_matches('.')
+            } else {
+                &text
+            };
+            match text {
+                "-0" => "0".to_string(),
+                text => text.to_string(),
+            }
+        }
+        _ => number.to_string(),
+    }
+}
+
+/// Limit of the nested macro expansion, to stop the recursive macros
+const MACRO_DEPTH: usize = 64;
+
+/// Limit of the interned symbols, the table is cleared over it not to grow by the dynamic code
+const SYMBOL_LIMIT: usize = 65536;
+
+/// Shared string of the token, which is cloned without allocation
+pub type Symbol = Arc<str>;
+
+/// Characters escaped in the string literal
+const ESCAPED: [char; 6] = ['\\', '(', ')', '[', ']', '#'];
+
+/// Escape the text to be embedded in the string literal, line breaks and tabs become `\n` and `\t`
+fn escape_string(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            c if ESCAPED.contains(&c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result += "\\n",
+            '\t' => result += "\\t",
+            '\r' => result += "\\r",
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Get the string literal, escaping the brackets and comments
+fn string_literal(text: &str) -> String {
+    let mut result = String::from("(");
+    let mut decoded = 0; // Characters concatenated by the code point
+    for c in text.chars() {
+        match c {
+            c if ESCAPED.contains(&c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            // These are read as the space, so they are made from the code point
+            '\n' | '\t' | '\r' | '　' => {
+                result += &format!(") {} decode concat (", c as u32);
+                decoded += 1;
+            }
+            c => result.push(c),
+        }
+    }
+    result.push(')');
+    // Drop the empty string after the last decoded character
+    if let Some(trimmed) = result.strip_suffix(" ()") {
+        result = trimmed.to_string();
+        decoded -= 1;
+    }
+    result + &" concat".repeat(decoded)
+}
+
+/// Get the value of the string token, processing the escapes
+fn string_value(token: &str) -> String {
+    let mut buffer = String::new(); // Temporary storage
+    let mut brackets = 0; // String's nest structure
+    let mut parentheses = 0; // List's nest structure
+    let mut hash = false; // Is it Comment
+    let mut escape = false; // Flag to indicate next character is escaped
+
+    for c in token[1..token.len() - 1].chars() {
+        match c {
+            '\\' if !escape => {
+                escape = true;
+            }
+            '(' if !hash && !escape => {
+                brackets += 1;
+                buffer.push('(');
+            }
+            ')' if !hash && !escape => {
+                brackets -= 1;
+                buffer.push(')');
+            }
+            '#' if !hash && !escape => {
+                hash = true;
+                buffer.push('#');
+            }
+            '#' if hash && !escape => {
+                hash = false;
+                buffer.push('#');
+            }
+            '[' if !hash && brackets == 0 && !escape => {
+                parentheses += 1;
+                buffer.push('[');
+            }
+            ']' if !hash && brackets == 0 && !escape => {
+                parentheses -= 1;
+                buffer.push(']');
+            }
+            _ => {
+                if parentheses == 0 && brackets == 0 && !hash {
+                    if escape {
+                        match c {
+                            'n' => buffer.push_str("\\n"),
+                            't' => buffer.push_str("\\t"),
+                            'r' => buffer.push_str("\\r"),
+                            _ => buffer.push(c),
+                        }
+                    } else {
+                        buffer.push(c);
+                    }
+                } else {
+                    if escape {
+                        buffer.push('\\');
+                    }
+                    buffer.push(c);
+                }
+                escape = false; // Reset escape flag for non-escape characters
+            }
+        }
+    }
+    buffer
+}
+
+/// Judge is the value shown over lines by the inspection
+fn is_compound(value: &Type) -> bool {
+    match value {
+        Type::List(list) => !list.is_empty(),
+        Type::PList(list) => !list.is_empty(),
+        Type::Object(..) => true,
+        _ => false,
+    }
+}
+
+/// Data type
+#[derive(Clone, Debug)]
+pub enum Type {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    List(Vec<Type>),
+    PList(Vector<Type>), // Persistent list sharing its structure with the copies
+    Object(String, IndexMap<String, Type>),
+    Function(Vec<String>, String), // Parameters and the body code
+    Error(ErrorValue),
+}
+
+/// Error record with its identity and detail
+#[derive(Clone, Debug)]
+pub struct ErrorValue {
+    pub code: String,                    // Identity to branch on
+    pub message: String,                 // Description for human
+    pub payload: Option<Box<Type>>,      // Value attached to the error
+    pub origin: Option<(String, usize)>, // Command and index of its token which raised it
+}
+
+/// Implement methods
+impl Type {
+    /// Make the error value by the code
+    pub fn error(code: &str) -> Type {
+        Type::Error(ErrorValue {
+            code: code.to_string(),
+            message: String::new(),
+            payload: None,
+            origin: None,
+        })
+    }
+
+    /// Show data to display
+    pub fn display(&self) -> String {
+        match self {
+            Type::Number(num) => format_number(*num),
+            Type::String(s) => format!("({})", s),
+            Type::Bool(b) => b.to_string(),
+            Type::List(list) => {
+                let result: Vec<String> = list.iter().map(|token| token.display()).collect();
+                format!("[{}]", result.join(" "))
+            }
+            Type::PList(list) => {
+                let result: Vec<String> = list.iter().map(|token| token.display()).collect();
+                format!("[{}]", result.join(" "))
+            }
+            Type::Error(err) => format!("error:{}", err.code),
+            Type::Object(name, _) => {
+                format!("Object<{name}>")
+            }
+            Type::Function(params, _) => format!("Function<{}>", params.join(" ")),
+        }
+    }
+
+    /// Show data like `display`, cutting the lists and strings longer than the limit
+    ///
+    /// Only the shown part is visited, so that huge values are cheap to show.
+    pub fn summary(&self, limit: usize) -> String {
+        let items = |list: &mut dyn ExactSizeIterator<Item = &Type>| {
+            let len = list.len();
+            let mut result: Vec<String> = list.take(limit).map(|x| x.summary(limit)).collect();
+            if len > limit {
+                result.push(format!("… {} more", len - limit));
+            }
+            format!("[{}]", result.join(" "))
+        };
+        match self {
+            _ if limit == 0 => self.display(),
+            Type::String(s) => match s.char_indices().nth(limit) {
+                Some((end, _)) => format!("({}…)", &s[..end]),
+                None => self.display(),
+            },
+            Type::List(list) => items(&mut list.iter()),
+            Type::PList(list) => items(&mut list.iter()),
+            _ => self.display(),
+        }
+    }
+
+    /// Show data in the readable form, where the objects and nested lists span lines
+    pub fn inspect(&self) -> String {
+        self.inspect_nested(0)
+    }
+
+    fn inspect_nested(&self, depth: usize) -> String {
+        let indent = "    ".repeat(depth + 1);
+        let close = "    ".repeat(depth);
+        match self {
+            Type::List(list) if list.iter().any(is_compound) => {
+                let items: Vec<String> = list
+                    .iter()
+                    .map(|x| format!("{indent}{}\n", x.inspect_nested(depth + 1)))
+                    .collect();
+                format!("[\n{}{close}]", items.concat())
+            }
+            Type::PList(list) if list.iter().any(is_compound) => {
+                Type::List(list.iter().cloned().collect()).inspect_nested(depth)
+            }
+            Type::Object(name, object) if !object.is_empty() => {
+                let items: Vec<String> = object
+                    .iter()
+                    .map(|(key, value)| {
+                        format!("{indent}{key}: {}\n", value.inspect_nested(depth + 1))
+                    })
+                    .collect();
+                format!("{name} {{\n{}{close}}}", items.concat())
+            }
+            Type::Object(name, _) => format!("{name} {{}}"),
+            Type::Error(err) if !err.message.is_empty() => {
+                format!("error:{} ({})", err.code, err.message)
+            }
+            _ => self.display(),
+        }
+    }
+
+    /// Get the code which pushes the same value when it is evaluated
+    pub fn literal(&self) -> String {
+        match self {
+            Type::Number(num) => num.to_string(),
+            Type::String(s) => string_literal(s),
+            Type::Bool(b) => b.to_string(),
+            Type::List(list) => {
+                let result: Vec<String> = list.iter().map(|x| x.literal()).collect();
+                format!("[{}]", result.join(" "))
+            }
+            Type::PList(list) => {
+                let result: Vec<String> = list.iter().map(|x| x.literal()).collect();
+                format!("[{}] plist", result.join(" "))
+            }
+            Type::Error(err) => {
+                let mut result = format!(
+                    "{} {} error",
+                    string_literal(&err.code),
+                    string_literal(&err.message)
+                );
+                if let Some(payload) = &err.payload {
+                    result += &format!(" {} with-payload", payload.literal());
+                }
+                result
+            }
+            Type::Object(name, object) => {
+                let pairs = object
+                    .iter()
+                    .map(|(key, value)| format!("{} {}", string_literal(key), value.literal()));
+                if name == "object" {
+                    format!("{{{}}}", pairs.collect::<Vec<_>>().join(" "))
+                } else {
+                    // The object of other class is made by the instance
+                    let items: Vec<String> = pairs.map(|x| format!("[{x}]")).collect();
+                    format!("[{} {}] [] instance", string_literal(name), items.join(" "))
+                }
+            }
+            Type::Function(params, body) => {
+                let params: Vec<String> = params.iter().map(|x| string_literal(x)).collect();
+                format!("[{}] {} func", params.join(" "), string_literal(body))
+            }
+        }
+    }
+
+    /// Get string form data
+    pub fn get_string(&mut self) -> String {
+        match self {
+            Type::String(s) => s.to_string(),
+            Type::Number(i) => format_number(*i),
+            Type::Bool(b) => b.to_string(),
+            Type::List(l) => Type::List(l.to_owned()).display(),
+            Type::PList(_) | Type::Function(..) => self.display(),
+            Type::Error(err) => format!("error:{}", err.code),
+            Type::Object(name, _) => {
+                format!("Object<{name}>")
+            }
+        }
+    }
+
+    /// Get number from data
+    pub fn get_number(&mut self) -> f64 {
+        match self {
+            Type::String(s) => s.parse().unwrap_or(0.0),
+            Type::Number(i) => *i,
+            Type::Bool(b) => {
+                if *b {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Type::List(l) => l.len() as f64,
+            Type::PList(l) => l.len() as f64,
+            Type::Error(e) => e.code.parse().unwrap_or(0f64),
+            Type::Object(_, object) => object.len() as f64,
+            Type::Function(params, _) => params.len() as f64,
+        }
+    }
+
+    /// Get bool from data
+    pub fn get_bool(&mut self) -> bool {
+        match self {
+            Type::String(s) => !s.is_empty(),
+            Type::Number(i) => *i != 0.0,
+            Type::Bool(b) => *b,
+            Type::List(l) => !l.is_empty(),
+            Type::PList(l) => !l.is_empty(),
+            Type::Error(e) => e.code.parse().unwrap_or(false),
+            Type::Object(_, object) => object.is_empty(),
+            Type::Function(..) => true,
+        }
+    }
+
+    /// Get list form data
+    pub fn get_list(&mut self) -> Vec<Type> {
+        match self {
+            Type::String(s) => s
+                .to_string()
+                .chars()
+                .map(|x| Type::String(x.to_string()))
+                .collect::<Vec<Type>>(),
+            Type::Number(i) => vec![Type::Number(*i)],
+            Type::Bool(b) => vec![Type::Bool(*b)],
+            Type::List(l) => l.to_vec(),
+            Type::PList(l) => l.iter().cloned().collect(),
+            Type::Error(e) => vec![Type::Error(e.clone())],
+            Type::Function(..) => vec![self.clone()],
+            Type::Object(_, object) => object.values().map(|x| x.to_owned()).collect::<Vec<Type>>(),
+        }
+    }
+
+    /// Get persistent list form data, which is cheap to copy
+    pub fn get_plist(&mut self) -> Vector<Type> {
+        match self {
+            Type::PList(l) => l.clone(),
+            _ => self.get_list().into(),
+        }
+    }
+
+    pub fn get_object(&self) -> (String, IndexMap<String, Type>) {
+        match self {
+            Type::Object(name, value) => (name.to_owned(), value.to_owned()),
+            _ => ("".to_string(), IndexMap::new()),
+        }
+    }
+}
+
+/// User configurable options
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub prompt: String,        // Prompt of the REPL
+    pub stack_open: String,    // Opening delimiter of the stack display
+    pub stack_close: String,   // Closing delimiter of the stack display
+    pub output_prefix: String, // Prefix of the output in debug mode
+    pub display_limit: usize,  // Items and characters shown in the stack display, 0 is all
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            prompt: "> ".to_string(),
+            stack_open: "〔".to_string(),
+            stack_close: "〕".to_string(),
+            output_prefix: "[Output]: ".to_string(),
+            display_limit: 20,
+        }
+    }
+}
+
+impl Options {
+    /// Set option value by the name
+    pub fn set(&mut self, name: &str, value: String) -> bool {
+        match name {
+            "prompt" => self.prompt = value,
+            "stack-open" => self.stack_open = value,
+            "stack-close" => self.stack_close = value,
+            "output-prefix" => self.output_prefix = value,
+            "display-limit" => match value.parse() {
+                Ok(limit) => self.display_limit = limit,
+                Err(_) => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Get option value by the name
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "prompt" => Some(self.prompt.clone()),
+            "stack-open" => Some(self.stack_open.clone()),
+            "stack-close" => Some(self.stack_close.clone()),
+            "output-prefix" => Some(self.output_prefix.clone()),
+            "display-limit" => Some(self.display_limit.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Manage program execution
+#[derive(Clone, Debug)]
+pub struct Executor {
+    pub stack: Vec<Type>,                                 // Data stack
+    pub memory: IndexMap<Symbol, Type>,                   // Variable's memory
+    pub mode: Mode,                                       // Execution mode
+    pub options: Options,                                 // User configurable options
+    pub interactive: bool,                                // Is the standard output a terminal
+    pub color: bool,                                      // Colorize the error messages
+    pub strict: bool,                                     // Treat the uncaught error as failure
+    pub precision: Option<usize>, // Digits to show numbers, None is the shortest
+    pub dry_run: bool,            // Report the file operations instead of doing them
+    pub confirm: HashSet<String>, // Commands asking the user before they run
+    pub permissions: Option<permission::Permissions>, // Accesses allowed in the secure mode
+    pub deterministic: Option<Determinism>, // Fixed random seed and time for the reproducible runs
+    pub cassette: Option<Arc<Mutex<cassette::Cassette>>>, // Recorded external interactions for the tests
+    command: Symbol,                                      // Token in execution
+    token: usize,                                         // Index of the token from 1 in the code being evaluated
+    failure: Option<StackError>,                          // Error to stop the evaluation
+    trying: usize,        // Depth of the try blocks, which the new error leaves
+    caught: Option<Type>, // Error caught by the last try block
+    pub trace: Option<Arc<Mutex<File>>>, // Output of the execution trace
+    pub audit: Option<Arc<Mutex<File>>>, // Log of the external effects
+    pub snapshots: Option<Vec<Snapshot>>, // Recorded states for stepping
+    pub memory_sample: Option<usize>, // Interval of tokens to report memory usage
+    pub capture: Option<Capture>, // Output kept for the client instead of printed
+    executed: usize,      // Number of the executed tokens
+    connections: Arc<Mutex<HashMap<String, Arc<Mutex<ipc::Connection>>>>>, // IPC connections
+    yielded: Option<Type>, // Value yielded by the generator
+    events: Arc<event::EventLoop>, // Event queue shared between threads
+    handlers: HashMap<String, Vec<String>>, // Callbacks of the events
+    kv: Option<kv::Store>, // Opened key-value store
+    cache: HashMap<String, (Instant, Type)>, // Cached values with the expiration
+    #[cfg(feature = "network")]
+    request: http::Config, // Settings of the HTTP requests
+    locals: Vec<HashMap<Symbol, Type>>, // Local scopes, the innermost is the last
+    symbols: HashSet<Symbol>, // Interned tokens
+    aliases: HashMap<String, String>, // Other names of the commands
+    macros: HashMap<String, Vec<Symbol>>, // Tokens replacing the macro names
+    wrappers: HashMap<String, String>, // User code intercepting the commands
+    wrapping: Vec<Symbol>, // Commands whose wrapper is running
+    docs: HashMap<String, String>, // Documentation of the user functions
+    namespaces: Vec<String>, // Namespaces in definition, the innermost is the last
+    modules: HashSet<PathBuf>, // Imported files, which aren't evaluated again
+    last_message: Option<String>, // Error message logged by the command in execution
+    plugins: HashMap<String, plugin::Command>, // Commands loaded from the plugins
+    stopwatches: HashMap<String, Instant>, // Start time of the stopwatches
+    buffers: Vec<String>, // String buffers, the handle has the index
+    hooks: Hooks,         // Functions called around each token
+    #[cfg(feature = "ffi")]
+    libraries: Vec<Arc<libloading::Library>>, // Shared libraries for the foreign functions
+}
+
+/// Output of the evaluation kept for the client, like the editor
+#[derive(Clone, Debug, Default)]
+pub struct Capture {
+    pub stdout: String,      // Printed text
+    pub errors: Vec<String>, // Logged messages
+}
+
+/// Fixed sources of the values which differ on every run, for the deterministic mode
+#[derive(Clone, Debug)]
+pub struct Determinism {
+    pub rng: StdRng, // Random numbers from the seed
+    pub now: f64,    // Frozen now time as unix epoch
+}
+
+impl Determinism {
+    /// Seed the random numbers and freeze the time
+    pub fn new(seed: u64, now: f64) -> Determinism {
+        Determinism {
+            rng: StdRng::seed_from_u64(seed),
+            now,
+        }
+    }
+}
+
+/// Function called around each token with its executor, the error stops the evaluation
+pub type Hook = Arc<dyn Fn(&Executor, &str) -> Result<(), String> + Send + Sync>;
+
+/// Hooks of the embedder and the script, called before and after each token
+#[derive(Clone, Default)]
+struct Hooks {
+    pre: Vec<Hook>,
+    post: Vec<Hook>,
+    pre_blocks: Vec<String>,  // Code registered by `on-token`
+    post_blocks: Vec<String>, // Code registered by `on-token`
+    running: bool,            // Is a block running, its tokens are not hooked
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("pre", &self.pre.len())
+            .field("post", &self.post.len())
+            .field("pre_blocks", &self.pre_blocks)
+            .field("post_blocks", &self.post_blocks)
+            .finish()
+    }
+}
+
+/// State of the executor after a token was executed
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub token: String,                  // Executed token
+    pub stack: Vec<Type>,               // Data stack
+    pub memory: IndexMap<Symbol, Type>, // Variable's memory
+}
+
+impl Executor {
+    /// Constructor
+    pub fn new(mode: Mode) -> Executor {
+        Executor {
+            stack: Vec::new(),
+            memory: IndexMap::new(),
+            mode,
+            options: Options::default(),
+            interactive: io::stdout().is_terminal(),
+            color: ColorChoice::Auto.enabled(io::stdout().is_terminal()),
+            precision: None,
+            strict: false,
+            dry_run: false,
+            confirm: HashSet::new(),
+            permissions: None,
+            deterministic: None,
+            cassette: None,
+            command: Symbol::from(""),
+            token: 0,
+            failure: None,
+            trying: 0,
+            caught: None,
+            trace: None,
+            audit: None,
+            snapshots: None,
+            memory_sample: None,
+            capture: None,
+            executed: 0,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            yielded: None,
+            events: Arc::new(event::EventLoop::default()),
+            handlers: HashMap::new(),
+            kv: None,
+            cache: HashMap::new(),
+            #[cfg(feature = "network")]
+            request: http::Config::default(),
+            locals: Vec::new(),
+            symbols: HashSet::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            wrappers: HashMap::new(),
+            wrapping: Vec::new(),
+            docs: HashMap::new(),
+            namespaces: Vec::new(),
+            modules: HashSet::new(),
+            last_message: None,
+            plugins: HashMap::new(),
+            stopwatches: HashMap::new(),
+            buffers: Vec::new(),
+            hooks: Hooks::default(),
+            #[cfg(feature = "ffi")]
+            libraries: Vec::new(),
+        }
+    }
+
+    /// Make the executor for the nested evaluation, restricted in the same way as this one
+    pub fn child(&self) -> Executor {
+        let mut child = Executor::new(self.mode.clone());
+        child.precision = self.precision;
+        child.dry_run = self.dry_run;
+        child.confirm = self.confirm.clone();
+        child.permissions = self.permissions.clone();
+        child.deterministic = self.deterministic.clone();
+        child.cassette = self.cassette.clone();
+        child.audit = self.audit.clone();
+        child
+    }
+
+    /// Clear the state left by the evaluation interrupted with a panic
+    pub fn recover(&mut self) {
+        self.failure = None;
+        self.trying = 0;
+        self.yielded = None;
+        self.last_message = None;
+        self.locals.clear();
+        self.wrapping.clear();
+        self.namespaces.clear();
+        self.command = Symbol::from("");
+        self.hooks.running = false;
+    }
+
+    /// Get the process exit status after the script finished
+    pub fn exit_status(&self) -> i32 {
+        let failed = self.stack.iter().any(|x| matches!(x, Type::Error(_)));
+        if self.strict && failed {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Output log
+    fn log_print(&mut self, msg: String) {
+        if let Mode::Debug = self.mode {
+            // Keep the piped output clean from the debug log
+            if self.interactive {
+                print!("{msg}");
+            } else {
+                eprint!("{msg}");
+            }
+        }
+    }
+
+    /// Output the text printed by the script
+    fn write_output(&mut self, text: &str) {
+        match &mut self.capture {
+            Some(capture) => capture.stdout += text,
+            None => print!("{text}"),
+        }
+    }
+
+    /// Format the error message with the token in execution
+    pub fn format_error(&self, severity: Severity, msg: &str) -> String {
+        let origin = if self.command.is_empty() {
+            None
+        } else {
+            Some((&*self.command, self.token))
+        };
+        diagnostics::format(severity, msg, origin, self.color)
+    }
+
+    /// Output the message of the error or warning, or keep it when captured
+    fn log_message(&mut self, severity: Severity, msg: &str) {
+        let formatted = self.format_error(severity, msg);
+        match &mut self.capture {
+            Some(capture) => capture.errors.push(formatted.trim_end().to_string()),
+            None => self.log_print(formatted),
+        }
+    }
+
+    /// Output error caused by the script
+    fn log_error(&mut self, msg: String) {
+        self.log_message(Severity::User, &msg);
+        self.last_message = Some(msg.clone());
+        self.fail(msg);
+    }
+
+    /// Output error caused by the interpreter or the host environment
+    fn log_internal_error(&mut self, msg: String) {
+        self.log_message(Severity::Internal, &msg);
+        self.last_message = Some(msg.clone());
+        self.fail(msg);
+    }
+
+    /// Output warning of the suspicious code, which is error in strict mode
+    fn log_warning(&mut self, msg: String) {
+        self.log_message(Severity::Warning, &msg);
+        self.fail(msg);
+    }
+
+    /// Stop the evaluation by the error in strict mode
+    fn fail(&mut self, message: String) {
+        if self.strict && self.failure.is_none() {
+            self.failure = Some(StackError::Runtime {
+                command: self.command.to_string(),
+                token: self.token,
+                message,
+            });
+        }
+    }
+
+    /// Record where the new error on the top was raised and why, and tell is there the new one
+    fn stamp_error(&mut self) -> bool {
+        let message = self.last_message.take();
+        if let Some(Type::Error(err)) = self.stack.last_mut() {
+            if err.origin.is_none() {
+                err.origin = Some((self.command.to_string(), self.token));
+                if err.message.is_empty() {
+                    err.message = message.unwrap_or_else(|| err.code.clone());
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Write the executed token to the trace file as JSON line
+    fn write_trace(&self, token: &str, elapsed: Duration) {
+        if let Some(trace) = &self.trace {
+            let record = serde_json::json!({
+                "token": token,
+                "index": self.token,
+                "stack": json::stack_to_json(&self.stack),
+                "elapsed_us": elapsed.as_micros() as u64,
+            });
+            if let Ok(mut file) = trace.lock() {
+                writeln!(file, "{record}").ok();
+            }
+        }
+    }
+
+    /// Write the command with the external effect and its arguments to the audit log
+    fn write_audit(&self, command: &str) {
+        let (Some(audit), Some(effect)) = (&self.audit, registry::effect(command)) else {
+            return;
+        };
+        let args = self.arguments(command);
+        let record = serde_json::json!({
+            "time": chrono::Local::now().to_rfc3339(),
+            "effect": effect,
+            "command": command,
+            "args": json::stack_to_json(args),
+            "dry_run": self.dry_run,
+        });
+        if let Ok(mut file) = audit.lock() {
+            writeln!(file, "{record}").ok();
+        }
+    }
+
+    /// Get the values the command takes from the top of the stack
+    fn arguments(&self, command: &str) -> &[Type] {
+        let arity = registry::find(command).map_or(0, |info| info.arity);
+        &self.stack[self.stack.len().saturating_sub(arity)..]
+    }
+
+    /// Report the operation which is skipped in the dry run, and tell whether it is
+    fn dry_run(&self, action: String) -> bool {
+        if self.dry_run {
+            eprintln!("[Dry run] {action}");
+        }
+        self.dry_run
+    }
+
+    /// Record the state of executor for stepping
+    fn record_snapshot(&mut self, token: &str) {
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.push(Snapshot {
+                token: token.to_string(),
+                stack: self.stack.clone(),
+                memory: self.memory.clone(),
+            });
+        }
+    }
+
+    /// Report the size of stack and memory every interval of tokens
+    fn sample_memory(&mut self) {
+        self.executed += 1;
+        if let Some(interval) = self.memory_sample {
+            if interval > 0 && self.executed.is_multiple_of(interval) {
+                let bytes: usize = self.stack.iter().map(approximate_size).sum::<usize>()
+                    + self
+                        .memory
+                        .iter()
+                        .map(|(name, value)| name.len() + approximate_size(value))
+                        .sum::<usize>();
+                eprintln!(
+                    "[Memory] tokens: {}, stack: {}, memory: {}, bytes: ~{}",
+                    self.executed,
+                    self.stack.len(),
+                    self.memory.len(),
+                    bytes
+                );
+            }
+        }
+    }
+
+    /// Call the function before each token is executed
+    pub fn add_pre_token_hook(
+        &mut self,
+        hook: impl Fn(&Executor, &str) -> Result<(), String> + Send + Sync + 'static,
+    ) {
+        self.hooks.pre.push(Arc::new(hook));
+    }
+
+    /// Call the function after each token is executed
+    pub fn add_post_token_hook(
+        &mut self,
+        hook: impl Fn(&Executor, &str) -> Result<(), String> + Send + Sync + 'static,
+    ) {
+        self.hooks.post.push(Arc::new(hook));
+    }
+
+    /// Run the hooks of the token, with the token on the stack for the blocks
+    fn run_hooks(&mut self, pre: bool, token: &str) -> Result<(), StackError> {
+        if self.hooks.running {
+            return Ok(());
+        }
+        let (hooks, blocks) = if pre {
+            (&self.hooks.pre, &self.hooks.pre_blocks)
+        } else {
+            (&self.hooks.post, &self.hooks.post_blocks)
+        };
+        if hooks.is_empty() && blocks.is_empty() {
+            return Ok(());
+        }
+        let (hooks, blocks) = (hooks.clone(), blocks.clone());
+
+        for hook in hooks {
+            if let Err(message) = hook(self, token) {
+                return Err(StackError::Runtime {
+                    command: token.to_string(),
+                    token: self.token,
+                    message,
+                });
+            }
+        }
+        let origin = (self.command.clone(), self.token);
+        self.hooks.running = true;
+        let mut result = Ok(());
+        for block in blocks {
+            self.stack.push(Type::String(token.to_string()));
+            result = self.evaluate_program(block);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.hooks.running = false;
+        (self.command, self.token) = origin;
+        result
+    }
+
+    /// Get the function to request the graceful shutdown, which can be called from other threads
+    pub fn shutdown_handle(&self) -> impl Fn() + Send + Sync + 'static {
+        let events = self.events.clone();
+        move || events.shutdown()
+    }
+
+    /// Load the commands of the plugin, and get the number of them
+    pub fn load_plugin(&mut self, path: &Path) -> Result<usize, String> {
+        let commands = plugin::load(path)?;
+        let count = commands.len();
+        self.plugins.extend(commands);
+        Ok(count)
+    }
+
+    /// Keep the IPC connection by the name
+    fn add_connection(&mut self, name: String, connection: ipc::Connection) {
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.insert(name, Arc::new(Mutex::new(connection)));
+        }
+    }
+
+    /// Get the IPC connection by the name
+    fn connection(&self, name: &str) -> Option<Arc<Mutex<ipc::Connection>>> {
+        self.connections.lock().ok()?.get(name).cloned()
+    }
+
+    /// Get the variable, the local scope is prior to the global memory
+    fn variable(&self, name: &str) -> Option<&Type> {
+        if let Some(value) = self.locals.last().and_then(|scope| scope.get(name)) {
+            return Some(value);
+        }
+        let index = self.global_index(name)?;
+        self.memory.get_index(index).map(|(_, value)| value)
+    }
+
+    /// Get the variable to reassign, resolved in the same order as `variable`
+    fn variable_mut(&mut self, name: &str) -> Option<&mut Type> {
+        if self
+            .locals
+            .last()
+            .is_some_and(|scope| scope.contains_key(name))
+        {
+            return self.locals.last_mut()?.get_mut(name);
+        }
+        let index = self.global_index(name)?;
+        self.memory.get_index_mut(index).map(|(_, value)| value)
+    }
+
+    /// Get the index of the global variable, the inner namespace is prior to the outer one
+    fn global_index(&self, name: &str) -> Option<usize> {
+        (1..=self.namespaces.len())
+            .rev()
+            .find_map(|depth| {
+                let prefix = self.namespaces[..depth].join(".");
+                self.memory
+                    .get_index_of(format!("{prefix}.{name}").as_str())
+            })
+            .or_else(|| self.memory.get_index_of(name))
+    }
+
+    /// Bind the value to the name where `var` defines it,
+    /// the local scope in the function or the global memory in the namespace
+    fn bind(&mut self, name: String, value: Type) {
+        let name = match self.locals.is_empty() {
+            true => self.qualify(name),
+            false => name,
+        };
+        let name = self.intern(&name);
+        match self.locals.last_mut() {
+            Some(scope) => {
+                scope.insert(name, value);
+            }
+            None => {
+                self.memory.insert(name, value);
+            }
+        }
+    }
+
+    /// Get the nested property by the dotted path like `user.address.city`
+    fn property_path(&self, path: &str) -> Option<Type> {
+        let mut names = path.split('.');
+        let mut value = self.variable(names.next()?)?;
+        if !matches!(value, Type::Object(..)) {
+            return None;
+        }
+        for name in names {
+            value = match value {
+                Type::Object(_, object) => match object.get(name) {
+                    Some(property) => property,
+                    None => return Some(Type::error("property")),
+                },
+                _ => return Some(Type::error("property")),
+            };
+        }
+        Some(value.clone())
+    }
+
+    /// Get the full name of variable in the current namespace
+    fn qualify(&self, name: String) -> String {
+        if self.namespaces.is_empty() {
+            name
+        } else {
+            format!("{}.{name}", self.namespaces.join("."))
+        }
+    }
+
+    /// Show variable inside memory
+    fn show_variables(&mut self) {
+        if !matches!(self.mode, Mode::Debug) {
+            return;
+        }
+        self.log_print("Variables {\n".to_string());
+        let max = self.memory.keys().map(|s| s.len()).max().unwrap_or(0);
+        let limit = self.options.display_limit;
+        let lines: Vec<String> = self
+            .memory
+            .iter()
+            .map(|(name, value)| format!(" {name:>max$}: {}\n", value.summary(limit)))
+            .collect();
+        for line in lines {
+            self.log_print(line)
+        }
+        self.log_print("}\n".to_string())
+    }
+
+    /// Show inside the stack
+    pub fn show_stack(&mut self) -> String {
+        format!(
+            "Stack{} {} {}",
+            self.options.stack_open,
+            self.stack
+                .iter()
+                .map(|x| x.summary(self.options.display_limit))
+                .collect::<Vec<_>>()
+                .join(" | "),
+            self.options.stack_close
+        )
+    }
+
+    /// Get the shared symbol of the text, which is allocated only the first time
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(text) {
+            return symbol.clone();
+        }
+        // The symbols in use are kept alive by their owners, only the sharing restarts
+        if self.symbols.len() >= SYMBOL_LIMIT {
+            self.symbols.clear();
+        }
+        let symbol = Symbol::from(text);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    /// Parse token by analyzing syntax
+    pub fn analyze_syntax(&mut self, code: String) -> Vec<Symbol> {
+        // Convert tabs, line breaks, and full-width spaces to half-width spaces
+        let code = code.replace(['\n', '\t', '\r', '　'], " ");
+
+        let mut syntax = Vec::new(); // Token string
+        let mut buffer = String::new(); // Temporary storage
+        let mut brackets = 0; // String's nest structure
+        let mut parentheses = 0; // List's nest structure
+        let mut hash = false; // Is it Comment
+        let mut escape = false; // Flag to indicate next character is escaped
+
+        for c in code.chars() {
+            match c {
+                '\\' if !escape => {
+                    escape = true;
+                }
+                '(' if !hash && !escape => {
+                    // The string next to the token is separated, like `(age)30`
+                    if brackets == 0 && parentheses == 0 && !buffer.is_empty() {
+                        syntax.push(self.intern(&buffer));
+                        buffer.clear();
+                    }
+                    brackets += 1;
+                    buffer.push('(');
+                }
+                ')' if !hash && !escape => {
+                    brackets -= 1;
+                    buffer.push(')');
+                    if brackets == 0 && parentheses == 0 {
+                        syntax.push(self.intern(&buffer));
+                        buffer.clear();
+                    }
+                }
+                '#' if !hash && !escape => {
+                    hash = true;
+                    buffer.push('#');
+                }
+                '#' if hash && !escape => {
+                    hash = false;
+                    buffer.push('#');
+                }
+                '[' if !hash && brackets == 0 && !escape => {
+                    parentheses += 1;
+                    buffer.push('[');
+                }
+                ']' if !hash && brackets == 0 && !escape => {
+                    parentheses -= 1;
+                    buffer.push(']');
+                }
+                '{' if !hash && brackets == 0 && !escape => {
+                    parentheses += 1;
+                    buffer.push('{');
+                }
+                '}' if !hash && brackets == 0 && !escape => {
+                    parentheses -= 1;
+                    buffer.push('}');
+                }
+                ' ' if !hash && parentheses == 0 && brackets == 0 && !escape => {
+                    if !buffer.is_empty() {
+                        syntax.push(self.intern(&buffer));
+                        buffer.clear();
+                    }
+                }
+                _ => {
+                    if parentheses == 0 && brackets == 0 && !hash {
+                        if escape {
+                            match c {
+                                'n' => buffer.push_str("\\n"),
+                                't' => buffer.push_str("\\t"),
+                                'r' => buffer.push_str("\\r"),
+                                _ => buffer.push(c),
+                            }
+                        } else {
+                            buffer.push(c);
+                        }
+                    } else {
+                        if escape {
+                            buffer.push('\\');
+                        }
+                        buffer.push(c);
+                    }
+                    escape = false; // Reset escape flag for non-escape characters
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            syntax.push(self.intern(&buffer));
+        }
+        syntax
+    }
+
+    /// evaluate string as program
+    pub fn evaluate_program(&mut self, code: String) -> Result<(), StackError> {
+        // Parse into token string
+        let syntax: Vec<Symbol> = self.analyze_syntax(code);
+        let syntax = self.expand_macros(syntax);
+        let result = self.evaluate_tokens(syntax);
+        // The values are shown by this precision after the evaluation, even if the nested one differs
+        set_precision(self.precision);
+        result
+    }
+
+    /// Evaluate the startup file before the REPL starts
+    pub fn load_init_file(&mut self, path: &Path) -> Result<(), StackError> {
+        let code = match get_file_contents(path) {
+            Ok(code) => code,
+            Err(err) => {
+                self.log_error(format!("{}: {err}", path.display()));
+                return Ok(());
+            }
+        };
+        // Run quietly so that the definitions don't flood the console
+        let mode = self.mode.clone();
+        self.mode = Mode::Script;
+        let result = self.evaluate_program(code);
+        self.mode = mode;
+        result
+    }
+
+    /// Evaluate the tokens once per line of the input, like awk
+    ///
+    /// The line is in `line` and its number from 1 in `line-number`, and `break` skips the rest.
+    pub fn evaluate_each_line(
+        &mut self,
+        syntax: Vec<Symbol>,
+        input: impl BufRead,
+    ) -> Result<(), StackError> {
+        let syntax = self.expand_macros(syntax);
+        for (i, line) in input.lines().map_while(Result::ok).enumerate() {
+            self.bind("line".to_string(), Type::String(line));
+            self.bind("line-number".to_string(), Type::Number((i + 1) as f64));
+            match self.evaluate_tokens(syntax.clone()) {
+                Err(StackError::Break) => break,
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the macro names by their tokens
+    ///
+    /// `(template) (name) macro` written with the string literals defines the macro for the
+    /// following tokens as well. It is kept to be defined again when the tokens are evaluated.
+    pub fn expand_macros(&mut self, syntax: Vec<Symbol>) -> Vec<Symbol> {
+        if self.macros.is_empty() && !syntax.iter().any(|token| &**token == "macro") {
+            return syntax;
+        }
+        self.expand(syntax, 0)
+    }
+
+    /// Expand the macros in the tokens, which may be used in the template of another
+    fn expand(&mut self, syntax: Vec<Symbol>, depth: usize) -> Vec<Symbol> {
+        let mut result: Vec<Symbol> = Vec::new();
+        for token in syntax {
+            if &*token == "macro" {
+                if let [.., template, name] = result.as_slice() {
+                    let is_string =
+                        |x: &str| x.len() >= 2 && x.starts_with('(') && x.ends_with(')');
+                    if is_string(template) && is_string(name) {
+                        let (template, name) = (string_value(template), string_value(name));
+                        self.define_macro(name, template);
+                    }
+                }
+                result.push(token);
+            } else if let Some(tokens) = self.macros.get(&*token).cloned() {
+                if depth < MACRO_DEPTH {
+                    result.extend(self.expand(tokens, depth + 1));
+                } else {
+                    self.log_error(format!("the macro `{token}` is expanded too deeply"));
+                    result.push(token);
+                }
+            } else {
+                result.push(token);
+            }
+        }
+        result
+    }
+
+    /// Register the macro, the template is tokenized once here
+    fn define_macro(&mut self, name: String, template: String) {
+        let tokens = self.analyze_syntax(template);
+        self.macros.insert(name, tokens);
+    }
+
+    /// evaluate parsed tokens as program
+    pub fn evaluate_tokens(&mut self, syntax: Vec<Symbol>) -> Result<(), StackError> {
+        let origin = (self.command.clone(), self.token);
+
+        for (index, token) in syntax.into_iter().enumerate() {
+            // Leave the program at the token boundary, when the shutdown is requested
+            if self.events.is_closed() {
+                return Err(StackError::Exit(0));
+            }
+            self.command = token.clone();
+            self.token = index + 1;
+            set_precision(self.precision);
+            self.run_hooks(true, &token)?;
+
+            let start = Instant::now();
+
+            // Show inside stack to debug, which is skipped otherwise as it costs the whole stack
+            if let Mode::Debug = self.mode {
+                let stack = self.show_stack();
+                self.log_print(format!("{stack} ←  {token}\n"));
+            }
+
+            // Judge is the token enclosed by the characters, like `(...)`
+            let enclosed = |open, close| token.starts_with(open) && token.ends_with(close);
+
+            // Judge what the token is
+            if let Ok(i) = token.parse::<f64>() {
+                // Push number value on the stack
+                self.stack.push(Type::Number(i));
+            } else if &*token == "true" || &*token == "false" {
+                // Push bool value on the stack
+                self.stack.push(Type::Bool(token.parse().unwrap_or(true)));
+            } else if enclosed('(', ')') {
+                // Push string value on the stack
+                self.stack.push(Type::String(string_value(&token)));
+            } else if enclosed('[', ']') {
+                // Push list value on the stack
+                let old_len = self.stack.len(); // length of old stack
+                let slice = &token[1..token.len() - 1];
+                self.evaluate_program(slice.to_string())?;
+                // Make increment of stack an element of list
+                let mut list = Vec::new();
+                for _ in old_len..self.stack.len() {
+                    list.push(self.pop_stack());
+                }
+                list.reverse(); // reverse list
+                self.stack.push(Type::List(list));
+            } else if enclosed('{', '}') {
+                // Push object value made from the pairs of key and value
+                let old_len = self.stack.len();
+                let slice = &token[1..token.len() - 1];
+                self.evaluate_program(slice.to_string())?;
+                let mut items = self.stack.split_off(old_len.min(self.stack.len()));
+                if items.len() % 2 == 1 {
+                    self.log_error("the object literal needs pairs of key and value".to_string());
+                    self.stack.push(Type::error("object-literal"));
+                    self.stamp_error();
+                } else {
+                    let object: IndexMap<String, Type> = items
+                        .chunks_exact_mut(2)
+                        .map(|pair| (pair[0].get_string(), pair[1].clone()))
+                        .collect();
+                    self.stack.push(Type::Object("object".to_string(), object));
+                }
+            } else if token.starts_with("error:") {
+                // Push error value on the stack
+                self.stack.push(Type::error(&token.replace("error:", "")));
+                self.stamp_error();
+            } else if let Some(i) = self.variable(&token) {
+                // Push variable's data on stack
+                self.stack.push(i.clone());
+            } else if let Some(i) = self.property_path(&token) {
+                // Push the property of object in the variable
+                self.stack.push(i);
+            } else if enclosed('#', '#') {
+                // Processing comments
+                self.log_print(format!("* Comment \"{}\"\n", token.replace('#', "")));
+            } else {
+                // Else, execute as command
+                self.last_message = None;
+                self.execute_command(token.clone())?;
+                // The new error leaves the try block
+                if self.stamp_error() && self.trying > 0 {
+                    return Err(StackError::Raise);
+                }
+            }
+            self.write_trace(&token, start.elapsed());
+            self.record_snapshot(&token);
+            self.sample_memory();
+            self.run_hooks(false, &token)?;
+
+            if let Some(err) = self.failure.take() {
+                return Err(err);
+            }
+        }
+
+        (self.command, self.token) = origin;
+
+        // Show inside stack, after execution
+        if let Mode::Debug = self.mode {
+            let stack = self.show_stack();
+            self.log_print(format!("{stack}\n"));
+        }
+        Ok(())
+    }
+
+    /// execute string as commands
+    fn execute_command(&mut self, command: Symbol) -> Result<(), StackError> {
+        let command = match self.aliases.get(&*command) {
+            Some(alias) => Symbol::from(alias.as_str()),
+            None => command,
+        };
+
+        // Inside its own wrapper, the command means the original one
+        if let Some(code) = self.wrappers.get(&*command).cloned() {
+            if !self.wrapping.contains(&command) {
+                self.wrapping.push(command);
+                let result = self.evaluate_program(code);
+                self.wrapping.pop();
+                return result;
+            }
+        }
+        if self.replay(&command) {
+            return Ok(());
+        }
+        let denied = self
+            .permissions
+            .as_ref()
+            .and_then(|x| x.check(&command, &self.stack));
+        if let Some((access, target)) = denied {
+            let flag = access.name();
+            let msg = match target {
+                Some(target) => format!("`{command}` needs --allow-{flag} for \"{target}\""),
+                None => format!("`{command}` needs --allow-{flag}"),
+            };
+            self.refuse(&command, msg, "permission-denied");
+            return Ok(());
+        }
+        if self.deterministic.is_some() && registry::is_nondeterministic(&command) {
+            let msg = format!("`{command}` is not allowed in the deterministic mode");
+            self.refuse(&command, msg, "nondeterministic");
+            return Ok(());
+        }
+        if !self.confirm_command(&command) {
+            let msg = format!("`{command}` is cancelled by the user");
+            self.refuse(&command, msg, "cancelled");
+            return Ok(());
+        }
+        self.write_audit(&command);
+        let recording = self
+            .cassette
+            .as_ref()
+            .is_some_and(|x| x.lock().is_ok_and(|x| !x.is_replaying()));
+        if !recording || !cassette::COMMANDS.contains(&&*command) {
+            return functions::execute_command(self, &command);
+        }
+        let args = self.arguments(&command).to_vec();
+        functions::execute_command(self, &command)?;
+        self.record(&command, &args);
+        Ok(())
+    }
+
+    /// Push the recorded result instead of running the command, when the cassette is replayed
+    fn replay(&mut self, command: &str) -> bool {
+        let replaying = self
+            .cassette
+            .as_ref()
+            .is_some_and(|x| x.lock().is_ok_and(|x| x.is_replaying()));
+        if !replaying || !cassette::COMMANDS.contains(&command) {
+            return false;
+        }
+        let args = self.arguments(command).to_vec();
+        let result = self
+            .cassette
+            .as_ref()
+            .and_then(|x| x.lock().ok()?.take(command, &args));
+        match result {
+            Some(result) => {
+                self.stack.truncate(self.stack.len() - args.len());
+                self.stack.push(result);
+            }
+            None => {
+                let msg = format!("no recorded result of `{command}` in the cassette");
+                self.refuse(command, msg, "cassette");
+            }
+        }
+        true
+    }
+
+    /// Keep the result of the command in the cassette being recorded
+    fn record(&mut self, command: &str, args: &[Type]) {
+        let (Some(cassette), Some(result)) = (&self.cassette, self.stack.last()) else {
+            return;
+        };
+        let Ok(added) = cassette.lock().map(|mut x| x.add(command, args, result)) else {
+            return;
+        };
+        if let Err(e) = added {
+            self.log_internal_error(e.to_string());
+        }
+    }
+
+    /// Stop the command from running, taking the arguments as it does on failure
+    fn refuse(&mut self, command: &str, msg: String, code: &str) {
+        let arity = registry::find(command).map_or(0, |info| info.arity);
+        for _ in 0..arity.min(self.stack.len()) {
+            self.stack.pop();
+        }
+        self.log_error(msg);
+        self.stack.push(Type::error(code));
+    }
+
+    /// Ask the user before the command runs, and tell whether it is allowed
+    fn confirm_command(&mut self, command: &str) -> bool {
+        if !self.confirm.contains(command) {
+            return true;
+        }
+        let target = self.stack.last().map(|x| x.clone().get_string());
+        let target = target.unwrap_or_default();
+        // Writing the new file destroys nothing
+        if command == "write-file" && !Path::new(&target).exists() {
+            return true;
+        }
+        let prompt = i18n::translate(&format!("Run `{command}` on \"{target}\"? [y/N] "));
+        let answer = input(&prompt);
+        matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    }
+
+    /// Pop stack's top value
+    pub fn pop_stack(&mut self) -> Type {
+        if let Some(value) = self.stack.pop() {
+            value
+        } else {
+            self.log_error(
+                "There are not enough values on the stack. returns default value".to_string(),
+            );
+            Type::String("".to_string())
+        }
+    }
+}
+
+/// Get the approximate bytes of the value
+fn approximate_size(value: &Type) -> usize {
+    std::mem::size_of::<Type>()
+        + match value {
+            Type::String(s) => s.len(),
+            Type::Error(e) => {
+                e.code.len() + e.message.len() + e.payload.as_deref().map_or(0, approximate_size)
+            }
+            Type::List(list) => list.iter().map(approximate_size).sum(),
+            Type::PList(list) => list.iter().map(approximate_size).sum(),
+            Type::Object(name, object) => {
+                name.len()
+                    + object
+                        .iter()
+                        .map(|(key, value)| key.len() + approximate_size(value))
+                        .sum::<usize>()
+            }
+            Type::Function(params, body) => {
+                params.iter().map(String::len).sum::<usize>() + body.len()
+            }
+            Type::Number(_) | Type::Bool(_) => 0,
+        }
+}
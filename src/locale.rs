@@ -0,0 +1,37 @@
+/// Parse the number written with the decimal separator and the thousands grouping
+pub fn parse_number(text: &str, decimal: char) -> Option<f64> {
+    let mut number = String::new();
+    for c in text.trim().chars() {
+        match c {
+            '0'..='9' | '-' | '+' => number.push(c),
+            c if c == decimal => number.push('.'),
+            // Grouping separators and currency symbols are skipped
+            '.' | ',' | ' ' | '\'' | '_' | '\u{a0}' | '\u{202f}' => {}
+            c if !c.is_alphanumeric() => {}
+            _ => return None,
+        }
+    }
+    number.parse().ok()
+}
+
+/// Format the amount of money with the currency symbol
+pub fn format_currency(amount: f64, symbol: &str, decimal: char) -> String {
+    let grouping = if decimal == ',' { '.' } else { ',' };
+    let text = format!("{:.2}", amount.abs());
+    let (integer, fraction) = text.split_once('.').unwrap_or((&text, "00"));
+
+    let mut grouped = String::new();
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(grouping);
+        }
+        grouped.push(c);
+    }
+
+    let sign = if amount < 0.0 && text != "0.00" {
+        "-"
+    } else {
+        ""
+    };
+    format!("{sign}{symbol}{grouped}{decimal}{fraction}")
+}
@@ -1,463 +1,1178 @@
-use clap::{App, Arg};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Error, Read, Write};
-use std::path::Path;
-mod functions;
+use chrono::DateTime;
+use clap::{App, Arg, ArgGroup, ArgMatches};
+use stack::cassette::Cassette;
+use stack::diagnostics::{format, ColorChoice, Severity};
+use stack::i18n::{self, Lang};
+use stack::permission::{Access, Permissions};
+use stack::registry;
+use stack::{
+    bench, check, compile, doc, fmt, get_file_contents, input, protocol, Capture, Determinism,
+    Executor, Mode, Snapshot, StackError, Type,
+};
+use std::any::Any;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+mod bundle;
+mod daemon;
+mod examples;
+#[cfg(feature = "jupyter")]
+mod jupyter;
+mod schedule;
+mod visualize;
 
-#[cfg(test)]
-mod test;
+const VERSION: &str = "1.12.0";
+const UNDO_LIMIT: usize = 100; // Evaluations kept to undo in the REPL
 
 fn main() {
+    // Messages are in the language of the environment unless specified
+    if let Some(lang) = env::var("STACK_LANG").ok().as_deref().and_then(Lang::parse) {
+        i18n::set_lang(lang);
+    }
+
+    // Run the embedded program instead, if it is a bundled executable
+    if let Some(program) = bundle::embedded() {
+        let mut stack = Executor::new(Mode::Script);
+        let result = match compile::load(&program) {
+            Some(syntax) => stack.evaluate_tokens(syntax),
+            None => stack.evaluate_program(program),
+        };
+        process::exit(finish(&stack, result));
+    }
+
+    let matches = app().get_matches();
+    if let Some(lang) = matches.value_of("lang").and_then(Lang::parse) {
+        i18n::set_lang(lang);
+    }
+
+    match matches.subcommand() {
+        Some(("run", sub)) => run(sub),
+        Some(("repl", sub)) => repl(sub),
+        Some(("check", sub)) => check(sub),
+        Some(("fmt", sub)) => format_scripts(sub),
+        Some(("doc", sub)) => generate_docs(sub),
+        Some(("test", sub)) => test(sub),
+        Some(("bench-self", sub)) => bench_self(sub),
+        #[cfg(feature = "jupyter")]
+        Some(("jupyter-kernel", sub)) => jupyter_kernel(sub),
+        Some(("visualize", sub)) => {
+            let script = sub.value_of("script").unwrap_or_default();
+            let mut stack = new_executor(Mode::Script, sub);
+            let code = read_script(&stack, script);
+            if let Err(err) = visualize::run(&mut stack, code) {
+                print!(
+                    "{}",
+                    stack.format_error(Severity::Internal, &err.to_string())
+                );
+                process::exit(1);
+            }
+        }
+        Some(("compile", sub)) => {
+            let script = sub.value_of("script").unwrap_or_default();
+            let stack = new_executor(Mode::Script, sub);
+            let output = match sub.value_of("output") {
+                Some(output) => PathBuf::from(output),
+                None => Path::new(script).with_extension("stkc"),
+            };
+            let compiled = compile::compile(read_script(&stack, script));
+            if let Err(err) = fs::write(&output, compiled) {
+                let msg = format!("{}: {err}", output.display());
+                print!("{}", stack.format_error(Severity::User, &msg));
+                process::exit(1);
+            }
+        }
+        Some(("bundle", sub)) => {
+            let script = sub.value_of("script").unwrap_or_default();
+            let output = sub.value_of("output").unwrap_or_default();
+            let stack = new_executor(Mode::Script, sub);
+            let compiled = compile::compile(read_script(&stack, script));
+            if let Err(err) = bundle::create(compiled, Path::new(output)) {
+                let msg = format!("{output}: {err}");
+                print!("{}", stack.format_error(Severity::User, &msg));
+                process::exit(1);
+            }
+        }
+        Some(("schedule", sub)) => {
+            let expr = sub.value_of("cron").unwrap_or_default();
+            let script = sub.value_of("script").unwrap_or_default();
+            let mode = mode_of(sub);
+            let stack = new_executor(mode.clone(), sub);
+            let schedule = match schedule::parse(expr) {
+                Ok(schedule) => schedule,
+                Err(err) => {
+                    let msg = format!("{expr}: {err}");
+                    print!("{}", stack.format_error(Severity::User, &msg));
+                    process::exit(1);
+                }
+            };
+            // Read the script every time, so that the changes are applied
+            schedule::run(&schedule, || {
+                let mut stack = new_executor(mode.clone(), sub);
+                stack.strict = sub.is_present("strict");
+                stack.dry_run = sub.is_present("dry-run");
+                confirm_destructive(&mut stack, sub);
+                permit(&mut stack, sub);
+                let code = read_script(&stack, script);
+                let result = stack.evaluate_program(code);
+                finish(&stack, result)
+            });
+        }
+        Some(("examples", sub)) => {
+            if let Some(run) = sub.subcommand_matches("run") {
+                let name = run.value_of("name").unwrap_or_default();
+                let mut stack = new_executor(Mode::Script, run);
+                let example = match examples::find(name) {
+                    Some(example) => example,
+                    None => {
+                        let msg = format!("unknown example \"{name}\"");
+                        print!("{}", stack.format_error(Severity::User, &msg));
+                        process::exit(1);
+                    }
+                };
+                let result = stack.evaluate_program(example.code.to_string());
+                process::exit(finish(&stack, result));
+            }
+            for example in examples::EXAMPLES {
+                println!("{:<10} {}", example.name, example.about);
+            }
+            println!("\nRun one with `stack examples run <NAME>`");
+        }
+        // The script without the subcommand is run as before
+        _ if matches.is_present("script") || matches.is_present("one-liner") => run(&matches),
+        _ => repl(&matches),
+    }
+}
+
+/// Define the command-line interface
+fn app() -> App<'static> {
     let app = App::new("Stack")
-        .version("1.12.0")
+        .version(VERSION)
         .author("Stack Programming Community")
         .about("Powerful script language with stack-oriented approach")
-        .arg(Arg::new("script")
+        .arg(Arg::new("color")
+            .long("color")
+            .value_name("WHEN")
+            .possible_values(["always", "never", "auto"])
+            .default_value("auto")
+            .help("Colorizes the error messages")
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::new("lang")
+            .long("lang")
+            .value_name("LANG")
+            .possible_values(["en", "ja"])
+            .help("Sets the language of the messages (default: $STACK_LANG or en)")
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::new("plugin")
+            .long("plugin")
+            .value_name("PATH")
+            .help("Loads the commands from the plugin library")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .global(true))
+        // Shorthands of `stack run FILE` and `stack repl`
+        .args(run_args().into_iter().map(|arg| arg.hide(true)))
+        .args(repl_args().into_iter().map(|arg| arg.hide(true)))
+        .subcommand(App::new("run")
+            .about("Runs the script file or the one-liner")
+            .args(run_args())
+            .group(
+                ArgGroup::new("program")
+                    .args(&["script", "one-liner"])
+                    .required(true),
+            ))
+        .subcommand(App::new("repl")
+            .about("Starts the interactive session (default without the script)")
+            .args(repl_args())
+            .args(trace_args()))
+        .subcommand(App::new("check")
+            .about("Checks that the brackets and comments of the scripts are closed")
+            .arg(Arg::new("scripts")
+                .index(1)
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the script files to check")
+                .takes_value(true)
+                .multiple_values(true)))
+        .subcommand(App::new("fmt")
+            .about("Formats the indentation and blank lines of the scripts")
+            .arg(Arg::new("scripts")
+                .index(1)
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the script files to format")
+                .takes_value(true)
+                .multiple_values(true))
+            .arg(Arg::new("check")
+                .long("check")
+                .help("Lists the unformatted files instead of writing them")))
+        .subcommand(App::new("doc")
+            .about("Generates the reference pages of the built-in commands")
+            .arg(Arg::new("out")
+                .long("out")
+                .value_name("DIR")
+                .default_value("docs")
+                .help("Sets the folder to write the pages")
+                .takes_value(true))
+            .arg(Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .default_value("markdown")
+                .possible_values(["markdown", "html"])
+                .help("Sets the format of the pages")
+                .takes_value(true)))
+        .subcommand(App::new("test")
+            .about("Runs the test scripts, which fail on an error value or nonzero exit")
+            .arg(Arg::new("paths")
+                .index(1)
+                .value_name("PATH")
+                .default_value("tests")
+                .help("Sets the test scripts or the folders of *.stk files")
+                .takes_value(true)
+                .multiple_values(true))
+            .arg(Arg::new("golden")
+                .long("golden")
+                .help("Compares the output of each script with the sibling .expected file"))
+            .arg(Arg::new("bless")
+                .long("bless")
+                .help("Writes the output into the .expected files instead of comparing"))
+            .args(deterministic_args()))
+        .subcommand(App::new("visualize")
+            .about("Steps through the stack and memory of the script")
+            .arg(Arg::new("script")
+                .index(1)
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the script file to visualize")
+                .takes_value(true)))
+        .subcommand(App::new("compile")
+            .about("Compiles the script into the token form")
+            .arg(Arg::new("script")
+                .index(1)
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the script file to compile")
+                .takes_value(true))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Sets the output file (default: FILE.stkc)")
+                .takes_value(true)))
+        .subcommand(App::new("bundle")
+            .about("Makes a standalone executable with the script embedded")
+            .arg(Arg::new("script")
+                .index(1)
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the script file to bundle")
+                .takes_value(true))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the output executable")
+                .takes_value(true)))
+        .subcommand(App::new("schedule")
+            .about("Runs the script repeatedly by the cron expression")
+            .arg(Arg::new("cron")
+                .index(1)
+                .value_name("CRON")
+                .required(true)
+                .help("Sets the schedule like \"*/5 * * * *\"")
+                .takes_value(true))
+            .arg(Arg::new("script")
+                .index(2)
+                .value_name("FILE")
+                .required(true)
+                .help("Sets the script file to run")
+                .takes_value(true))
+            .args(mode_args())
+            .args(permission_args()))
+        .subcommand(App::new("examples")
+            .about("Lists the example programs")
+            .subcommand(App::new("run").about("Runs the example program").arg(Arg::new("name")
+                .index(1)
+                .value_name("NAME")
+                .required(true)
+                .help("Sets the name of the example")
+                .takes_value(true))))
+        .subcommand(App::new("bench-self")
+            .about("Measures the interpreter by the benchmark workloads")
+            .hide(true)
+            .arg(Arg::new("runs")
+                .long("runs")
+                .value_name("N")
+                .default_value("10")
+                .help("Sets the number of runs of each workload")
+                .takes_value(true)));
+    #[cfg(feature = "jupyter")]
+    let app = app.subcommand(App::new("jupyter-kernel")
+        .about("Runs as the Jupyter kernel, or installs its kernel spec")
+        .arg(Arg::new("connection")
             .index(1)
             .value_name("FILE")
-            .help("Sets the script file to execution")
+            .help("Sets the connection file given by Jupyter")
             .takes_value(true))
-        .arg(Arg::new("one-liner")
+        .arg(Arg::new("install")
+            .long("install")
+            .help("Installs the kernel spec for the user"))
+        .group(
+            ArgGroup::new("action")
+                .args(&["connection", "install"])
+                .required(true),
+        ));
+    app
+}
+
+/// Options of `stack run`
+fn run_args() -> Vec<Arg<'static>> {
+    let mut args = vec![
+        Arg::new("script")
+            .index(1)
+            .value_name("FILE")
+            .help("Sets the script file to execution")
+            .takes_value(true),
+        Arg::new("one-liner")
             .long("one-liner")
             .short('l')
+            .short_alias('e')
             .help("One-liner script execution")
-            .takes_value(true))
-        .arg(Arg::new("debug")
+            .takes_value(true),
+        Arg::new("daemon")
+            .long("daemon")
+            .requires("script")
+            .help("Runs the script in the background (default PID file: stack.pid)"),
+        Arg::new("pid-file")
+            .long("pid-file")
+            .value_name("PATH")
+            .requires("script")
+            .help("Writes the process ID, and stops gracefully by the signal")
+            .takes_value(true),
+        Arg::new("show-result")
+            .long("show-result")
+            .help("Prints the values left on the stack when the script finishes"),
+        Arg::new("each-line")
+            .long("each-line")
+            .help("Runs the script once per line of the standard input, which is in `line`"),
+    ];
+    args.extend(mode_args());
+    args.extend(permission_args());
+    args.extend(trace_args());
+    args.extend(deterministic_args());
+    args.extend(cassette_args());
+    args
+}
+
+/// Options of the execution mode
+fn mode_args() -> [Arg<'static>; 4] {
+    [
+        Arg::new("debug")
             .short('d')
             .long("debug")
-            .help("Enables debug mode"));
-    let matches = app.clone().get_matches();
-
-    if let Some(script) = matches.value_of("script") {
-        if matches.is_present("debug") {
-            let mut stack = Executor::new(Mode::Debug);
-            stack.evaluate_program(match get_file_contents(Path::new(&script.to_string())) {
-                Ok(code) => code,
-                Err(err) => {
-                    println!("Error! {err}");
-                    return;
-                }
-            })
-        } else {
-            let mut stack = Executor::new(Mode::Script);
-            stack.evaluate_program(match get_file_contents(Path::new(&script.to_string())) {
-                Ok(code) => code,
-                Err(err) => {
-                    println!("Error! {err}");
-                    return;
-                }
-            })
+            .help("Enables debug mode"),
+        Arg::new("strict")
+            .long("strict")
+            .help("Fails when the script ends with an error value"),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("Reports the file operations instead of doing them"),
+        Arg::new("confirm-destructive")
+            .long("confirm-destructive")
+            .help("Asks before removing or overwriting the files"),
+    ]
+}
+
+/// Options of the secure mode, where the accesses to the outside need to be allowed
+fn permission_args() -> [Arg<'static>; 5] {
+    let allow = |name: &'static str, value: &'static str, help: &'static str| {
+        Arg::new(name)
+            .long(name)
+            .value_name(value)
+            .help(help)
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .use_value_delimiter(true)
+    };
+    [
+        Arg::new("secure")
+            .long("secure")
+            .help("Denies the files, network and processes unless they are allowed"),
+        allow(
+            "allow-read",
+            "PATH",
+            "Allows reading the files under the paths (default: all)",
+        ),
+        allow(
+            "allow-write",
+            "PATH",
+            "Allows writing the files under the paths (default: all)",
+        ),
+        allow(
+            "allow-net",
+            "HOST",
+            "Allows the network access to the hosts (default: all)",
+        ),
+        Arg::new("allow-run")
+            .long("allow-run")
+            .help("Allows running the programs, plugins and foreign functions"),
+    ]
+}
+
+/// Options of observing the execution
+fn trace_args() -> [Arg<'static>; 3] {
+    [
+        Arg::new("trace-file")
+            .long("trace-file")
+            .value_name("PATH")
+            .help("Writes the execution trace as JSON lines")
+            .takes_value(true),
+        Arg::new("debug-mem")
+            .long("debug-mem")
+            .value_name("TOKENS")
+            .help("Reports the stack and memory size every TOKENS (default: 1000)")
+            .takes_value(true)
+            .min_values(0)
+            .default_missing_value("1000"),
+        Arg::new("audit")
+            .long("audit")
+            .value_name("PATH")
+            .help("Appends the file writes, network, processes and clipboard access as JSON lines")
+            .takes_value(true),
+    ]
+}
+
+/// Options of the reproducible runs
+fn deterministic_args() -> [Arg<'static>; 3] {
+    [
+        Arg::new("deterministic")
+            .long("deterministic")
+            .help("Seeds the random numbers, freezes the time and denies the unreproducible commands"),
+        Arg::new("seed")
+            .long("seed")
+            .value_name("NUMBER")
+            .help("Sets the seed of the random numbers in the deterministic mode (default: 0)")
+            .takes_value(true),
+        Arg::new("now")
+            .long("now")
+            .value_name("TIME")
+            .help("Sets the frozen time in the deterministic mode, as unix epoch or ISO 8601 (default: 0)")
+            .takes_value(true),
+    ]
+}
+
+/// Options of the cassette, which keeps the external interactions for the tests
+fn cassette_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("record")
+            .long("record")
+            .value_name("PATH")
+            .conflicts_with("replay")
+            .help("Records the results of request and input into the cassette file")
+            .takes_value(true),
+        Arg::new("replay")
+            .long("replay")
+            .value_name("PATH")
+            .help("Replays the results of request and input from the cassette file, recording it if missing")
+            .takes_value(true),
+    ]
+}
+
+/// Options of `stack repl`
+fn repl_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("no-init")
+            .long("no-init")
+            .help("Skips loading the startup file (~/.stackrc)"),
+        Arg::new("json").long("json").help(
+            "Answers each line of the code with the result in JSON, without the startup file",
+        ),
+    ]
+}
+
+/// Get the execution mode by the options
+fn mode_of(matches: &ArgMatches) -> Mode {
+    if matches.is_present("debug") {
+        Mode::Debug
+    } else {
+        Mode::Script
+    }
+}
+
+/// Run the script file or the one-liner
+fn run(matches: &ArgMatches) {
+    let mut stack = new_executor(mode_of(matches), matches);
+    stack.strict = matches.is_present("strict");
+    stack.dry_run = matches.is_present("dry-run");
+    confirm_destructive(&mut stack, matches);
+    permit(&mut stack, matches);
+    trace(&mut stack, matches);
+    deterministic(&mut stack, matches);
+    cassette(&mut stack, matches);
+
+    let script = match matches.value_of("script") {
+        Some(script) => script,
+        None => {
+            let code = matches.value_of("one-liner").unwrap_or_default();
+            let result = if matches.is_present("each-line") {
+                each_line(&mut stack, code.to_string())
+            } else {
+                stack.evaluate_program(code.to_string())
+            };
+            show_result(&stack, &result, matches);
+            process::exit(finish(&stack, result));
         }
-    } else if let Some(code) = matches.value_of("one-liner") {
-        if matches.is_present("debug") {
-            let mut stack = Executor::new(Mode::Debug);
-            stack.evaluate_program(code.to_string());
+    };
+    let code = read_script(&stack, script);
+
+    // Run as the service which can be stopped by the signal
+    let daemon = matches.is_present("daemon");
+    let pid_file = match matches.value_of("pid-file") {
+        Some(path) => Some(daemon::absolute(path)),
+        None if daemon => Some(daemon::absolute("stack.pid")),
+        None => None,
+    };
+    if let Some(pid_file) = &pid_file {
+        let result = if daemon {
+            daemon::detach(pid_file)
         } else {
-            let mut stack = Executor::new(Mode::Script);
-            stack.evaluate_program(code.to_string());
+            daemon::write_pid(pid_file).map_err(|e| e.to_string())
+        };
+        if let Err(err) = result.and_then(|_| daemon::handle_signals(&stack)) {
+            let msg = format!("{}: {err}", pid_file.display());
+            print!("{}", stack.format_error(Severity::Internal, &msg));
+            process::exit(1);
         }
+    }
+
+    // Skip parsing, if the script is compiled
+    let result = if matches.is_present("each-line") {
+        each_line(&mut stack, code)
     } else {
-        // Show a title
-        println!("Stack Programming Language");
-        println!("Version {}", { app.get_version().unwrap_or("unknown") });
-        let mut executor = Executor::new(Mode::Debug);
-        // REPL Execution
-        loop {
-            let mut code = String::new();
-            loop {
-                let enter = input("> ");
-                code += &format!("{enter}\n");
-                if enter.is_empty() {
-                    break;
-                }
-            }
+        match compile::load(&code) {
+            Some(syntax) => stack.evaluate_tokens(syntax),
+            None => stack.evaluate_program(code),
+        }
+    };
+    show_result(&stack, &result, matches);
+    let status = finish(&stack, result);
+    if let Some(pid_file) = pid_file {
+        let _ = fs::remove_file(pid_file);
+    }
+    process::exit(status);
+}
+
+/// Run the script once per line of the standard input, with the line in `line`
+fn each_line(executor: &mut Executor, code: String) -> Result<(), StackError> {
+    let syntax = match compile::load(&code) {
+        Some(syntax) => syntax,
+        None => executor.analyze_syntax(code),
+    };
+    executor.evaluate_each_line(syntax, io::stdin().lock())
+}
 
-            executor.evaluate_program(code)
+/// Make the commands removing or overwriting the files ask the user
+fn confirm_destructive(executor: &mut Executor, matches: &ArgMatches) {
+    if matches.is_present("confirm-destructive") {
+        for command in ["rm", "write-file"] {
+            executor.confirm.insert(command.to_string());
         }
     }
 }
 
-/// Read string of the file
-fn get_file_contents(name: &Path) -> Result<String, Error> {
-    let mut f = File::open(name)?;
-    let mut contents = String::new();
-    f.read_to_string(&mut contents)?;
-    Ok(contents)
+/// Enter the secure mode by the command-line options, any `--allow-*` enters it as well
+fn permit(executor: &mut Executor, matches: &ArgMatches) {
+    let flags = [
+        ("allow-read", Access::Read),
+        ("allow-write", Access::Write),
+        ("allow-net", Access::Net),
+        ("allow-run", Access::Run),
+    ];
+    let secure = matches.is_present("secure");
+    if !secure && !flags.iter().any(|(flag, _)| matches.is_present(flag)) {
+        return;
+    }
+    let mut permissions = Permissions::default();
+    for (flag, access) in flags {
+        if matches.is_present(flag) {
+            let values: Vec<&str> = matches.values_of(flag).into_iter().flatten().collect();
+            permissions.allow(access, &values);
+        }
+    }
+    executor.permissions = Some(permissions);
 }
 
-/// Get standard input
-fn input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().unwrap();
-    let mut result = String::new();
-    io::stdin().read_line(&mut result).ok();
-    result.trim().to_string()
+/// Print the stack after the script, unless it exited
+fn show_result(executor: &Executor, result: &Result<(), StackError>, matches: &ArgMatches) {
+    if matches.is_present("show-result") && !matches!(result, Err(StackError::Exit(_))) {
+        for value in &executor.stack {
+            println!("{}", value.inspect());
+        }
+    }
 }
 
-/// Execution Mode
-#[derive(Clone, Debug)]
-enum Mode {
-    Script, // Script execution
-    Debug,  // Debug execution
+/// Start the interactive session
+fn repl(matches: &ArgMatches) {
+    if matches.is_present("json") {
+        serve_json(matches);
+        return;
+    }
+
+    // Show a title
+    println!("Stack Programming Language");
+    println!("Version {VERSION}");
+    let mut executor = new_executor(Mode::Debug, matches);
+    trace(&mut executor, matches);
+    silence_panics();
+    if !matches.is_present("no-init") {
+        load_init_file(&mut executor);
+    }
+    // REPL Execution
+    let mut history: Vec<Snapshot> = Vec::new();
+    loop {
+        let mut code = String::new();
+        loop {
+            let enter = input(&executor.options.prompt.clone());
+            code += &format!("{enter}\n");
+            if enter.is_empty() {
+                break;
+            }
+        }
+        if let Some(command) = code.trim().strip_prefix(':') {
+            repl_command(&mut executor, &mut history, command);
+            continue;
+        }
+
+        // Remember the state before the evaluation to undo it
+        if history.len() >= UNDO_LIMIT {
+            history.remove(0);
+        }
+        history.push(Snapshot {
+            token: code.clone(),
+            stack: executor.stack.clone(),
+            memory: executor.memory.clone(),
+        });
+        let top = top_of_stack(&executor);
+
+        // Keep the session alive even if the command panicked
+        match panic::catch_unwind(AssertUnwindSafe(|| executor.evaluate_program(code))) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => report(&executor, err),
+            Err(payload) => {
+                let msg = format!("the command panicked: {}", panic_message(&payload));
+                print!("{}", executor.format_error(Severity::Internal, &msg));
+                executor.recover();
+            }
+        }
+        if top_of_stack(&executor) != top {
+            echo_result(&mut executor);
+        }
+    }
 }
 
-/// Data type
-#[derive(Clone, Debug)]
-enum Type {
-    Number(f64),
-    String(String),
-    Bool(bool),
-    List(Vec<Type>),
-    Object(String, HashMap<String, Type>),
-    Error(String),
+/// Get the size and the top of the stack, to find the result of the evaluation
+fn top_of_stack(executor: &Executor) -> (usize, Option<String>) {
+    (
+        executor.stack.len(),
+        executor.stack.last().map(Type::literal),
+    )
 }
 
-/// Implement methods
-impl Type {
-    /// Show data to display
-    fn display(&self) -> String {
-        match self {
-            Type::Number(num) => num.to_string(),
-            Type::String(s) => format!("({})", s),
-            Type::Bool(b) => b.to_string(),
-            Type::List(list) => {
-                let result: Vec<String> = list.iter().map(|token| token.display()).collect();
-                format!("[{}]", result.join(" "))
+/// Show the new top of the stack, and keep the last results as `it`, `it2` and `it3`
+fn echo_result(executor: &mut Executor) {
+    let Some(value) = executor.stack.last().cloned() else {
+        return;
+    };
+    let text = format!("= {}", value.display());
+    if executor.color {
+        println!("\x1b[2m{text}\x1b[0m");
+    } else {
+        println!("{text}");
+    }
+
+    let memory = &mut executor.memory;
+    if let Some(it2) = memory.get("it2").cloned() {
+        memory.insert("it3".into(), it2);
+    }
+    if let Some(it) = memory.get("it").cloned() {
+        memory.insert("it2".into(), it);
+    }
+    memory.insert("it".into(), value);
+}
+
+/// Run the command of the REPL itself, written after `:`
+fn repl_command(executor: &mut Executor, history: &mut Vec<Snapshot>, command: &str) {
+    let command = command.trim();
+    let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        // Roll back the last evaluation
+        "undo" => match history.pop() {
+            Some(snapshot) => {
+                executor.stack = snapshot.stack;
+                executor.memory = snapshot.memory;
+                println!("{}", executor.show_stack());
+            }
+            None => print!(
+                "{}",
+                format(Severity::User, "nothing to undo", None, executor.color)
+            ),
+        },
+
+        // Search the commands by the word
+        "apropos" => {
+            let found = registry::search(arg);
+            if found.is_empty() {
+                let msg = format!("no command matches \"{}\"", arg.trim());
+                print!("{}", format(Severity::User, &msg, None, executor.color));
             }
-            Type::Error(err) => format!("error:{err}"),
-            Type::Object(name, _) => {
-                format!("Object<{name}>")
+            for info in found {
+                println!(
+                    "{} ({}, {}): {}",
+                    info.name, info.category, info.arity, info.doc
+                );
             }
         }
+        _ => {
+            let msg = format!("unknown REPL command \":{name}\"");
+            print!("{}", format(Severity::User, &msg, None, executor.color));
+        }
     }
+}
 
-    /// Get string form data
-    fn get_string(&mut self) -> String {
-        match self {
-            Type::String(s) => s.to_string(),
-            Type::Number(i) => i.to_string(),
-            Type::Bool(b) => b.to_string(),
-            Type::List(l) => Type::List(l.to_owned()).display(),
-            Type::Error(err) => format!("error:{err}"),
-            Type::Object(name, _) => {
-                format!("Object<{name}>")
+/// Answer the code of each line with the result in JSON, for the editors and notebooks
+fn serve_json(matches: &ArgMatches) {
+    let mut executor = new_executor(Mode::Script, matches);
+    executor.color = false;
+    trace(&mut executor, matches);
+    silence_panics();
+
+    for line in io::stdin().lines() {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| {
+            protocol::evaluate(&mut executor, protocol::parse_request(&line))
+        })) {
+            Ok(response) => response,
+            Err(payload) => {
+                executor.recover();
+                let mut response = protocol::respond(&mut executor);
+                let msg = format!("the command panicked: {}", panic_message(&payload));
+                response.errors.push(i18n::translate(&msg));
+                response
             }
+        };
+        println!("{}", response.to_json());
+        if let Some(status) = response.exit {
+            process::exit(status);
         }
     }
+}
 
-    /// Get number from data
-    fn get_number(&mut self) -> f64 {
-        match self {
-            Type::String(s) => s.parse().unwrap_or(0.0),
-            Type::Number(i) => *i,
-            Type::Bool(b) => {
-                if *b {
-                    1.0
-                } else {
-                    0.0
-                }
+/// Keep the panic in the main thread quiet, it is reported as the error instead
+fn silence_panics() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() != Some("main") {
+            default_hook(info);
+        }
+    }));
+}
+
+/// Serve the notebook as the Jupyter kernel
+#[cfg(feature = "jupyter")]
+fn jupyter_kernel(matches: &ArgMatches) {
+    let mut executor = new_executor(Mode::Script, matches);
+    executor.color = false;
+    let result = match matches.value_of("connection") {
+        Some(connection) => jupyter::run(Path::new(connection), executor.clone(), VERSION),
+        None => jupyter::install().map(|path| println!("Installed {}", path.display())),
+    };
+    if let Err(err) = result {
+        print!("{}", executor.format_error(Severity::Internal, &err));
+        process::exit(1);
+    }
+}
+
+/// Report the problems of the scripts without running them
+fn check(matches: &ArgMatches) {
+    let executor = new_executor(Mode::Script, matches);
+    let mut failed = false;
+    for script in matches.values_of("scripts").into_iter().flatten() {
+        let code = read_script(&executor, script);
+        for problem in check::check(&code) {
+            let msg = format!(
+                "{script}:{}:{}: {}",
+                problem.line,
+                problem.column,
+                i18n::translate(&problem.message)
+            );
+            print!("{}", executor.format_error(Severity::User, &msg));
+            failed = true;
+        }
+    }
+    process::exit(failed as i32);
+}
+
+/// Format the scripts in place, or list the unformatted ones
+fn format_scripts(matches: &ArgMatches) {
+    let executor = new_executor(Mode::Script, matches);
+    let mut unformatted = false;
+    for script in matches.values_of("scripts").into_iter().flatten() {
+        let code = read_script(&executor, script);
+        let formatted = fmt::format(&code);
+        if formatted == code {
+            continue;
+        }
+        if matches.is_present("check") {
+            println!("{script}");
+            unformatted = true;
+        } else if let Err(err) = fs::write(script, formatted) {
+            let msg = format!("{script}: {err}");
+            print!("{}", executor.format_error(Severity::User, &msg));
+            process::exit(1);
+        }
+    }
+    process::exit(unformatted as i32);
+}
+
+/// Write the reference pages of the command registry into the folder
+fn generate_docs(matches: &ArgMatches) {
+    let executor = new_executor(Mode::Script, matches);
+    let out = Path::new(matches.value_of("out").unwrap_or("docs"));
+    let format = matches
+        .value_of("format")
+        .and_then(doc::Format::parse)
+        .unwrap_or(doc::Format::Markdown);
+    let written = fs::create_dir_all(out).and_then(|()| {
+        doc::pages(format)
+            .into_iter()
+            .try_for_each(|(name, page)| fs::write(out.join(name), page))
+    });
+    if let Err(err) = written {
+        let msg = format!("{}: {err}", out.display());
+        print!("{}", executor.format_error(Severity::User, &msg));
+        process::exit(1);
+    }
+}
+
+/// Measure the mean time of each benchmark workload, the quick check without criterion
+fn bench_self(matches: &ArgMatches) {
+    let executor = new_executor(Mode::Script, matches);
+    let runs = matches.value_of("runs").unwrap_or("10");
+    let runs: usize = match runs.parse() {
+        Ok(runs) => runs,
+        Err(_) => {
+            let msg = format!("\"{runs}\" is not a number");
+            print!("{}", executor.format_error(Severity::User, &msg));
+            process::exit(1);
+        }
+    };
+    for workload in bench::WORKLOADS {
+        match workload.measure(runs) {
+            Ok(mean) => println!("{:<16} {mean:>12.3?}", workload.name),
+            Err(err) => {
+                let msg = format!("{}: {err}", workload.name);
+                print!("{}", executor.format_error(Severity::Internal, &msg));
+                process::exit(1);
             }
-            Type::List(l) => l.len() as f64,
-            Type::Error(e) => e.parse().unwrap_or(0f64),
-            Type::Object(_, object) => object.len() as f64,
         }
     }
+}
 
-    /// Get bool from data
-    fn get_bool(&mut self) -> bool {
-        match self {
-            Type::String(s) => !s.is_empty(),
-            Type::Number(i) => *i != 0.0,
-            Type::Bool(b) => *b,
-            Type::List(l) => !l.is_empty(),
-            Type::Error(e) => e.parse().unwrap_or(false),
-            Type::Object(_, object) => object.is_empty(),
+/// Run every test script in the strict mode, and summarize the results
+fn test(matches: &ArgMatches) {
+    let executor = new_executor(Mode::Script, matches);
+    let mut scripts = Vec::new();
+    for path in matches.values_of("paths").into_iter().flatten() {
+        let path = PathBuf::from(path);
+        if !path.is_dir() {
+            scripts.push(path);
+            continue;
+        }
+        match fs::read_dir(&path) {
+            Ok(entries) => {
+                let mut found: Vec<PathBuf> = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "stk"))
+                    .collect();
+                found.sort();
+                scripts.extend(found);
+            }
+            Err(err) => {
+                let msg = format!("{}: {err}", path.display());
+                print!("{}", executor.format_error(Severity::User, &msg));
+                process::exit(1);
+            }
         }
     }
 
-    /// Get list form data
-    fn get_list(&mut self) -> Vec<Type> {
-        match self {
-            Type::String(s) => s
-                .to_string()
-                .chars()
-                .map(|x| Type::String(x.to_string()))
-                .collect::<Vec<Type>>(),
-            Type::Number(i) => vec![Type::Number(*i)],
-            Type::Bool(b) => vec![Type::Bool(*b)],
-            Type::List(l) => l.to_vec(),
-            Type::Error(e) => vec![Type::Error(e.to_string())],
-            Type::Object(_, object) => object.values().map(|x| x.to_owned()).collect::<Vec<Type>>(),
+    let golden = matches.is_present("golden") || matches.is_present("bless");
+    let mut failed = Vec::new();
+    for script in &scripts {
+        let (passed, note) = if golden {
+            match golden_test(script, matches) {
+                Ok(()) => (true, None),
+                Err(note) => (false, Some(note)),
+            }
+        } else {
+            let mut stack = new_executor(Mode::Script, matches);
+            stack.strict = true;
+            deterministic(&mut stack, matches);
+            let code = read_script(&stack, &script.to_string_lossy());
+            let result = stack.evaluate_program(code);
+            (finish(&stack, result) == 0, None)
+        };
+        println!(
+            "test {} ... {}",
+            script.display(),
+            if passed { "ok" } else { "FAILED" }
+        );
+        if let Some(note) = note {
+            println!("    {note}");
+        }
+        if !passed {
+            failed.push(script);
         }
     }
+    println!(
+        "\ntest result: {} passed; {} failed",
+        scripts.len() - failed.len(),
+        failed.len()
+    );
+    process::exit(!failed.is_empty() as i32);
+}
 
-    fn get_object(&self) -> (String, HashMap<String, Type>) {
-        match self {
-            Type::Object(name, value) => (name.to_owned(), value.to_owned()),
-            _ => ("".to_string(), HashMap::new()),
+/// Run the script and compare the printed text with the `.expected` file next to it
+///
+/// The file is written instead with `--bless`, to make or update the expectation.
+fn golden_test(script: &Path, matches: &ArgMatches) -> Result<(), String> {
+    let mut stack = new_executor(Mode::Script, matches);
+    stack.capture = Some(Capture::default());
+    deterministic(&mut stack, matches);
+    let code = read_script(&stack, &script.to_string_lossy());
+    // The errors are judged by the printed text only
+    let _ = stack.evaluate_program(code);
+    let output = stack.capture.take().unwrap_or_default().stdout;
+
+    let path = script.with_extension("expected");
+    if matches.is_present("bless") {
+        return fs::write(&path, output).map_err(|err| format!("{}: {err}", path.display()));
+    }
+    let expected = match fs::read_to_string(&path) {
+        Ok(expected) => expected,
+        Err(err) => {
+            return Err(format!(
+                "{}: {err}, run with --bless to make it",
+                path.display()
+            ))
+        }
+    };
+    if output == expected {
+        return Ok(());
+    }
+    // Show the first line different from the expectation
+    let mut actual_lines = output.lines();
+    let mut expected_lines = expected.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(x), Some(y)) if x == y => line += 1,
+            (x, y) => {
+                return Err(format!(
+                    "line {line}: expected {:?} but got {:?}",
+                    x.unwrap_or("<end>"),
+                    y.unwrap_or("<end>")
+                ))
+            }
         }
     }
 }
 
-/// Manage program execution
-#[derive(Clone, Debug)]
-struct Executor {
-    stack: Vec<Type>,              // Data stack
-    memory: HashMap<String, Type>, // Variable's memory
-    mode: Mode,                    // Execution mode
+/// Read the script file, or exit with the error
+fn read_script(executor: &Executor, script: &str) -> String {
+    match get_file_contents(Path::new(script)) {
+        Ok(code) => code,
+        Err(err) => {
+            let msg = format!("{script}: {err}");
+            print!("{}", executor.format_error(Severity::User, &msg));
+            process::exit(1);
+        }
+    }
 }
 
-impl Executor {
-    /// Constructor
-    fn new(mode: Mode) -> Executor {
-        Executor {
-            stack: Vec::new(),
-            memory: HashMap::new(),
-            mode,
+/// Get the exit status from the result of the script
+fn finish(executor: &Executor, result: Result<(), StackError>) -> i32 {
+    match result {
+        Ok(()) => executor.exit_status(),
+        Err(StackError::Exit(status)) => status,
+        Err(err) => {
+            report(executor, err);
+            1
         }
     }
+}
 
-    /// Output log
-    fn log_print(&mut self, msg: String) {
-        if let Mode::Debug = self.mode {
+/// Show the error which stopped the evaluation
+fn report(executor: &Executor, err: StackError) {
+    match err {
+        StackError::Exit(status) => process::exit(status),
+        err => {
+            let msg = format(Severity::User, &err.to_string(), None, executor.color);
             print!("{msg}");
         }
     }
+}
 
-    /// Show variable inside memory
-    fn show_variables(&mut self) {
-        self.log_print("Variables {\n".to_string());
-        let max = self.memory.keys().map(|s| s.len()).max().unwrap_or(0);
-        for (name, value) in self.memory.clone() {
-            self.log_print(format!(
-                " {:>width$}: {}\n",
-                name,
-                value.display(),
-                width = max
-            ))
+/// Get the message of the panic
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Make the executor configured by the command-line options
+fn new_executor(mode: Mode, matches: &ArgMatches) -> Executor {
+    let mut executor = Executor::new(mode);
+    let color = matches
+        .value_of("color")
+        .and_then(ColorChoice::parse)
+        .unwrap_or(ColorChoice::Auto);
+    executor.color = color.enabled(executor.interactive);
+    for path in matches.values_of("plugin").into_iter().flatten() {
+        if let Err(err) = executor.load_plugin(Path::new(path)) {
+            print!("{}", executor.format_error(Severity::User, &err));
+            process::exit(1);
         }
-        self.log_print("}\n".to_string())
-    }
-
-    /// Show inside the stack
-    fn show_stack(&mut self) -> String {
-        format!(
-            "Stack〔 {} 〕",
-            self.stack
-                .iter()
-                .map(|x| x.display())
-                .collect::<Vec<_>>()
-                .join(" | ")
-        )
-    }
-
-    /// Parse token by analyzing syntax
-    fn analyze_syntax(&mut self, code: String) -> Vec<String> {
-        // Convert tabs, line breaks, and full-width spaces to half-width spaces
-        let code = code.replace(['\n', '\t', '\r', '　'], " ");
-
-        let mut syntax = Vec::new(); // Token string
-        let mut buffer = String::new(); // Temporary storage
-        let mut brackets = 0; // String's nest structure
-        let mut parentheses = 0; // List's nest structure
-        let mut hash = false; // Is it Comment
-        let mut escape = false; // Flag to indicate next character is escaped
-
-        for c in code.chars() {
-            match c {
-                '\\' if !escape => {
-                    escape = true;
-                }
-                '(' if !hash && !escape => {
-                    brackets += 1;
-                    buffer.push('(');
-                }
-                ')' if !hash && !escape => {
-                    brackets -= 1;
-                    buffer.push(')');
-                }
-                '#' if !hash && !escape => {
-                    hash = true;
-                    buffer.push('#');
-                }
-                '#' if hash && !escape => {
-                    hash = false;
-                    buffer.push('#');
-                }
-                '[' if !hash && brackets == 0 && !escape => {
-                    parentheses += 1;
-                    buffer.push('[');
-                }
-                ']' if !hash && brackets == 0 && !escape => {
-                    parentheses -= 1;
-                    buffer.push(']');
-                }
-                ' ' if !hash && parentheses == 0 && brackets == 0 && !escape => {
-                    if !buffer.is_empty() {
-                        syntax.push(buffer.clone());
-                        buffer.clear();
-                    }
-                }
-                _ => {
-                    if parentheses == 0 && brackets == 0 && !hash {
-                        if escape {
-                            match c {
-                                'n' => buffer.push_str("\\n"),
-                                't' => buffer.push_str("\\t"),
-                                'r' => buffer.push_str("\\r"),
-                                _ => buffer.push(c),
-                            }
-                        } else {
-                            buffer.push(c);
-                        }
-                    } else {
-                        if escape {
-                            buffer.push('\\');
-                        }
-                        buffer.push(c);
-                    }
-                    escape = false; // Reset escape flag for non-escape characters
-                }
+    }
+    executor
+}
+
+/// Set up the trace, audit log and memory report by the command-line options
+fn trace(executor: &mut Executor, matches: &ArgMatches) {
+    if let Some(path) = matches.value_of("trace-file") {
+        match File::create(path) {
+            Ok(file) => executor.trace = Some(Arc::new(Mutex::new(file))),
+            Err(err) => {
+                let msg = format!("{path}: {err}");
+                print!("{}", executor.format_error(Severity::User, &msg));
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = matches.value_of("audit") {
+        match File::options().create(true).append(true).open(path) {
+            Ok(file) => executor.audit = Some(Arc::new(Mutex::new(file))),
+            Err(err) => {
+                let msg = format!("{path}: {err}");
+                print!("{}", executor.format_error(Severity::User, &msg));
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(interval) = matches.value_of("debug-mem") {
+        match interval.parse() {
+            Ok(interval) => executor.memory_sample = Some(interval),
+            Err(err) => {
+                let msg = format!("--debug-mem {interval}: {err}");
+                print!("{}", executor.format_error(Severity::User, &msg));
+                process::exit(1);
             }
         }
+    }
+}
 
-        if !buffer.is_empty() {
-            syntax.push(buffer);
-        }
-        syntax
-    }
-
-    /// evaluate string as program
-    fn evaluate_program(&mut self, code: String) {
-        // Parse into token string
-        let syntax: Vec<String> = self.analyze_syntax(code);
-
-        for token in syntax {
-            // Show inside stack to debug
-            let stack = self.show_stack();
-            self.log_print(format!("{stack} ←  {token}\n"));
-
-            // Character vector for token processing
-            let chars: Vec<char> = token.chars().collect();
-
-            // Judge what the token is
-            if let Ok(i) = token.parse::<f64>() {
-                // Push number value on the stack
-                self.stack.push(Type::Number(i));
-            } else if token == "true" || token == "false" {
-                // Push bool value on the stack
-                self.stack.push(Type::Bool(token.parse().unwrap_or(true)));
-            } else if chars[0] == '(' && chars[chars.len() - 1] == ')' {
-                // Processing string escape
-                let string = {
-                    let mut buffer = String::new(); // Temporary storage
-                    let mut brackets = 0; // String's nest structure
-                    let mut parentheses = 0; // List's nest structure
-                    let mut hash = false; // Is it Comment
-                    let mut escape = false; // Flag to indicate next character is escaped
-
-                    for c in token[1..token.len() - 1].to_string().chars() {
-                        match c {
-                            '\\' if !escape => {
-                                escape = true;
-                            }
-                            '(' if !hash && !escape => {
-                                brackets += 1;
-                                buffer.push('(');
-                            }
-                            ')' if !hash && !escape => {
-                                brackets -= 1;
-                                buffer.push(')');
-                            }
-                            '#' if !hash && !escape => {
-                                hash = true;
-                                buffer.push('#');
-                            }
-                            '#' if hash && !escape => {
-                                hash = false;
-                                buffer.push('#');
-                            }
-                            '[' if !hash && brackets == 0 && !escape => {
-                                parentheses += 1;
-                                buffer.push('[');
-                            }
-                            ']' if !hash && brackets == 0 && !escape => {
-                                parentheses -= 1;
-                                buffer.push(']');
-                            }
-                            _ => {
-                                if parentheses == 0 && brackets == 0 && !hash {
-                                    if escape {
-                                        match c {
-                                            'n' => buffer.push_str("\\n"),
-                                            't' => buffer.push_str("\\t"),
-                                            'r' => buffer.push_str("\\r"),
-                                            _ => buffer.push(c),
-                                        }
-                                    } else {
-                                        buffer.push(c);
-                                    }
-                                } else {
-                                    if escape {
-                                        buffer.push('\\');
-                                    }
-                                    buffer.push(c);
-                                }
-                                escape = false; // Reset escape flag for non-escape characters
-                            }
-                        }
-                    }
-                    buffer
-                }; // Push string value on the stack
-                self.stack.push(Type::String(string));
-            } else if chars[0] == '[' && chars[chars.len() - 1] == ']' {
-                // Push list value on the stack
-                let old_len = self.stack.len(); // length of old stack
-                let slice = &token[1..token.len() - 1];
-                self.evaluate_program(slice.to_string());
-                // Make increment of stack an element of list
-                let mut list = Vec::new();
-                for _ in old_len..self.stack.len() {
-                    list.push(self.pop_stack());
+/// Enter the deterministic mode by the command-line options, `--seed` and `--now` enter it as well
+fn deterministic(executor: &mut Executor, matches: &ArgMatches) {
+    if !["deterministic", "seed", "now"]
+        .iter()
+        .any(|flag| matches.is_present(flag))
+    {
+        return;
+    }
+    let seed = match matches.value_of("seed").map(str::parse) {
+        None => 0,
+        Some(Ok(seed)) => seed,
+        Some(Err(err)) => {
+            let msg = format!(
+                "--seed {}: {err}",
+                matches.value_of("seed").unwrap_or_default()
+            );
+            print!("{}", executor.format_error(Severity::User, &msg));
+            process::exit(1);
+        }
+    };
+    let now = match matches.value_of("now") {
+        None => 0.0,
+        Some(time) => match time.parse::<f64>() {
+            Ok(now) => now,
+            Err(_) => match DateTime::parse_from_rfc3339(time) {
+                Ok(now) => now.timestamp_millis() as f64 / 1000.0,
+                Err(err) => {
+                    let msg = format!("--now {time}: {err}");
+                    print!("{}", executor.format_error(Severity::User, &msg));
+                    process::exit(1);
                 }
-                list.reverse(); // reverse list
-                self.stack.push(Type::List(list));
-            } else if token.starts_with("error:") {
-                // Push error value on the stack
-                self.stack.push(Type::Error(token.replace("error:", "")))
-            } else if let Some(i) = self.memory.get(&token) {
-                // Push variable's data on stack
-                self.stack.push(i.clone());
-            } else if chars[0] == '#' && chars[chars.len() - 1] == '#' {
-                // Processing comments
-                self.log_print(format!("* Comment \"{}\"\n", token.replace('#', "")));
-            } else {
-                // Else, execute as command
-                self.execute_command(token);
+            },
+        },
+    };
+    executor.deterministic = Some(Determinism::new(seed, now));
+}
+
+/// Record or replay the external interactions by the command-line options
+fn cassette(executor: &mut Executor, matches: &ArgMatches) {
+    if let Some(path) = matches.value_of("record") {
+        let cassette = Cassette::record(Path::new(path));
+        executor.cassette = Some(Arc::new(Mutex::new(cassette)));
+    }
+    if let Some(path) = matches.value_of("replay") {
+        match Cassette::replay(Path::new(path)) {
+            Ok(cassette) => executor.cassette = Some(Arc::new(Mutex::new(cassette))),
+            Err(err) => {
+                let msg = format!("{path}: {err}");
+                print!("{}", executor.format_error(Severity::User, &msg));
+                process::exit(1);
             }
         }
+    }
+}
 
-        // Show inside stack, after execution
-        let stack = self.show_stack();
-        self.log_print(format!("{stack}\n"));
+/// Get path of the startup file
+fn init_file_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var("STACK_INIT") {
+        return Some(PathBuf::from(path));
     }
+    env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .ok()
+        .map(|home| Path::new(&home).join(".stackrc"))
+}
 
-    /// execute string as commands
-    fn execute_command(&mut self, command: String) {
-        functions::execute_command(self, command);
+/// Evaluate the startup file before the REPL starts
+fn load_init_file(executor: &mut Executor) {
+    let path = match init_file_path() {
+        Some(path) => path,
+        None => return,
+    };
+    if !path.exists() && env::var("STACK_INIT").is_err() {
+        return;
     }
 
-    /// Pop stack's top value
-    fn pop_stack(&mut self) -> Type {
-        if let Some(value) = self.stack.pop() {
-            value
-        } else {
-            self.log_print(
-                "Error! There are not enough values on the stack. returns default value\n"
-                    .to_string(),
-            );
-            Type::String("".to_string())
-        }
+    if let Err(err) = executor.load_init_file(&path) {
+        report(executor, err);
     }
 }
@@ -0,0 +1,150 @@
+use crate::Type;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+/// Kind of the access to the outside of the interpreter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Net,
+    Run,
+}
+
+impl Access {
+    /// Name used in the command-line flag, like `--allow-read`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Net => "net",
+            Access::Run => "run",
+        }
+    }
+}
+
+/// Accesses granted to the script in the secure mode, everything else is denied
+///
+/// `None` denies the access, an empty list allows all, and the list allows only
+/// the paths under them or the hosts.
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+    read: Option<Vec<PathBuf>>,
+    write: Option<Vec<PathBuf>>,
+    net: Option<Vec<String>>,
+    run: bool,
+}
+
+impl Permissions {
+    /// Allow the access, limited to the paths or hosts if they are given
+    pub fn allow(&mut self, access: Access, values: &[&str]) {
+        match access {
+            Access::Read => grant(&mut self.read, values.iter().map(|x| absolute(x))),
+            Access::Write => grant(&mut self.write, values.iter().map(|x| absolute(x))),
+            Access::Net => grant(&mut self.net, values.iter().map(|x| x.to_string())),
+            Access::Run => self.run = true,
+        }
+    }
+
+    /// Judge is the access to the path or host allowed, `None` target needs the unlimited one
+    pub fn allows(&self, access: Access, target: Option<&str>) -> bool {
+        match access {
+            Access::Read => allows_path(&self.read, target),
+            Access::Write => allows_path(&self.write, target),
+            Access::Net => match (&self.net, target) {
+                (None, _) => false,
+                (Some(hosts), _) if hosts.is_empty() => true,
+                (Some(hosts), Some(url)) => {
+                    let host = host_of(url);
+                    let name = host.split(':').next().unwrap_or_default();
+                    hosts.iter().any(|x| x == host || x == name)
+                }
+                (Some(_), None) => false,
+            },
+            Access::Run => self.run,
+        }
+    }
+
+    /// Find the access the command needs and is not allowed
+    pub fn check(&self, command: &str, stack: &[Type]) -> Option<(Access, Option<String>)> {
+        let arg = |depth: usize| {
+            let index = stack.len().checked_sub(depth + 1)?;
+            Some(stack[index].clone().get_string())
+        };
+        let needs: Vec<(Access, Option<String>)> = match command {
+            "read-file" | "size-file" | "folder" | "dedupe-scan" | "import" => {
+                vec![(Access::Read, arg(0))]
+            }
+            "xlsx-read" => vec![(Access::Read, arg(1))],
+            "diff-files" => vec![(Access::Read, arg(1)), (Access::Read, arg(0))],
+            "ls" => vec![(Access::Read, Some(".".to_string()))],
+            "write-file" | "rm" | "mkdir" | "dump-stack" => vec![(Access::Write, arg(0))],
+            "rename" => vec![(Access::Write, arg(1)), (Access::Write, arg(0))],
+            "cp" => vec![(Access::Read, arg(1)), (Access::Write, arg(0))],
+            "sync-dirs" => vec![(Access::Read, arg(2)), (Access::Write, arg(1))],
+            "kv-open" => vec![(Access::Read, arg(0)), (Access::Write, arg(0))],
+            "get-clipboard" => vec![(Access::Read, None)],
+            "set-clipboard" => vec![(Access::Write, None)],
+            "request" | "request-response" => vec![(Access::Net, arg(0))],
+            "graphql" => vec![(Access::Net, arg(2))],
+            "oauth-token" => vec![(Access::Net, arg(2))],
+            "ipc-listen" | "ipc-connect" | "ipc-send" | "ipc-recv" => vec![(Access::Net, None)],
+            "open" | "load-plugin" | "py-eval" | "py-call" | "ffi-load" | "ffi-call" => {
+                vec![(Access::Run, None)]
+            }
+            _ => Vec::new(),
+        };
+        needs
+            .into_iter()
+            .find(|(access, target)| !self.allows(*access, target.as_deref()))
+    }
+}
+
+/// Add the values to the allowed ones, or allow all if no value is given
+fn grant<T>(allowed: &mut Option<Vec<T>>, values: impl Iterator<Item = T>) {
+    let values: Vec<T> = values.collect();
+    match allowed {
+        // Allowing all is kept
+        Some(list) if list.is_empty() => {}
+        Some(list) if !values.is_empty() => list.extend(values),
+        _ => *allowed = Some(values),
+    }
+}
+
+/// Judge is the path under one of the allowed paths
+fn allows_path(allowed: &Option<Vec<PathBuf>>, target: Option<&str>) -> bool {
+    match (allowed, target) {
+        (None, _) => false,
+        (Some(paths), _) if paths.is_empty() => true,
+        (Some(paths), Some(target)) => {
+            let target = absolute(target);
+            paths.iter().any(|path| target.starts_with(path))
+        }
+        (Some(_), None) => false,
+    }
+}
+
+/// Get the absolute path without `.` and `..`, not following the links
+fn absolute(path: &str) -> PathBuf {
+    let path = env::current_dir().unwrap_or_default().join(Path::new(path));
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+/// Get the host and port of the URL
+fn host_of(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let host = &rest[..end];
+    // Drop the user information
+    host.rsplit_once('@').map_or(host, |(_, host)| host)
+}
@@ -0,0 +1,106 @@
+use crate::{json, Type};
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::Arc;
+
+// The plugin is a dynamic library exporting these C functions:
+//
+//   void stack_plugin_register(void *context, register_fn add);
+//   void stack_plugin_free(char *result);
+//
+// `stack_plugin_register` calls `add(context, name, arity, command)` for each command.
+// The command gets the popped arguments as JSON array, and returns the value to push as JSON.
+// The returned string is released by `stack_plugin_free`, and NULL means failure.
+
+/// Command implemented in the plugin
+pub type CommandFn = unsafe extern "C" fn(args: *const c_char) -> *mut c_char;
+
+/// Release the string returned by the command
+pub type FreeFn = unsafe extern "C" fn(result: *mut c_char);
+
+/// Add the command, given to the plugin on the registration
+pub type AddFn = unsafe extern "C" fn(
+    context: *mut c_void,
+    name: *const c_char,
+    arity: usize,
+    command: CommandFn,
+);
+
+/// Registration function of the plugin
+type RegisterFn = unsafe extern "C" fn(context: *mut c_void, add: AddFn);
+
+/// Command loaded from the plugin
+#[derive(Clone, Debug)]
+pub struct Command {
+    _library: Arc<Library>, // Keep the code loaded while the command is alive
+    function: CommandFn,
+    free: FreeFn,
+    pub arity: usize, // Number of the values popped as arguments
+}
+
+/// Load the plugin, and get its commands
+pub fn load(path: &Path) -> Result<Vec<(String, Command)>, String> {
+    // SAFETY: the plugin must follow the ABI above, it runs its code on loading
+    unsafe {
+        let library = Arc::new(Library::new(path).map_err(|e| e.to_string())?);
+        let register = *library
+            .get::<RegisterFn>(b"stack_plugin_register\0")
+            .map_err(|e| e.to_string())?;
+        let free = *library
+            .get::<FreeFn>(b"stack_plugin_free\0")
+            .map_err(|e| e.to_string())?;
+
+        let mut added: Vec<(String, usize, CommandFn)> = Vec::new();
+        register(&mut added as *mut _ as *mut c_void, add);
+        Ok(added
+            .into_iter()
+            .map(|(name, arity, function)| {
+                let command = Command {
+                    _library: library.clone(),
+                    function,
+                    free,
+                    arity,
+                };
+                (name, command)
+            })
+            .collect())
+    }
+}
+
+/// Collect the command registered by the plugin
+unsafe extern "C" fn add(
+    context: *mut c_void,
+    name: *const c_char,
+    arity: usize,
+    command: CommandFn,
+) {
+    if context.is_null() || name.is_null() {
+        return;
+    }
+    let added = &mut *(context as *mut Vec<(String, usize, CommandFn)>);
+    if let Ok(name) = CStr::from_ptr(name).to_str() {
+        added.push((name.to_string(), arity, command));
+    }
+}
+
+impl Command {
+    /// Call the command with the arguments, and get the result
+    pub fn call(&self, args: Vec<Type>) -> Result<Type, String> {
+        let args = json::to_json(&Type::List(args)).to_string();
+        let args = CString::new(args).map_err(|e| e.to_string())?;
+
+        // SAFETY: the result is the string made by the plugin, and released by it
+        let text = unsafe {
+            let result = (self.function)(args.as_ptr());
+            if result.is_null() {
+                return Err("the plugin command failed".to_string());
+            }
+            let text = CStr::from_ptr(result).to_string_lossy().into_owned();
+            (self.free)(result);
+            text
+        };
+        let value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        Ok(json::from_json(&value))
+    }
+}
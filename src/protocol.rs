@@ -0,0 +1,73 @@
+use crate::{json, Capture, Executor, StackError, Type};
+use serde_json::Value;
+use std::time::Instant;
+
+// The client writes one request per line, the code as JSON string or `{"code": ...}`,
+// otherwise the line itself is the code. The interpreter answers with one JSON line:
+//
+//   {"stack": [...], "stdout": "...", "errors": [...], "time": 0.001, "exit": null}
+
+/// Result of the evaluation sent to the client
+#[derive(Clone, Debug, Default)]
+pub struct Response {
+    pub stack: Vec<Type>,    // Whole stack after the evaluation
+    pub stdout: String,      // Printed text
+    pub errors: Vec<String>, // Logged messages and the error stopped the evaluation
+    pub time: f64,           // Seconds taken
+    pub exit: Option<i32>,   // Status if the script exited
+}
+
+/// Get the code from the request line
+pub fn parse_request(line: &str) -> String {
+    match serde_json::from_str(line) {
+        Ok(Value::String(code)) => code,
+        Ok(Value::Object(request)) => match request.get("code") {
+            Some(Value::String(code)) => code.clone(),
+            _ => String::new(),
+        },
+        _ => line.to_string(),
+    }
+}
+
+/// Evaluate the code, keeping the output for the response
+pub fn evaluate(executor: &mut Executor, code: String) -> Response {
+    executor.capture = Some(Capture::default());
+    let start = Instant::now();
+    let result = executor.evaluate_program(code);
+    let time = start.elapsed().as_secs_f64();
+
+    let mut response = respond(executor);
+    response.time = time;
+    match result {
+        Ok(()) => {}
+        Err(StackError::Exit(status)) => response.exit = Some(status),
+        Err(err) => response.errors.push(err.to_string()),
+    }
+    response
+}
+
+/// Make the response from the current state and the kept output
+pub fn respond(executor: &mut Executor) -> Response {
+    let capture = executor.capture.replace(Capture::default());
+    let capture = capture.unwrap_or_default();
+    Response {
+        stack: executor.stack.clone(),
+        stdout: capture.stdout,
+        errors: capture.errors,
+        ..Response::default()
+    }
+}
+
+impl Response {
+    /// Encode as the single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "stack": json::stack_to_json(&self.stack),
+            "stdout": self.stdout,
+            "errors": self.errors,
+            "time": self.time,
+            "exit": self.exit,
+        })
+        .to_string()
+    }
+}
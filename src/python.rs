@@ -0,0 +1,91 @@
+use crate::Type;
+use indexmap::IndexMap;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList, PyString, PyTuple};
+use std::ffi::CString;
+
+/// Evaluate the Python expression
+pub fn eval(expr: &str) -> Result<Type, String> {
+    let expr = CString::new(expr).map_err(|e| e.to_string())?;
+    Python::with_gil(|py| {
+        let value = py.eval(&expr, None, None).map_err(|e| e.to_string())?;
+        Ok(from_python(&value))
+    })
+}
+
+/// Call the Python function named with its module, like `math.sqrt`
+pub fn call(name: &str, args: &[Type]) -> Result<Type, String> {
+    // The function without the module is built-in
+    let (module, function) = name.rsplit_once('.').unwrap_or(("builtins", name));
+    Python::with_gil(|py| {
+        let result = (|| {
+            let function = py.import(module)?.getattr(function)?;
+            let args = args
+                .iter()
+                .map(|x| to_python(py, x))
+                .collect::<PyResult<Vec<_>>>()?;
+            function.call1(PyTuple::new(py, args)?)
+        })();
+        result
+            .map(|value| from_python(&value))
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Convert the value to the Python object
+fn to_python<'py>(py: Python<'py>, value: &Type) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        // Whole numbers are passed as int, which many functions require
+        Type::Number(n) if n.fract() == 0.0 && n.abs() < 9e15 => {
+            (*n as i64).into_pyobject(py)?.into_any()
+        }
+        Type::Number(n) => n.into_pyobject(py)?.into_any(),
+        Type::String(s) => PyString::new(py, s).into_any(),
+        Type::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Type::List(list) => PyList::new(
+            py,
+            list.iter()
+                .map(|x| to_python(py, x))
+                .collect::<PyResult<Vec<_>>>()?,
+        )?
+        .into_any(),
+        Type::PList(list) => PyList::new(
+            py,
+            list.iter()
+                .map(|x| to_python(py, x))
+                .collect::<PyResult<Vec<_>>>()?,
+        )?
+        .into_any(),
+        Type::Object(_, object) => {
+            let dict = PyDict::new(py);
+            for (key, value) in object {
+                dict.set_item(key, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+        Type::Error(_) | Type::Function(..) => py.None().into_bound(py),
+    })
+}
+
+/// Convert the Python object to the value
+fn from_python(value: &Bound<PyAny>) -> Type {
+    if value.is_none() {
+        Type::Number(f64::NAN)
+    } else if let Ok(b) = value.downcast::<PyBool>() {
+        Type::Bool(b.is_true())
+    } else if let Ok(s) = value.downcast::<PyString>() {
+        Type::String(s.to_string())
+    } else if let Ok(n) = value.extract::<f64>() {
+        Type::Number(n)
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let object: IndexMap<String, Type> = dict
+            .iter()
+            .map(|(key, value)| (key.to_string(), from_python(&value)))
+            .collect();
+        Type::Object("dict".to_string(), object)
+    } else if let Ok(list) = value.extract::<Vec<Bound<PyAny>>>() {
+        Type::List(list.iter().map(from_python).collect())
+    } else {
+        Type::String(value.to_string())
+    }
+}
@@ -0,0 +1,1212 @@
+/// Information of the built-in command
+#[derive(Clone, Copy, Debug)]
+pub struct CommandInfo {
+    pub name: &'static str,     // Name to call the command
+    pub category: &'static str, // Group of the command
+    pub arity: usize,           // Number of values taken from the stack
+    pub doc: &'static str,      // Description of the command
+}
+
+const fn info(
+    name: &'static str,
+    category: &'static str,
+    arity: usize,
+    doc: &'static str,
+) -> CommandInfo {
+    CommandInfo {
+        name,
+        category,
+        arity,
+        doc,
+    }
+}
+
+/// All of the built-in commands
+pub const COMMANDS: &[CommandInfo] = &[
+    info("add", "calculation", 2, "Addition"),
+    info("sub", "calculation", 2, "Subtraction"),
+    info("mul", "calculation", 2, "Multiplication"),
+    info("div", "calculation", 2, "Division"),
+    info("mod", "calculation", 2, "Remainder of division"),
+    info("pow", "calculation", 2, "Exponentiation"),
+    info("round", "calculation", 1, "Rounding off"),
+    info("sin", "calculation", 1, "Trigonometric sine"),
+    info("cos", "calculation", 1, "Trigonometric cosine"),
+    info("tan", "calculation", 1, "Trigonometric tangent"),
+    info(
+        "v-add",
+        "calculation",
+        2,
+        "Add the numbers of the lists elementwise",
+    ),
+    info(
+        "v-mul",
+        "calculation",
+        2,
+        "Multiply the numbers of the lists elementwise",
+    ),
+    info(
+        "scale",
+        "calculation",
+        2,
+        "Multiply the numbers of the list by the number",
+    ),
+    info("dot", "calculation", 2, "Dot product of the lists"),
+    info("and", "calculation", 2, "Logical operations of AND"),
+    info("or", "calculation", 2, "Logical operations of OR"),
+    info(
+        "and-then",
+        "calculation",
+        2,
+        "Logical AND evaluating code only if the value is true",
+    ),
+    info(
+        "or-else",
+        "calculation",
+        2,
+        "Logical OR evaluating code only if the value is false",
+    ),
+    info("not", "calculation", 1, "Logical operations of NOT"),
+    info("equal", "calculation", 2, "Judge is it equal"),
+    info(
+        "approx-equal",
+        "calculation",
+        3,
+        "Judge is it equal within the tolerance",
+    ),
+    info("less", "calculation", 2, "Judge is it less"),
+    info("rand", "calculation", 1, "Get random value from list"),
+    info("shuffle", "calculation", 1, "Shuffle list by random"),
+    info(
+        "repeat",
+        "string processing",
+        2,
+        "Repeat string a number of times",
+    ),
+    info(
+        "decode",
+        "string processing",
+        1,
+        "Get unicode character from number",
+    ),
+    info("encode", "string processing", 1, "Encode string by UTF-8"),
+    info(
+        "parse",
+        "string processing",
+        1,
+        "Split the code into the tokens as the interpreter does",
+    ),
+    info(
+        "token-type",
+        "string processing",
+        1,
+        "Get the kind of the token, how the interpreter evaluates it",
+    ),
+    info(
+        "code-char",
+        "string processing",
+        1,
+        "Get unicode character from number",
+    ),
+    info(
+        "char-code",
+        "string processing",
+        1,
+        "Get the code of the character",
+    ),
+    info(
+        "chars",
+        "string processing",
+        1,
+        "Split the string into the characters",
+    ),
+    info(
+        "is-digit",
+        "string processing",
+        1,
+        "Judge are all the characters digits",
+    ),
+    info(
+        "is-alpha",
+        "string processing",
+        1,
+        "Judge are all the characters alphabets",
+    ),
+    info(
+        "is-space",
+        "string processing",
+        1,
+        "Judge are all the characters whitespace",
+    ),
+    info(
+        "is-upper",
+        "string processing",
+        1,
+        "Judge are all the characters uppercase",
+    ),
+    info(
+        "to-literal",
+        "string processing",
+        1,
+        "Get the code which pushes the same value",
+    ),
+    info(
+        "quote",
+        "string processing",
+        1,
+        "Get the code which pushes the value, to build code with concat",
+    ),
+    info(
+        "escape-string",
+        "string processing",
+        1,
+        "Escape the brackets and comments to embed the text in the string literal",
+    ),
+    info("concat", "string processing", 2, "Concatenate the string"),
+    info(
+        "diff",
+        "string processing",
+        2,
+        "Get the differences of the strings as unified diff",
+    ),
+    info(
+        "sbuf-new",
+        "string processing",
+        0,
+        "Make the string buffer to accumulate the text without copying",
+    ),
+    info(
+        "sbuf-push",
+        "string processing",
+        2,
+        "Add the text at the end of the string buffer",
+    ),
+    info(
+        "sbuf-to-string",
+        "string processing",
+        1,
+        "Get the text accumulated in the string buffer",
+    ),
+    info("replace", "string processing", 3, "Replacing string"),
+    info("split", "string processing", 2, "Split string by the key"),
+    info("case", "string processing", 2, "Change string style case"),
+    info(
+        "join",
+        "string processing",
+        2,
+        "Generate a string by concat list",
+    ),
+    info("find", "string processing", 2, "Judge is it find in string"),
+    info(
+        "regex",
+        "string processing",
+        2,
+        "Search by regular expression",
+    ),
+    info(
+        "parse-locale-number",
+        "string processing",
+        2,
+        "Parse the number written in the locale style",
+    ),
+    info(
+        "format-currency",
+        "string processing",
+        3,
+        "Format the amount of money with the currency symbol",
+    ),
+    info("write-file", "I/O", 2, "Write string in the file"),
+    info("read-file", "I/O", 1, "Read string in the file"),
+    info(
+        "diff-files",
+        "I/O",
+        2,
+        "Get the differences of the files as unified diff",
+    ),
+    info(
+        "xlsx-read",
+        "I/O",
+        2,
+        "Read rows of the sheet in the spreadsheet",
+    ),
+    info("input", "I/O", 1, "Standard input"),
+    info(
+        "read-line",
+        "I/O",
+        0,
+        "Read the line of the standard input, or error at the end",
+    ),
+    info(
+        "stdin-lines",
+        "I/O",
+        0,
+        "Get the generator of the lines of the standard input",
+    ),
+    info("print", "I/O", 1, "Standard output"),
+    info("println", "I/O", 1, "Standard output with new line"),
+    info("args-cmd", "I/O", 0, "Get command-line arguments"),
+    info("play-sound", "I/O", 2, "Play sound from frequency"),
+    info("play-file", "I/O", 1, "Play the music file"),
+    info("cls", "I/O", 0, "Clear the console screen"),
+    info("clear", "I/O", 0, "Clear the console screen"),
+    info("eval", "control", 1, "Evaluate string as program"),
+    info(
+        "func",
+        "control",
+        2,
+        "Make the function of the parameters and the body",
+    ),
+    info(
+        "call",
+        "control",
+        1,
+        "Call the function in its own local scope",
+    ),
+    info("if", "control", 3, "Conditional branch"),
+    info(
+        "when",
+        "control",
+        2,
+        "Evaluate code only if the condition is true",
+    ),
+    info(
+        "unless",
+        "control",
+        2,
+        "Evaluate code only if the condition is false",
+    ),
+    info(
+        "select",
+        "control",
+        3,
+        "Choose one of the values by the condition",
+    ),
+    info("while", "control", 2, "Loop while condition is true"),
+    info("until", "control", 2, "Loop until condition is true"),
+    info("loop", "control", 1, "Loop forever until break"),
+    info("break", "control", 0, "Leave the innermost loop"),
+    info(
+        "assert",
+        "control",
+        2,
+        "Check the condition, which is error when it is false",
+    ),
+    info(
+        "ensure-type",
+        "control",
+        2,
+        "Check the data type of value, which is error when it is different",
+    ),
+    info(
+        "retry",
+        "control",
+        3,
+        "Evaluate code again while it results in error, waiting longer every time",
+    ),
+    info("thread", "control", 1, "Generate a thread"),
+    info(
+        "isolate",
+        "control",
+        1,
+        "Evaluate code apart from the stack and variables, under the same restrictions",
+    ),
+    info(
+        "isolate-with",
+        "control",
+        2,
+        "Evaluate code apart from the stack and variables, with copy of the given ones",
+    ),
+    info(
+        "transact",
+        "control",
+        1,
+        "Evaluate code all or nothing, rolling back on error",
+    ),
+    info(
+        "exit",
+        "control",
+        1,
+        "Exit a process, the status is optional",
+    ),
+    info("on-event", "control", 2, "Register the callback of event"),
+    info(
+        "on-token",
+        "control",
+        2,
+        "Register the block run before or after each token, with the token on the stack",
+    ),
+    info("emit-event", "control", 2, "Emit the event with the value"),
+    info(
+        "emit-after",
+        "control",
+        3,
+        "Emit the event with the value after seconds",
+    ),
+    info(
+        "run-loop",
+        "control",
+        0,
+        "Deliver the events to the callbacks until stopped or idle",
+    ),
+    info("stop-loop", "control", 0, "Stop the event loop"),
+    info(
+        "try",
+        "error handling",
+        1,
+        "Evaluate code, leaving it at the first new error to be handled by catch",
+    ),
+    info(
+        "catch",
+        "error handling",
+        1,
+        "Evaluate code with the error on the stack, if the last try block caught it",
+    ),
+    info(
+        "propagate",
+        "error handling",
+        0,
+        "Leave the function with the error, if the top of the stack is error",
+    ),
+    info(
+        "error",
+        "error handling",
+        2,
+        "Make the error value with the code and the message",
+    ),
+    info(
+        "with-payload",
+        "error handling",
+        2,
+        "Attach the value to the error",
+    ),
+    info(
+        "error-code",
+        "error handling",
+        1,
+        "Get the code of the error",
+    ),
+    info(
+        "error-message",
+        "error handling",
+        1,
+        "Get the message of the error",
+    ),
+    info(
+        "error-payload",
+        "error handling",
+        1,
+        "Get the value attached to the error",
+    ),
+    info(
+        "error-origin",
+        "error handling",
+        1,
+        "Get the command and the index of its token which raised the error",
+    ),
+    info("get", "list processing", 2, "Get list value by index"),
+    info("set", "list processing", 3, "Set list value by index"),
+    info("del", "list processing", 2, "Delete list value by index"),
+    info("append", "list processing", 2, "Append value in the list"),
+    info("insert", "list processing", 3, "Insert value in the list"),
+    info("index", "list processing", 2, "Get index of the list"),
+    info("sort", "list processing", 1, "Sorting in the list"),
+    info(
+        "min-of",
+        "list processing",
+        1,
+        "Get the smallest value in the list",
+    ),
+    info(
+        "max-of",
+        "list processing",
+        1,
+        "Get the largest value in the list",
+    ),
+    info(
+        "bsearch",
+        "list processing",
+        2,
+        "Search the index of value in the sorted list",
+    ),
+    info(
+        "insert-sorted",
+        "list processing",
+        2,
+        "Insert the value keeping the list sorted",
+    ),
+    info("reverse", "list processing", 1, "Reverse in the list"),
+    info("for", "list processing", 3, "Iteration for the list"),
+    info(
+        "range",
+        "list processing",
+        3,
+        "Generate a range excluding the max, the step may be float or negative",
+    ),
+    info("len", "list processing", 1, "Get length of list"),
+    info(
+        "plist",
+        "list processing",
+        1,
+        "Make persistent list from the list",
+    ),
+    info(
+        "plist-push",
+        "list processing",
+        2,
+        "Append value in the persistent list",
+    ),
+    info(
+        "plist-get",
+        "list processing",
+        2,
+        "Get persistent list value by index",
+    ),
+    info(
+        "plist-set",
+        "list processing",
+        3,
+        "Set persistent list value by index",
+    ),
+    info(
+        "plist-len",
+        "list processing",
+        1,
+        "Get length of persistent list",
+    ),
+    info(
+        "plist-to-list",
+        "list processing",
+        1,
+        "Make normal list from the persistent list",
+    ),
+    info("map", "functional programming", 3, "Mapping a list"),
+    info(
+        "filter",
+        "functional programming",
+        3,
+        "Filtering a list value",
+    ),
+    info(
+        "find-if",
+        "functional programming",
+        3,
+        "Get the first item that satisfies the condition",
+    ),
+    info(
+        "position-if",
+        "functional programming",
+        3,
+        "Get the index of the first item that satisfies the condition",
+    ),
+    info(
+        "any",
+        "functional programming",
+        3,
+        "Judge is there any item that satisfies the condition",
+    ),
+    info(
+        "every",
+        "functional programming",
+        3,
+        "Judge do all items satisfy the condition",
+    ),
+    info(
+        "count-if",
+        "functional programming",
+        3,
+        "Count the items that satisfy the condition",
+    ),
+    info(
+        "group-by",
+        "functional programming",
+        3,
+        "Group the items by the key, into the map of key to list",
+    ),
+    info(
+        "frequencies",
+        "functional programming",
+        1,
+        "Count the occurrences of each value, into the map of value to count",
+    ),
+    info(
+        "reduce",
+        "functional programming",
+        5,
+        "Generate value from list",
+    ),
+    info(
+        "step-generator",
+        "generator",
+        2,
+        "Make the generator from the initial code and the step code run from its start every time",
+    ),
+    info(
+        "yield",
+        "generator",
+        1,
+        "Give the value of the step, which runs on to its end",
+    ),
+    info("next", "generator", 1, "Get the next value of generator"),
+    info("pop", "memory manage", 1, "Pop in the stack"),
+    info("size-stack", "memory manage", 0, "Get size of stack"),
+    info(
+        "get-stack",
+        "memory manage",
+        0,
+        "Get Stack as List, the copy of the values below it",
+    ),
+    info(
+        "dump-stack",
+        "memory manage",
+        1,
+        "Write the stack into the file as JSON, without changing it",
+    ),
+    info("var", "memory manage", 2, "Define variable at memory"),
+    info("set!", "memory manage", 2, "Reassign the defined variable"),
+    info("type", "memory manage", 1, "Get data type of value"),
+    info(
+        "is-number",
+        "memory manage",
+        1,
+        "Judge is the value of the data type",
+    ),
+    info(
+        "is-string",
+        "memory manage",
+        1,
+        "Judge is the value of the data type",
+    ),
+    info(
+        "is-bool",
+        "memory manage",
+        1,
+        "Judge is the value of the data type",
+    ),
+    info(
+        "is-list",
+        "memory manage",
+        1,
+        "Judge is the value of the data type",
+    ),
+    info(
+        "is-object",
+        "memory manage",
+        1,
+        "Judge is the value of the data type",
+    ),
+    info(
+        "is-error",
+        "memory manage",
+        1,
+        "Judge is the value of the data type",
+    ),
+    info("cast", "memory manage", 2, "Explicit data type casting"),
+    info("mem", "memory manage", 0, "Get memory information"),
+    info(
+        "unpack",
+        "memory manage",
+        2,
+        "Bind the list values to the variables pairwise, `...name` takes the rest",
+    ),
+    info(
+        "unpack-object",
+        "memory manage",
+        2,
+        "Bind the properties to the variables of the same names",
+    ),
+    info(
+        "match-list",
+        "memory manage",
+        2,
+        "Bind the list values if it has the shape of the pattern like `[head ...rest]`",
+    ),
+    info(
+        "in-namespace",
+        "memory manage",
+        2,
+        "Evaluate code defining variables in the namespace",
+    ),
+    info(
+        "import",
+        "memory manage",
+        1,
+        "Import the definitions of the file into the namespace of its name, only once",
+    ),
+    info(
+        "global",
+        "memory manage",
+        2,
+        "Define variable in the global memory",
+    ),
+    info(
+        "local",
+        "memory manage",
+        2,
+        "Define variable in the innermost local scope",
+    ),
+    info(
+        "vars-local",
+        "memory manage",
+        0,
+        "Get list of the local variables",
+    ),
+    info(
+        "vars-global",
+        "memory manage",
+        0,
+        "Get list of the global variables",
+    ),
+    info(
+        "free",
+        "memory manage",
+        1,
+        "Free up memory space of variable",
+    ),
+    info("copy", "memory manage", 1, "Copy stack's top value"),
+    info("swap", "memory manage", 2, "Swap stack's top 2 value"),
+    info(
+        "shuffle-stack",
+        "memory manage",
+        1,
+        "Rearrange the top of the stack by the pattern like `ab-bab`",
+    ),
+    info(
+        "pack-n",
+        "memory manage",
+        1,
+        "Pop the number of values into a list",
+    ),
+    info(
+        "unpack-all",
+        "memory manage",
+        1,
+        "Push all elements of the list",
+    ),
+    info(
+        "dip",
+        "memory manage",
+        2,
+        "Evaluate code beneath the top value, and push it back",
+    ),
+    info(
+        "set-option",
+        "memory manage",
+        2,
+        "Set the interpreter option",
+    ),
+    info(
+        "get-option",
+        "memory manage",
+        1,
+        "Get the interpreter option",
+    ),
+    info(
+        "set-precision",
+        "memory manage",
+        1,
+        "Set the digits after the decimal point to show numbers",
+    ),
+    info(
+        "commands",
+        "reflection",
+        0,
+        "Get list of the built-in commands",
+    ),
+    info(
+        "command-info",
+        "reflection",
+        1,
+        "Get category, arity and description of the command",
+    ),
+    info(
+        "doc",
+        "reflection",
+        2,
+        "Attach the documentation to the user function",
+    ),
+    info(
+        "help",
+        "reflection",
+        1,
+        "Get the documentation of the user function or the command",
+    ),
+    info(
+        "alias",
+        "reflection",
+        2,
+        "Give the other name to the command",
+    ),
+    info(
+        "macro",
+        "reflection",
+        2,
+        "Define the name replaced by the tokens of the template, before the evaluation",
+    ),
+    info(
+        "wrap-command",
+        "reflection",
+        2,
+        "Intercept the command by the code",
+    ),
+    info(
+        "confirm-before",
+        "reflection",
+        1,
+        "Ask the user before the command runs",
+    ),
+    info(
+        "call-original",
+        "reflection",
+        0,
+        "Call the original command in the wrapper",
+    ),
+    info(
+        "kv-open",
+        "key-value store",
+        1,
+        "Open the key-value store of the file",
+    ),
+    info(
+        "kv-get",
+        "key-value store",
+        1,
+        "Get the value of the key in the store",
+    ),
+    info(
+        "kv-set",
+        "key-value store",
+        2,
+        "Set the value of the key in the store",
+    ),
+    info(
+        "kv-delete",
+        "key-value store",
+        1,
+        "Delete the key in the store",
+    ),
+    info(
+        "kv-keys",
+        "key-value store",
+        0,
+        "Get all of keys in the store",
+    ),
+    info("cache-set", "cache", 3, "Cache the value for seconds"),
+    info(
+        "cache-get",
+        "cache",
+        1,
+        "Get the cached value, or error if it is missing or expired",
+    ),
+    info("now-time", "times", 0, "Get now time as unix epoch"),
+    info("now-utc", "times", 0, "Get now time in UTC as ISO 8601"),
+    info(
+        "now-tz",
+        "times",
+        1,
+        "Get now time in the IANA time zone as ISO 8601",
+    ),
+    info(
+        "parse-time",
+        "times",
+        1,
+        "Parse the ISO 8601 or RFC 2822 time into unix epoch",
+    ),
+    info(
+        "format-time",
+        "times",
+        2,
+        "Format the time by `iso`, `rfc2822` or the strftime pattern",
+    ),
+    info(
+        "add-duration",
+        "times",
+        2,
+        "Add the seconds or the ISO 8601 duration to the time",
+    ),
+    info(
+        "between",
+        "times",
+        2,
+        "Get the seconds from the time to the other",
+    ),
+    info(
+        "parse-duration",
+        "times",
+        1,
+        "Parse the ISO 8601 duration like `PT1H30M` into seconds",
+    ),
+    info(
+        "format-duration",
+        "times",
+        1,
+        "Format the seconds as ISO 8601 duration",
+    ),
+    info(
+        "stopwatch-start",
+        "times",
+        1,
+        "Start the stopwatch of the name",
+    ),
+    info(
+        "stopwatch-elapsed",
+        "times",
+        1,
+        "Get the seconds since the stopwatch started",
+    ),
+    info("sleep", "times", 1, "Sleep fixed time"),
+    info(
+        "instance",
+        "object oriented system",
+        2,
+        "Generate a instance of object",
+    ),
+    info(
+        "property",
+        "object oriented system",
+        2,
+        "Get property of object",
+    ),
+    info(
+        "method",
+        "object oriented system",
+        2,
+        "Call the method of object",
+    ),
+    info(
+        "modify",
+        "object oriented system",
+        3,
+        "Modify the property of object",
+    ),
+    info("all", "object oriented system", 1, "Get all of properties"),
+    info(
+        "request",
+        "external cooperation processing",
+        1,
+        "Send the http request",
+    ),
+    info(
+        "request-response",
+        "external cooperation processing",
+        1,
+        "Send the http request, and get the status, the final url and the body",
+    ),
+    info(
+        "request-config",
+        "external cooperation processing",
+        2,
+        "Set the setting of the http request",
+    ),
+    info(
+        "http-auth-bearer",
+        "external cooperation processing",
+        1,
+        "Send the bearer token with the following http requests",
+    ),
+    info(
+        "oauth-token",
+        "external cooperation processing",
+        3,
+        "Get the access token by the OAuth2 client credentials",
+    ),
+    info(
+        "graphql",
+        "external cooperation processing",
+        3,
+        "Send the GraphQL query with the variables, and get the data",
+    ),
+    info(
+        "open",
+        "external cooperation processing",
+        1,
+        "Open the file or url",
+    ),
+    info(
+        "cd",
+        "external cooperation processing",
+        1,
+        "Change current directory",
+    ),
+    info(
+        "pwd",
+        "external cooperation processing",
+        0,
+        "Get current directory",
+    ),
+    info(
+        "mkdir",
+        "external cooperation processing",
+        1,
+        "Make directory",
+    ),
+    info("rm", "external cooperation processing", 1, "Remove item"),
+    info(
+        "rename",
+        "external cooperation processing",
+        2,
+        "Rename item",
+    ),
+    info("cp", "external cooperation processing", 2, "Copy the item"),
+    info(
+        "size-file",
+        "external cooperation processing",
+        1,
+        "Get size of the file",
+    ),
+    info(
+        "ls",
+        "external cooperation processing",
+        0,
+        "Get list of files",
+    ),
+    info(
+        "folder",
+        "external cooperation processing",
+        1,
+        "Judge is it folder",
+    ),
+    info(
+        "dedupe-scan",
+        "external cooperation processing",
+        1,
+        "Find the groups of the files with the same contents under the folder",
+    ),
+    info(
+        "sync-dirs",
+        "external cooperation processing",
+        3,
+        "Mirror the files of the folder into the other, with the options `delete` and `dry-run`",
+    ),
+    info(
+        "sys-info",
+        "external cooperation processing",
+        1,
+        "Get system information",
+    ),
+    info(
+        "set-clipboard",
+        "external cooperation processing",
+        1,
+        "Set value in the clipboard",
+    ),
+    info(
+        "get-clipboard",
+        "external cooperation processing",
+        0,
+        "Get value in the clipboard",
+    ),
+    info(
+        "ipc-listen",
+        "external cooperation processing",
+        1,
+        "Wait for the other process to connect by IPC",
+    ),
+    info(
+        "ipc-connect",
+        "external cooperation processing",
+        1,
+        "Connect to the other process by IPC",
+    ),
+    info(
+        "ipc-send",
+        "external cooperation processing",
+        2,
+        "Send the message by IPC",
+    ),
+    info(
+        "ipc-recv",
+        "external cooperation processing",
+        1,
+        "Receive the message by IPC",
+    ),
+    info(
+        "load-plugin",
+        "external cooperation processing",
+        1,
+        "Load the commands from the plugin library",
+    ),
+    info(
+        "py-eval",
+        "external cooperation processing",
+        1,
+        "Evaluate the Python expression",
+    ),
+    info(
+        "py-call",
+        "external cooperation processing",
+        2,
+        "Call the Python function with the list of arguments",
+    ),
+    info(
+        "ffi-load",
+        "external cooperation processing",
+        1,
+        "Load the shared library for the foreign functions",
+    ),
+    info(
+        "ffi-call",
+        "external cooperation processing",
+        3,
+        "Call the foreign function by the signature like `f64(f64, str)`",
+    ),
+];
+
+/// Commands affecting the outside of the interpreter, and the kind of the effect
+const EFFECTS: &[(&str, &str)] = &[
+    ("write-file", "file"),
+    ("rm", "file"),
+    ("rename", "file"),
+    ("cp", "file"),
+    ("dump-stack", "file"),
+    ("sync-dirs", "file"),
+    ("mkdir", "file"),
+    ("kv-set", "file"),
+    ("kv-delete", "file"),
+    ("request", "network"),
+    ("request-response", "network"),
+    ("oauth-token", "network"),
+    ("graphql", "network"),
+    ("ipc-listen", "network"),
+    ("ipc-connect", "network"),
+    ("ipc-send", "network"),
+    ("open", "process"),
+    ("load-plugin", "process"),
+    ("py-eval", "process"),
+    ("py-call", "process"),
+    ("ffi-load", "process"),
+    ("ffi-call", "process"),
+    ("set-clipboard", "clipboard"),
+    ("get-clipboard", "clipboard"),
+];
+
+/// Commands whose results can't be reproduced, denied in the deterministic mode
+const NONDETERMINISTIC: &[&str] = &[
+    "sys-info",
+    "request",
+    "request-response",
+    "graphql",
+    "oauth-token",
+    "get-clipboard",
+    "ipc-recv",
+];
+
+/// Judge is the result of the command different on every run
+pub fn is_nondeterministic(name: &str) -> bool {
+    NONDETERMINISTIC.contains(&name)
+}
+
+/// Short code using the command, shown in the reference pages
+const EXAMPLES: &[(&str, &str)] = &[
+    ("add", "1 2 add"),
+    ("mod", "10 3 mod"),
+    ("pow", "2 10 pow"),
+    ("v-add", "[1 2 3] 10 v-add"),
+    ("dot", "[1 2 3] [4 5 6] dot"),
+    ("equal", "1 1 equal"),
+    ("less", "1 2 less"),
+    ("repeat", "(ab) 3 repeat"),
+    ("concat", "(hello) ( world) concat"),
+    ("replace", "(hello) (l) (L) replace"),
+    ("split", "(a,b,c) (,) split"),
+    ("case", "(stack) (upper) case"),
+    ("join", "[(a) (b)] (-) join"),
+    ("chars", "(ab) chars"),
+    ("char-code", "(A) char-code"),
+    ("code-char", "65 code-char"),
+    ("parse", "(1 2 add) parse"),
+    ("to-literal", "[1 2] to-literal"),
+    ("println", "(hello) println"),
+    ("eval", "(1 2 add) eval"),
+    ("try", "([1 2] 5 get) try (error-code) catch"),
+    ("call", "1 2 [(a) (b)] (a b add) func call"),
+    ("if", "(yes) (no) true if"),
+    ("get", "[1 2 3] 0 get"),
+    ("append", "[1 2] 3 append"),
+    ("sort", "[3 1 2] sort"),
+    ("reverse", "[1 2 3] reverse"),
+    ("range", "1 5 1 range"),
+    ("len", "[1 2 3] len"),
+    ("min-of", "[3 1 2] min-of"),
+    ("for", "[1 2 3] (x) (x println) for"),
+    ("map", "[1 2 3] (x) (x 2 mul) map"),
+    ("filter", "[1 2 3 4] (x) (x 2 mod 0 equal) filter"),
+    ("reduce", "[1 2 3] (acc) 0 (x) (acc x add) reduce"),
+    ("var", "5 (x) var x"),
+    ("type", "3 type"),
+    ("swap", "1 2 swap"),
+];
+
+/// Get the example code of the command, if it has
+pub fn example(name: &str) -> Option<&'static str> {
+    EXAMPLES
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, code)| *code)
+}
+
+/// Get the kind of the external effect of the command, if it has
+pub fn effect(name: &str) -> Option<&'static str> {
+    EFFECTS
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, effect)| *effect)
+}
+
+/// Find the built-in command by the name
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Search the commands by the word in the name, category or description
+///
+/// The closer matches come first: the exact name, a part of the name, the category,
+/// the description, and the name spelled close to the word.
+pub fn search(word: &str) -> Vec<&'static CommandInfo> {
+    let word = word.trim().to_lowercase();
+    if word.is_empty() {
+        return Vec::new();
+    }
+    let rank = |command: &CommandInfo| {
+        if command.name == word {
+            Some(0)
+        } else if command.name.contains(&word) {
+            Some(1)
+        } else if command.category.contains(&word) {
+            Some(2)
+        } else if command.doc.to_lowercase().contains(&word) {
+            Some(3)
+        } else if is_subsequence(&word, command.name) || distance(&word, command.name) <= 2 {
+            Some(4)
+        } else {
+            None
+        }
+    };
+    let mut found: Vec<(usize, &CommandInfo)> = COMMANDS
+        .iter()
+        .filter_map(|command| Some((rank(command)?, command)))
+        .collect();
+    found.sort_by_key(|(rank, command)| (*rank, command.name));
+    found.into_iter().map(|(_, command)| command).collect()
+}
+
+/// Judge are the characters of the word in the text in order, like `rdf` in `read-file`
+fn is_subsequence(word: &str, text: &str) -> bool {
+    let mut chars = text.chars();
+    word.chars().all(|c| chars.any(|x| x == c))
+}
+
+/// Count the edits to change the text into the other
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let replaced = previous + usize::from(x != *y);
+            previous = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
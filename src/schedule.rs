@@ -0,0 +1,32 @@
+use chrono::{Local, SecondsFormat};
+use cron::Schedule;
+use std::str::FromStr;
+use std::thread;
+
+/// Parse the cron expression, the seconds field is optional
+pub fn parse(expr: &str) -> Result<Schedule, String> {
+    let expr = if expr.split_whitespace().count() == 5 {
+        format!("0 {expr}")
+    } else {
+        expr.to_string()
+    };
+    Schedule::from_str(&expr).map_err(|e| e.to_string())
+}
+
+/// Run the job at every time of the schedule, logging each run
+pub fn run(schedule: &Schedule, mut job: impl FnMut() -> i32) {
+    for time in schedule.upcoming(Local) {
+        if let Ok(wait) = (time - Local::now()).to_std() {
+            thread::sleep(wait);
+        }
+        eprintln!(
+            "[Schedule] started at {}",
+            time.to_rfc3339_opts(SecondsFormat::Secs, false)
+        );
+        let status = job();
+        eprintln!(
+            "[Schedule] finished at {} with status {status}",
+            Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+        );
+    }
+}
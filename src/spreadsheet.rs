@@ -0,0 +1,36 @@
+use crate::Type;
+use calamine::{open_workbook_auto, Data, Reader};
+use std::path::Path;
+
+/// Read the sheet as list of the rows, the first sheet if the name is empty
+pub fn read(path: &Path, sheet: &str) -> Result<Vec<Type>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| e.to_string())?;
+    let sheet = match sheet {
+        "" => workbook
+            .sheet_names()
+            .first()
+            .cloned()
+            .ok_or("the workbook has no sheet")?,
+        name => name.to_string(),
+    };
+    let range = workbook
+        .worksheet_range(&sheet)
+        .map_err(|e| e.to_string())?;
+    Ok(range
+        .rows()
+        .map(|row| Type::List(row.iter().map(cell).collect()))
+        .collect())
+}
+
+/// Convert the cell to the value
+fn cell(data: &Data) -> Type {
+    match data {
+        Data::Int(i) => Type::Number(*i as f64),
+        Data::Float(f) => Type::Number(*f),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => Type::String(s.clone()),
+        Data::Bool(b) => Type::Bool(*b),
+        Data::DateTime(d) => Type::Number(d.as_f64()),
+        Data::Error(e) => Type::error(&e.to_string()),
+        Data::Empty => Type::String(String::new()),
+    }
+}
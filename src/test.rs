@@ -1,4 +1,10 @@
-use super::{Executor, Mode};
+use super::cassette::Cassette;
+use super::permission::{Access, Permissions};
+use super::{
+    bench, check, compile, doc, fmt, i18n, json, protocol, registry, Determinism, Executor, Mode,
+    Severity, StackError, Type,
+};
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn calculate() {
@@ -6,7 +12,7 @@ fn calculate() {
 
     assert_eq!(
         {
-            executor.evaluate_program("5 8 add".to_string());
+            executor.evaluate_program("5 8 add".to_string()).unwrap();
             executor.pop_stack().get_number()
         },
         13f64
@@ -14,7 +20,7 @@ fn calculate() {
 
     assert_eq!(
         {
-            executor.evaluate_program("8 3 sub".to_string());
+            executor.evaluate_program("8 3 sub".to_string()).unwrap();
             executor.pop_stack().get_number()
         },
         5f64
@@ -22,7 +28,7 @@ fn calculate() {
 
     assert_eq!(
         {
-            executor.evaluate_program("5 8 mul".to_string());
+            executor.evaluate_program("5 8 mul".to_string()).unwrap();
             executor.pop_stack().get_number()
         },
         40f64
@@ -30,7 +36,7 @@ fn calculate() {
 
     assert_eq!(
         {
-            executor.evaluate_program("10 5 div".to_string());
+            executor.evaluate_program("10 5 div".to_string()).unwrap();
             executor.pop_stack().get_number()
         },
         2f64
@@ -38,7 +44,7 @@ fn calculate() {
 
     assert_eq!(
         {
-            executor.evaluate_program("3 2 pow".to_string());
+            executor.evaluate_program("3 2 pow".to_string()).unwrap();
             executor.pop_stack().get_number()
         },
         9f64
@@ -51,7 +57,9 @@ fn variables() {
 
     assert_eq!(
         {
-            executor.evaluate_program("5987 (x) var x".to_string());
+            executor
+                .evaluate_program("5987 (x) var x".to_string())
+                .unwrap();
             executor.pop_stack().get_number()
         },
         5987f64
@@ -59,7 +67,9 @@ fn variables() {
 
     assert_eq!(
         {
-            executor.evaluate_program("5987 (x) var x 1 add (x) var x".to_string());
+            executor
+                .evaluate_program("5987 (x) var x 1 add (x) var x".to_string())
+                .unwrap();
             executor.pop_stack().get_number()
         },
         5988f64
@@ -72,7 +82,9 @@ fn control_if() {
 
     assert_eq!(
         {
-            executor.evaluate_program("(true) (false) 10 2 div 5 equal if".to_string());
+            executor
+                .evaluate_program("(true) (false) 10 2 div 5 equal if".to_string())
+                .unwrap();
             executor.pop_stack().get_bool()
         },
         true
@@ -80,13 +92,83 @@ fn control_if() {
 
     assert_eq!(
         {
-            executor.evaluate_program("(true) (false) 10 2 div 4 equal if".to_string());
+            executor
+                .evaluate_program("(true) (false) 10 2 div 4 equal if".to_string())
+                .unwrap();
             executor.pop_stack().get_bool()
         },
         false
     );
 }
 
+#[test]
+fn control_when() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(1) 10 2 div 5 equal when (2) 1 2 equal when (3) false unless (4) true unless"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["1", "3"]
+    );
+}
+
+#[test]
+fn control_select() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(yes) (no) 1 1 equal select [1] (x) 1 2 equal select".to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(yes)", "(x)"]
+    );
+}
+
+#[test]
+fn short_circuit() {
+    let mut executor = Executor::new(Mode::Script);
+
+    // The code isn't evaluated when the first value decides the result
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "0 (n) var
+                    false (1 (n) set! true) and-then true (2 (n) set! false) or-else
+                    true (3 (n) set! true) and-then false (n 3 equal) or-else n"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["false", "true", "true", "true", "3"]
+    );
+}
+
 #[test]
 fn control_while() {
     let mut executor = Executor::new(Mode::Script);
@@ -94,7 +176,8 @@ fn control_while() {
     assert_eq!(
         {
             executor
-                .evaluate_program("5 (i) var (i 1 add (i) var) (i 10 less) while i".to_string());
+                .evaluate_program("5 (i) var (i 1 add (i) var) (i 10 less) while i".to_string())
+                .unwrap();
             executor.pop_stack().get_number()
         },
         10f64
@@ -107,7 +190,9 @@ fn equal_true() {
 
     assert_eq!(
         {
-            executor.evaluate_program("1 1 add 2 equal".to_string());
+            executor
+                .evaluate_program("1 1 add 2 equal".to_string())
+                .unwrap();
             executor.pop_stack().get_bool()
         },
         true
@@ -120,9 +205,2565 @@ fn equal_false() {
 
     assert_eq!(
         {
-            executor.evaluate_program("1 1 mul 999 equal".to_string());
+            executor
+                .evaluate_program("1 1 mul 999 equal".to_string())
+                .unwrap();
             executor.pop_stack().get_bool()
         },
         false
     );
 }
+
+#[test]
+fn init_file() {
+    let mut executor = Executor::new(Mode::Debug);
+    let path = std::env::temp_dir().join("stack-test-stackrc");
+    std::fs::write(&path, "(hello) (greeting) var").unwrap();
+
+    // The definitions are kept for the REPL, which stays in its mode
+    executor.load_init_file(&path).unwrap();
+    assert_eq!(
+        executor.memory.get("greeting").unwrap().display(),
+        "(hello)"
+    );
+    assert!(matches!(executor.mode, Mode::Debug));
+    std::fs::remove_file(&path).unwrap();
+
+    // The missing file is reported without stopping the REPL
+    executor.load_init_file(&path).unwrap();
+}
+
+#[test]
+fn options() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(>>> ) (prompt) set-option (prompt) get-option".to_string())
+                .unwrap();
+            executor.pop_stack().get_string()
+        },
+        ">>> ".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(<) (stack-open) set-option 1 get-stack".to_string())
+                .unwrap();
+            executor.show_stack()
+        },
+        "Stack< 1 | [1] 〕".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "pop pop (3) (display-limit) set-option 0 1000000 1 range (abcdef)".to_string(),
+                )
+                .unwrap();
+            executor.show_stack()
+        },
+        "Stack< [0 1 2 … 999997 more] | (abc…) 〕".to_string()
+    );
+}
+
+#[test]
+fn error_message() {
+    let mut executor = Executor::new(Mode::Script);
+    executor.color = false;
+
+    assert_eq!(
+        {
+            executor.command = "get".into();
+            executor.token = 3;
+            executor.format_error(Severity::User, "Index specification is out of range")
+        },
+        "Error! Index specification is out of range at `get` (token 3)\n".to_string()
+    );
+}
+
+#[test]
+fn exit_status() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 2 3] 5 get".to_string())
+                .unwrap();
+            executor.exit_status()
+        },
+        0
+    );
+
+    assert_eq!(
+        {
+            executor.strict = true;
+            executor.exit_status()
+        },
+        1
+    );
+}
+
+#[test]
+fn evaluation_result() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        executor.evaluate_program("[1 2 3] 5 get 1 2 add".to_string()),
+        Ok(())
+    );
+
+    assert_eq!(
+        executor.evaluate_program("(2 exit) eval 3".to_string()),
+        Err(StackError::Exit(2))
+    );
+
+    assert_eq!(
+        {
+            executor.strict = true;
+            executor.evaluate_program("[1 2 3] 5 get 1 2 add".to_string())
+        },
+        Err(StackError::Runtime {
+            command: "get".to_string(),
+            token: 3,
+            message: "Index specification is out of range".to_string()
+        })
+    );
+}
+
+#[test]
+fn json_value() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 (a) true error:e]".to_string())
+                .unwrap();
+            json::to_json(&executor.pop_stack()).to_string()
+        },
+        r#"[1.0,"a",true,{"error":"e"}]"#.to_string()
+    );
+
+    // Null is read as the object of its own type
+    assert_eq!(
+        json::from_json(&serde_json::Value::Null).display(),
+        Type::Object("null".to_string(), Default::default()).display()
+    );
+}
+
+#[test]
+fn compiled_program() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            let compiled = compile::compile("1 (a b) pop [2 3] len add".to_string());
+            executor
+                .evaluate_tokens(compile::load(&compiled).unwrap())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        3f64
+    );
+
+    assert_eq!(compile::load("1 2 add"), None);
+}
+
+#[test]
+fn ipc_message() {
+    let server = std::thread::spawn(|| {
+        let mut executor = Executor::new(Mode::Script);
+        executor
+            .evaluate_program(
+                "(stack-test-ipc) ipc-listen pop (stack-test-ipc) ipc-recv".to_string(),
+            )
+            .unwrap();
+        executor.pop_stack().get_string()
+    });
+
+    let mut executor = Executor::new(Mode::Script);
+    while {
+        executor
+            .evaluate_program("(stack-test-ipc) ipc-connect type".to_string())
+            .unwrap();
+        executor.pop_stack().get_string() == "error"
+    } {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    executor
+        .evaluate_program("(hello) (stack-test-ipc) ipc-send".to_string())
+        .unwrap();
+
+    assert_eq!(server.join().unwrap(), "hello".to_string());
+}
+
+#[test]
+fn isolated_evaluation() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("5 (x) var 9 (y) var (x y 1) isolate".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[(x) (y) 1]".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[(x)] (x 2 mul (z) var z) isolate-with z".to_string())
+                .unwrap();
+            executor
+                .stack
+                .drain(..)
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["[10]".to_string(), "(z)".to_string()]
+    );
+}
+
+#[test]
+fn generator() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(0 (i) var) ((i yield i 1 add (i) var) () i 3 less if) step-generator (g) var"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .evaluate_program("g next (a) var next (b) var pop a b add".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        1f64
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[] (l) var g (x) (l x append (l) var) for l".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[0 1 2]".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("g next pop next pop next pop next".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:generator-done".to_string()
+    );
+
+    // The step doesn't suspend at `yield`, it runs to the end and the last value is taken
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("() (1 yield 2 yield) step-generator next".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "2".to_string()
+    );
+}
+
+#[test]
+fn event_loop() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[] (log) var
+                    (tick) (log swap append (log) var) on-event
+                    (stop) (pop stop-loop) on-event
+                    3 (tick) 0.02 emit-after
+                    2 (tick) emit-event
+                    0 (stop) 0.05 emit-after
+                    9 (tick) 0.1 emit-after
+                    run-loop log"
+                        .to_string(),
+                )
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[2 3]".to_string()
+    );
+
+    // The panicked thread doesn't keep the loop waiting
+    let source = executor.events.source();
+    let thread = std::thread::spawn(move || {
+        let _source = source;
+        panic!("the handler failed");
+    });
+    assert!(thread.join().is_err());
+    executor.evaluate_program("run-loop".to_string()).unwrap();
+}
+
+#[test]
+fn find_if() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 4 7 10] (x) (5 x less) find-if".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        7f64
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 4 7 10] (x) (5 x less) position-if".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        2f64
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 4 7 10] (x) (20 x less) find-if".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:item-not-found".to_string()
+    );
+}
+
+#[test]
+fn list_predicates() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert!({
+        executor
+            .evaluate_program("[1 4 7] (x) (5 x less) any".to_string())
+            .unwrap();
+        executor.pop_stack().get_bool()
+    });
+
+    assert!(!{
+        executor
+            .evaluate_program(
+                "[1 4 7] (x) (0 x less) every [1 -4 7] (x) (0 x less) every".to_string(),
+            )
+            .unwrap();
+        executor.pop_stack().get_bool()
+    });
+    assert!(executor.pop_stack().get_bool());
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 4 7 10] (x) (5 x less) count-if".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        2f64
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[(a) [(x)]] [1] instance all".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[(x)]".to_string()
+    );
+}
+
+#[test]
+fn group_by() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 2 3 4 5] (x) (x 2 mod) group-by (1) property".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[1 3 5]".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[(a) (b) (a) (c) (a)] frequencies (a) property".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        3f64
+    );
+}
+
+#[test]
+fn float_range() {
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(
+            "0 1 0.25 range 5 1 -2 range 0 0.3 0.1 range 1 1 1 range 1 5 0 range".to_string(),
+        )
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "error:range");
+    assert_eq!(executor.pop_stack().display(), "[]");
+    assert_eq!(executor.pop_stack().display(), "[0 0.1 0.2]");
+    assert_eq!(executor.pop_stack().display(), "[5 3]");
+    assert_eq!(executor.pop_stack().display(), "[0 0.25 0.5 0.75]");
+}
+
+#[test]
+fn list_arithmetic() {
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(
+            "[1 2 3] [4 5 6] v-add 10 [1 2] v-mul [1 2] 0.5 scale [1 2 3] [4 5 6] dot 2 3 v-add [1 2] [1] v-add"
+                .to_string(),
+        )
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "error:length-mismatch");
+    assert_eq!(executor.pop_stack().display(), "5");
+    assert_eq!(executor.pop_stack().display(), "32");
+    assert_eq!(executor.pop_stack().display(), "[0.5 1]");
+    assert_eq!(executor.pop_stack().display(), "[10 20]");
+    assert_eq!(executor.pop_stack().display(), "[5 7 9]");
+}
+
+#[test]
+fn sorted_list() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 3 5 7 9 11] 9 bsearch".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        4f64
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 3 5] 4 bsearch".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:item-not-found".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[1 3 5 10] 4 insert-sorted 20 insert-sorted 0 insert-sorted".to_string(),
+                )
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[0 1 3 4 5 10 20]".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[10 (b) 9 true [1] (a) [0 5] false] sort".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[9 10 (a) (b) false true [0 5] [1]]".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[(x) 3 [2] -1] copy min-of swap max-of [] min-of".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["-1", "[2]", "error:empty-list"]
+    );
+}
+
+#[test]
+fn persistent_list() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[1 2] plist (p) var p 3 plist-push (q) var p plist-len q plist-len q 2 plist-get q type p 0 9 plist-set plist-to-list q to-literal"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["2", "3", "3", "(plist)", "[9 2]", "([1 2 3] plist)"]
+    );
+    executor.stack.clear();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[] plist 0 plist-get".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:index-out-range".to_string()
+    );
+}
+
+#[test]
+fn text_diff() {
+    let mut executor = Executor::new(Mode::Script);
+    // Newlines in the string literal are spaces
+    let text = |x: &str| Type::String(x.to_string());
+    executor.memory.insert("a".into(), text("a\nb\nc\n"));
+    executor.memory.insert("b".into(), text("a\nB\nc\n"));
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("a b diff (same) (same) diff".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.clone().get_string())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n".to_string(),
+            String::new()
+        ]
+    );
+}
+
+#[test]
+fn string_buffer() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "sbuf-new (b) var [1 2 3] (x) (b x sbuf-push b (,) sbuf-push) for b sbuf-to-string"
+                        .to_string(),
+                )
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "(1,2,3,)".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(text) sbuf-to-string".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:sbuf".to_string()
+    );
+}
+
+#[test]
+fn dry_run() {
+    let mut executor = Executor::new(Mode::Script);
+    executor.dry_run = true;
+    let path = std::env::temp_dir().join("stack-test-dry-run.txt");
+    let path = path.to_string_lossy();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(format!(
+                    "(text) ({path}) write-file ({path}) rm ({path}) ({path}.bak) rename"
+                ))
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![format!("({path})"), format!("({path}.bak)")]
+    );
+    assert!(!std::path::Path::new(path.as_ref()).exists());
+
+    // The nested executors don't touch the files either
+    std::fs::write(path.as_ref(), "text").unwrap();
+    executor
+        .evaluate_program(format!("(({path}) rm) isolate"))
+        .unwrap();
+    assert!(std::path::Path::new(path.as_ref()).exists());
+    std::fs::remove_file(path.as_ref()).unwrap();
+}
+
+#[test]
+fn dedupe_scan() {
+    let dir = std::env::temp_dir().join("stack-test-dedupe");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    for (name, text) in [("a", "same"), ("sub/b", "same"), ("c", "other"), ("d", "")] {
+        std::fs::write(dir.join(name), text).unwrap();
+    }
+    std::fs::write(dir.join("e"), "").unwrap();
+
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(format!("({}) dedupe-scan", dir.display()))
+        .unwrap();
+    assert_eq!(
+        executor.pop_stack().display(),
+        format!("[[({0}/a) ({0}/sub/b)]]", dir.display())
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sync_dirs() {
+    let root = std::env::temp_dir().join("stack-test-sync");
+    let (src, dst) = (root.join("src"), root.join("dst"));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::create_dir_all(&dst).unwrap();
+    std::fs::write(src.join("a"), "new").unwrap();
+    std::fs::write(src.join("sub/b"), "new").unwrap();
+    std::fs::write(dst.join("a"), "old text").unwrap();
+    std::fs::write(dst.join("c"), "extra").unwrap();
+
+    let mut executor = Executor::new(Mode::Script);
+    let sync = |options: &str| {
+        format!(
+            "({}) ({}) {options} sync-dirs",
+            src.display(),
+            dst.display()
+        )
+    };
+    executor
+        .evaluate_program(sync("{(delete) true (dry-run) true}"))
+        .unwrap();
+    assert_eq!(
+        executor.pop_stack().display(),
+        "[(update a) (add sub/b) (delete c)]"
+    );
+    assert!(dst.join("c").exists());
+
+    executor
+        .evaluate_program(sync("{(delete) true}") + " " + &sync("{}"))
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "[]");
+    assert_eq!(std::fs::read_to_string(dst.join("sub/b")).unwrap(), "new");
+    assert!(!dst.join("c").exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn dump_stack() {
+    let path = std::env::temp_dir().join("stack-test-dump.json");
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(format!(
+            "1 (a) get-stack ({}) dump-stack size-stack",
+            path.display()
+        ))
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "3");
+    let dumped = std::fs::read_to_string(&path).unwrap();
+    let dumped: serde_json::Value = serde_json::from_str(&dumped).unwrap();
+    assert_eq!(dumped, serde_json::json!([1.0, "a", [1.0, "a"]]));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn interned_names() {
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program("1 (x) var x".to_string())
+        .unwrap();
+    // The variable's name shares the string with the token
+    let symbol = executor.intern("x");
+    let (name, _) = executor.memory.get_key_value("x").unwrap();
+    assert!(Arc::ptr_eq(name, &symbol));
+}
+
+#[test]
+fn function_scope() {
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(
+            "10 (x) var [(a) (b)] (a b add (x) var x 2 mul) func (double-sum) var 1 2 double-sum call x"
+                .to_string(),
+        )
+        .unwrap();
+    // The variable defined in the function doesn't collide with the caller's
+    assert_eq!(executor.pop_stack().display(), "10");
+    assert_eq!(executor.pop_stack().display(), "6");
+    assert!(executor.locals.is_empty());
+
+    executor
+        .evaluate_program("double-sum type 3 4 double-sum to-literal eval call".to_string())
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "14");
+    assert_eq!(executor.pop_stack().display(), "(function)");
+
+    executor.evaluate_program("5 call".to_string()).unwrap();
+    assert_eq!(executor.pop_stack().display(), "error:not-function");
+
+    // The loops and the unpacking bind in the local scope, over the parameters
+    executor
+        .evaluate_program(
+            "9 [(x)] ([1 2 3] (x) (x) for) func call
+            3 [(n)] ([1 2] (y) (y n mul) map) func call
+            9 [(a)] ([1 2] [(a) (b)] unpack a b) func call"
+                .to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        executor
+            .stack
+            .drain(..)
+            .map(|x| x.display())
+            .collect::<Vec<_>>(),
+        vec!["1", "2", "3", "[3 6]", "1", "2"]
+    );
+    assert!(!executor.memory.contains_key("y") && !executor.memory.contains_key("b"));
+
+    // The reassignment finds the variable in the namespace as the reference does
+    executor
+        .evaluate_program("(ns) (5 (n) var 6 (n) set! n) in-namespace".to_string())
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "6");
+
+    // The local at the top level is defined in the namespace as the var is
+    executor
+        .evaluate_program("(ns) (7 (m) local m) in-namespace ns.m".to_string())
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "7");
+    assert_eq!(executor.pop_stack().display(), "7");
+    assert!(!executor.memory.contains_key("m"));
+}
+
+#[test]
+fn confirm_before() {
+    let mut executor = Executor::new(Mode::Script);
+    let path = std::env::temp_dir().join("stack-test-confirm.txt");
+    std::fs::remove_file(&path).ok();
+
+    // Writing the new file doesn't ask the user
+    executor
+        .evaluate_program(format!(
+            "(write-file) confirm-before (text) ({}) write-file",
+            path.display()
+        ))
+        .unwrap();
+    assert!(executor.confirm.contains("write-file"));
+    assert!(executor.child().confirm.contains("write-file"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "text");
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn trace_file() {
+    let mut executor = Executor::new(Mode::Script);
+    let trace = std::env::temp_dir().join("stack-test-trace.jsonl");
+    let file = std::fs::File::create(&trace).unwrap();
+    executor.trace = Some(Arc::new(Mutex::new(file)));
+
+    executor.evaluate_program("1 2 add".to_string()).unwrap();
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(&trace)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[2]["token"], "add");
+    assert_eq!(records[2]["index"], 3);
+    assert_eq!(records[2]["stack"], serde_json::json!([3.0]));
+    std::fs::remove_file(&trace).ok();
+}
+
+#[test]
+fn audit_log() {
+    let mut executor = Executor::new(Mode::Script);
+    let log = std::env::temp_dir().join("stack-test-audit.log");
+    let path = std::env::temp_dir().join("stack-test-audited.txt");
+    let file = std::fs::File::create(&log).unwrap();
+    executor.audit = Some(std::sync::Arc::new(std::sync::Mutex::new(file)));
+
+    executor
+        .evaluate_program(format!(
+            "(text) ({0}) write-file 1 2 add ((text) ({0}) write-file) isolate",
+            path.display()
+        ))
+        .unwrap();
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    for record in &records {
+        assert_eq!(record["effect"], "file");
+        assert_eq!(record["command"], "write-file");
+        assert_eq!(
+            record["args"],
+            serde_json::json!(["text", path.display().to_string()])
+        );
+    }
+    std::fs::remove_file(&log).ok();
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn secure_mode() {
+    let mut executor = Executor::new(Mode::Script);
+    let temp = std::env::temp_dir();
+    let mut permissions = Permissions::default();
+    permissions.allow(Access::Read, &[&temp.to_string_lossy()]);
+    permissions.allow(Access::Net, &["example.com"]);
+
+    assert!(permissions.allows(Access::Read, Some(&temp.join("a").to_string_lossy())));
+    assert!(!permissions.allows(Access::Read, Some(&temp.join("../a").to_string_lossy())));
+    assert!(!permissions.allows(Access::Write, Some(&temp.join("a").to_string_lossy())));
+    assert!(permissions.allows(Access::Net, Some("https://example.com:443/path")));
+    assert!(!permissions.allows(Access::Net, Some("https://example.com.evil/")));
+    assert!(!permissions.allows(Access::Run, None));
+
+    executor.permissions = Some(permissions);
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(format!(
+                    "({}) folder (text) ({}) write-file (ls) open",
+                    temp.display(),
+                    temp.join("stack-test-secure.txt").display()
+                ))
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["true", "error:permission-denied", "error:permission-denied"]
+    );
+
+    // The nested executors are restricted as well
+    executor.stack.clear();
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("((ls) open) isolate".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[error:permission-denied]"
+    );
+}
+
+#[test]
+fn time_and_duration() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(2024-01-02T03:04:05Z) parse-time
+                    (Tue, 02 Jan 2024 12:04:05 +0900) parse-time
+                    1704164645 (%Y/%m/%d) format-time
+                    (2024-01-02T03:04:05+09:00) (PT1H30M) add-duration
+                    (2024-01-01) (2024-01-02T12:00:00Z) between
+                    (P1DT2H30M) parse-duration 95400 format-duration
+                    (P1Y) parse-duration"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "1704164645",
+            "1704164645",
+            "(2024/01/02)",
+            "(2024-01-02T04:34:05+09:00)",
+            "129600",
+            "95400",
+            "(P1DT2H30M)",
+            "error:parse-duration"
+        ]
+    );
+}
+
+#[test]
+fn deterministic_mode() {
+    let run = |code: &str| {
+        let mut executor = Executor::new(Mode::Script);
+        executor.deterministic = Some(Determinism::new(42, 1704164645.0));
+        executor.evaluate_program(code.to_string()).unwrap();
+        executor
+            .stack
+            .iter()
+            .map(|x| x.display())
+            .collect::<Vec<_>>()
+    };
+    let code = "[1 2 3 4 5 6 7 8] shuffle [1 2 3 4 5 6 7 8] rand now-time now-utc";
+    let result = run(code);
+    assert_eq!(result, run(code));
+    assert_eq!(result[2..], ["1704164645", "(2024-01-02T03:04:05Z)"]);
+    assert_eq!(run("(cpu) sys-info"), ["error:nondeterministic"]);
+}
+
+#[test]
+fn each_line() {
+    let mut executor = Executor::new(Mode::Script);
+    let input = std::io::Cursor::new("a\nbb\nstop\nccc\n");
+    let code = "(break) line (stop) equal when line len".to_string();
+    let syntax = executor.analyze_syntax(code);
+    executor.evaluate_each_line(syntax, input).unwrap();
+    assert_eq!(
+        executor
+            .stack
+            .iter()
+            .map(|x| x.display())
+            .collect::<Vec<_>>(),
+        vec!["1", "2"]
+    );
+    assert_eq!(executor.memory["line-number"].display(), "3");
+}
+
+#[test]
+fn cassette_replay() {
+    let path = std::env::temp_dir().join("stack-test-cassette.json");
+    let url = Type::String("https://example.com".to_string());
+    let page = Type::String("page".to_string());
+    Cassette::record(&path)
+        .add("request", &[url], &page)
+        .unwrap();
+
+    let mut executor = Executor::new(Mode::Script);
+    executor.cassette = Some(Arc::new(Mutex::new(Cassette::replay(&path).unwrap())));
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(https://example.com) request (https://example.com) request".to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(page)", "error:cassette"]
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn kv_store() {
+    let path = std::env::temp_dir().join("stack-test-kv.json");
+    let _ = std::fs::remove_file(&path);
+    let open = format!("({}) kv-open", path.display());
+
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(format!(
+            "{open} 1 (count) kv-set [1 (a)] (list) kv-set 0 (tmp) kv-set (tmp) kv-delete"
+        ))
+        .unwrap();
+
+    // The values are kept for the next run
+    let mut executor = Executor::new(Mode::Script);
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(format!("{open} (count) kv-get 1 add (list) kv-get kv-keys"))
+                .unwrap();
+            executor.stack.clone()
+        }
+        .iter()
+        .map(|x| x.display())
+        .collect::<Vec<_>>(),
+        vec!["2", "[1 (a)]", "[(count) (list)]"]
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(tmp) kv-get".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:kv-get".to_string()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn cache() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(hello) (a) 60 cache-set (world) (b) 0.01 cache-set 0.05 sleep (a) cache-get (b) cache-get"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(hello)", "error:cache-miss"]
+    );
+
+    // The time to live out of the range of duration is refused
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(hello) (c) 1e300 cache-set".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:cache-set".to_string()
+    );
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn request_config() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(stack-test) (user-agent) request-config 5 (timeout) request-config 0 (redirects) request-config"
+                        .to_string(),
+                )
+                .unwrap();
+            executor.stack.len()
+        },
+        0
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(soon) (timeout) request-config".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:request-config".to_string()
+    );
+
+    // The time limit out of the range of duration is refused instead of panicking
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("1e30 (timeout) request-config".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:request-config".to_string()
+    );
+}
+
+/// Serve one HTTP response on the local port, and get the received request
+#[cfg(feature = "network")]
+fn serve_once(body: &'static str) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let n = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..n]);
+            if n == 0 || is_complete(&String::from_utf8_lossy(&request)) {
+                break;
+            }
+        }
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        String::from_utf8_lossy(&request).to_string()
+    });
+    (url, handle)
+}
+
+/// Judge is the whole request with the body received
+#[cfg(feature = "network")]
+fn is_complete(request: &str) -> bool {
+    let Some((headers, body)) = request.split_once("\r\n\r\n") else {
+        return false;
+    };
+    let length = headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0);
+    body.len() >= length
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn oauth_token() {
+    let (url, server) = serve_once(r#"{"access_token":"abc123","token_type":"bearer"}"#);
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(format!("({url}/token) (id) (secret) oauth-token"))
+                .unwrap();
+            executor.pop_stack().get_string()
+        },
+        "abc123".to_string()
+    );
+
+    let request = server.join().unwrap();
+    assert!(request.contains("grant_type=client_credentials"));
+    // Basic authorization of "id:secret"
+    assert!(request.contains("aWQ6c2VjcmV0"));
+
+    let (url, server) = serve_once("ok");
+    executor
+        .evaluate_program(format!("(abc123) http-auth-bearer ({url}) request"))
+        .unwrap();
+    assert!(server.join().unwrap().contains("Bearer abc123"));
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn graphql() {
+    let (url, server) = serve_once(r#"{"data":{"user":{"name":"ann"}}}"#);
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(format!(
+                    "({url}) (query($id: Int) {{ user(id: $id) {{ name }} }}) [(vars) [(id) 1]] [] instance graphql
+                    (user) property (name) property"
+                ))
+                .unwrap();
+            executor.pop_stack().get_string()
+        },
+        "ann".to_string()
+    );
+
+    let request = server.join().unwrap();
+    assert!(request.contains(r#""variables":{"id":1.0}"#));
+
+    // The nested objects of the variables are plain as well, and null is kept
+    let json = serde_json::json!({"user": {"name": "ann", "tags": [{"id": 1.0}], "age": null}});
+    assert_eq!(json::to_plain_json(&json::from_json(&json)), json);
+}
+
+#[test]
+fn reassign_variable() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("1 (a) var a 1 add (a) set! 0 (b) set! a".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["error:set!", "2"]
+    );
+
+    // Shadowing by `var` is the error in strict mode
+    executor.strict = true;
+    assert_eq!(
+        executor.evaluate_program("3 (a) var".to_string()),
+        Err(StackError::Runtime {
+            command: "var".to_string(),
+            token: 3,
+            message: "the variable `a` is already defined, use `set!` to reassign it".to_string()
+        })
+    );
+}
+
+#[test]
+fn variable_scope() {
+    let mut executor = Executor::new(Mode::Script);
+
+    // The top level binds the local variable in the global memory
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("1 (a) local vars-local vars-global".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["[]", "[(a)]"]
+    );
+    executor.stack.clear();
+
+    executor.locals.push(std::collections::HashMap::new());
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("2 (a) local 3 (b) global a b vars-local".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["2", "3", "[(a)]"]
+    );
+    executor.stack.clear();
+
+    executor.locals.pop();
+    assert_eq!(
+        {
+            executor.evaluate_program("a".to_string()).unwrap();
+            executor.pop_stack().get_number()
+        },
+        1f64
+    );
+}
+
+#[test]
+fn command_registry() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert!({
+        executor.evaluate_program("commands".to_string()).unwrap();
+        let commands = executor.pop_stack().get_list();
+        commands.iter().any(|x| x.display() == "(add)")
+    });
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(add) command-info copy (category) property swap (arity) property".to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(calculation)", "2"]
+    );
+    executor.stack.clear();
+
+    // Every name is registered once
+    let mut names: Vec<&str> = registry::COMMANDS.iter().map(|x| x.name).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), registry::COMMANDS.len());
+}
+
+#[test]
+fn search_commands() {
+    let names = |word: &str| {
+        registry::search(word)
+            .iter()
+            .map(|x| x.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("concat")[0], "concat");
+    assert!(names("string").contains(&"replace"));
+    assert!(names("clipboard").contains(&"get-clipboard"));
+    assert!(names("reed-file").contains(&"read-file"));
+    assert!(names("").is_empty());
+}
+
+#[test]
+fn reference_pages() {
+    let pages = doc::pages(doc::Format::Markdown);
+    assert_eq!(pages[0].0, "index.md");
+    assert!(pages[0]
+        .1
+        .contains("[string processing](string-processing.md)"));
+    let (_, page) = pages
+        .iter()
+        .find(|(name, _)| name == "calculation.md")
+        .unwrap();
+    assert!(page.contains("## `add`\n\n`( a b -- ... )`\n\nAddition\n\n```\n1 2 add\n```"));
+
+    let pages = doc::pages(doc::Format::Html);
+    let (_, page) = pages.iter().find(|(name, _)| name == "i-o.html").unwrap();
+    assert!(page.contains("<h2 id=\"print\"><code>print</code></h2>"));
+
+    // Every example works as it is written
+    for command in registry::COMMANDS {
+        if let Some(code) = registry::example(command.name) {
+            let mut executor = Executor::new(Mode::Script);
+            executor.evaluate_program(code.to_string()).unwrap();
+            let failed = executor.stack.iter().any(|x| matches!(x, Type::Error(_)));
+            assert!(!failed, "{code}");
+        }
+    }
+}
+
+#[test]
+fn command_alias() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(足す) (add) alias (plus) (足す) alias 1 2 足す 3 plus".to_string(),
+                )
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        6f64
+    );
+}
+
+#[test]
+fn macros() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(copy mul) (square) macro 5 square (square square) (fourth) macro 2 fourth
+                    [1 2 3] (x) (x square) map"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["25", "16", "[1 4 9]"]
+    );
+
+    assert_eq!(
+        {
+            let compiled = compile::compile("(1 add) (inc) macro 3 inc inc".to_string());
+            let syntax = compile::load(&compiled).unwrap();
+            syntax[3..]
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+        },
+        vec!["3", "1", "add", "1", "add"]
+    );
+}
+
+#[test]
+fn wrap_command() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "0 (count) var (add) (count 1 add (count) set! call-original) wrap-command
+                    1 2 add 3 add count"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["6", "2"]
+    );
+}
+
+#[test]
+fn loop_control() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "0 (i) var (i 1 add (i) set! (break) () 5 i equal if) loop i".to_string(),
+                )
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        5f64
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(i 2 mul (i) set!) (100 i less) until i".to_string())
+                .unwrap();
+            executor.pop_stack().get_number()
+        },
+        160f64
+    );
+
+    // The iteration over the list stops as well
+    executor.stack.clear();
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 2 3 4] (x) (x (break) () x 2 equal if) for".to_string())
+                .unwrap();
+            executor
+                .stack
+                .drain(..)
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["1", "2"]
+    );
+
+    assert_eq!(
+        executor.evaluate_program("break".to_string()),
+        Err(StackError::Break)
+    );
+}
+
+#[test]
+fn retry() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "0 (n) var (n 1 add (n) set! (n) (error:flaky) 3 n equal if) 5 0.001 retry n"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["3", "3"]
+    );
+    executor.stack.clear();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(error:flaky) 2 0.001 retry".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:flaky".to_string()
+    );
+
+    // The backoff out of range ends the retry instead of waiting forever
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(error:flaky) 2000 0 retry".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:retry".to_string()
+    );
+}
+
+#[test]
+fn transaction() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "1 (a) var 2 (b) var 5 (10 (a) set! pop [] 3 get 20 (b) set!) transact a b"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["5", "error:index-out-range", "1", "2"]
+    );
+    executor.stack.clear();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(30 (a) set! 7) transact a".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["7", "30"]
+    );
+}
+
+#[test]
+fn assertion() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "1 1 equal (same) assert 1 2 equal (same) assert 5 (number) ensure-type (a) (number) ensure-type"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["error:assert", "5", "error:ensure-type"]
+    );
+
+    executor.strict = true;
+    assert_eq!(
+        executor.evaluate_program("false (positive) assert 1".to_string()),
+        Err(StackError::Runtime {
+            command: "assert".to_string(),
+            token: 3,
+            message: "assertion failed: positive".to_string()
+        })
+    );
+}
+
+#[test]
+fn documentation() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(2 mul) (double) var (double) (Double the number) doc (double) help (sub) help"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(Double the number)", "(Subtraction)"]
+    );
+}
+
+#[test]
+fn namespace() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "1 (limit) var
+                    (math) (
+                        10 (limit) var
+                        (limit) (max) var
+                        (copy math.limit less (pop math.limit) () if) (clamp) var
+                    ) in-namespace
+                    15 math.clamp eval limit math.max"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["10", "1", "(limit)"]
+    );
+}
+
+#[test]
+fn import_module() {
+    let dir = std::env::temp_dir().join("stack-test-import");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("geometry.stk");
+    std::fs::write(
+        &path,
+        "(loaded) [(x)] (x x mul) func (square) var 1 (count) var",
+    )
+    .unwrap();
+
+    let mut executor = Executor::new(Mode::Script);
+    executor
+        .evaluate_program(format!(
+            "({0}) import 5 (count) var ({0}.stk) import 3 geometry.square call geometry.count count",
+            dir.join("geometry").display()
+        ))
+        .unwrap();
+    // The second import is no-op, and the values left by the module are dropped
+    assert_eq!(
+        executor
+            .stack
+            .iter()
+            .map(|x| x.display())
+            .collect::<Vec<_>>(),
+        vec!["9", "1", "5"]
+    );
+
+    executor
+        .evaluate_program("(stack-test-missing) import".to_string())
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "error:import");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn property_path() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[(address) [(city)]] [(Tokyo)] instance (address) var
+                    [(user) [(name)] [(address)]] [(Taro) address] instance (user) var
+                    user.address.city user.name user.age"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(Tokyo)", "(Taro)", "error:property"]
+    );
+}
+
+#[test]
+fn unpack() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[1 2 3 4] [(a) (b) (...rest)] unpack
+                    [(point) [(x)] [(y)]] [5 6] instance [(x) (y)] unpack-object
+                    a b rest x y [1] [(a) (b)] unpack"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["1", "2", "[3 4]", "5", "6", "error:unpack"]
+    );
+}
+
+#[test]
+fn match_list() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[1 2 3] ([head ...rest]) match-list head rest [1 2 3] ([first ...mid last]) match-list mid last"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["true", "1", "[2 3]", "true", "[2]", "3"]
+    );
+    executor.stack.clear();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[1] ([a b]) match-list [] ([]) match-list [5 6] [(_) (y)] match-list y (ab) ([x ...xs]) match-list"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["false", "true", "true", "6", "false"]
+    );
+}
+
+#[test]
+fn shuffle_stack() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "1 2 3 (abc-cab) shuffle-stack 4 (ab-bab) shuffle-stack".to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["3", "1", "4", "2", "4"]
+    );
+    executor.stack.clear();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("1 2 (ab-c) shuffle-stack".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["1", "2", "error:shuffle-stack"]
+    );
+}
+
+#[test]
+fn bench_workloads() {
+    for workload in bench::WORKLOADS {
+        let mut executor = Executor::new(Mode::Script);
+        assert_eq!(workload.run(&mut executor), Ok(()), "{}", workload.name);
+        assert!(
+            !executor.stack.iter().any(|x| matches!(x, Type::Error(_))),
+            "{}",
+            workload.name
+        );
+    }
+}
+
+#[test]
+fn parse_tokens() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(1 (a b) [2 3] #note# x println) parse".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[(1) ((a b)) ([2 3]) (#note#) (x) (println)]".to_string()
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "5 (x) var (1 true (s) [l] {o} error:e x #c# println) parse (t) (t token-type) map"
+                        .to_string(),
+                )
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "[(number) (bool) (string) (list) (object) (error) (variable) (comment) (command)]"
+            .to_string()
+    );
+
+    // The same token is shared, even across the evaluations
+    let first = executor.analyze_syntax("x 1 x".to_string());
+    let second = executor.analyze_syntax("x".to_string());
+    assert!(std::sync::Arc::ptr_eq(&first[0], &first[2]));
+    assert!(std::sync::Arc::ptr_eq(&first[0], &second[0]));
+}
+
+#[test]
+fn characters() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(aβ1) chars (β) char-code 946 code-char (ab) char-code
+                    (42) is-digit (4a) is-digit (aβ) is-alpha 32 code-char is-space (AB) is-upper () is-upper"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "[(a) (β) (1)]",
+            "946",
+            "(β)",
+            "error:char",
+            "true",
+            "false",
+            "true",
+            "true",
+            "true",
+            "false"
+        ]
+    );
+}
+
+#[test]
+fn type_predicates() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[(person) [(name)]] [(Taro)] instance (p) var
+                    p type 1 type p is-object p is-list error:e is-error (1) is-number"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "(object:person)",
+            "(number)",
+            "true",
+            "false",
+            "true",
+            "false"
+        ]
+    );
+}
+
+#[test]
+fn error_value() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "[1 2 3] 5 get copy error-code swap copy error-message swap error-origin"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "(index-out-range)",
+            "(Index specification is out of range)",
+            "[(get) 3]"
+        ]
+    );
+    executor.stack.clear();
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(not-found) (no such user) error 42 with-payload copy error-payload swap error-code 1 error-code"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["42", "(not-found)", "error:not-error"]
+    );
+}
+
+#[test]
+fn propagate() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "([1 2] 5 get propagate (unreachable)) (fetch) var
+                    fetch eval [1 2] 0 get propagate"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["error:index-out-range", "1"]
+    );
+
+    assert_eq!(
+        executor.evaluate_program("error:e propagate 1".to_string()),
+        Err(StackError::Propagate)
+    );
+}
+
+#[test]
+fn try_catch() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "0 (1 [1 2] 5 get (unreachable)) try (error-code) catch
+                    (2 3) try (unreachable) catch
+                    ((e) (raised) error propagate) try (error-message) catch"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["0", "(index-out-range)", "2", "3", "(raised)"]
+    );
+    assert_eq!(executor.trying, 0);
+
+    // The error from the function in the block is caught after its scope is left
+    executor.stack.clear();
+    executor
+        .evaluate_program(
+            "[(i)] ([1 2] i get) func (at) var (5 at call) try (type) catch".to_string(),
+        )
+        .unwrap();
+    assert_eq!(executor.pop_stack().display(), "(error)");
+    assert!(executor.locals.is_empty());
+}
+
+#[test]
+fn number_precision() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "0.1 0.2 add 0.3 equal 0.1 0.2 add 0.3 0.000001 approx-equal
+                    4 set-precision 0.1 0.2 add 2 3 div -0.00001"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["false", "true", "0.3", "0.6667", "0",]
+    );
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("-1 set-precision 2 3 div".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "0.6666666666666666".to_string()
+    );
+
+    // The precision belongs to the executor, not to the thread
+    executor
+        .evaluate_program("2 set-precision".to_string())
+        .unwrap();
+    let mut other = Executor::new(Mode::Script);
+    other.evaluate_program("2 3 div".to_string()).unwrap();
+    assert_eq!(other.pop_stack().display(), "0.6666666666666666");
+    executor.evaluate_program("2 3 div".to_string()).unwrap();
+    assert_eq!(executor.pop_stack().display(), "0.67");
+}
+
+#[test]
+fn locale_number() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(1.234.567,89 €) (,) parse-locale-number ($1,234.5) (.) parse-locale-number
+                    (12abc) (.) parse-locale-number
+                    1234567.891 ($) (.) format-currency -0.5 (€) (,) format-currency"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "1234567.89",
+            "1234.5",
+            "error:parse-locale-number",
+            "($1,234,567.89)",
+            "(-€0,50)"
+        ]
+    );
+}
+
+#[test]
+fn xlsx_read() {
+    let mut executor = Executor::new(Mode::Script);
+
+    // Missing workbook and unsupported format are reported as the error value
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(no-such-book.xlsx) (Sheet1) xlsx-read (Cargo.toml) () xlsx-read".to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["error:xlsx-read", "error:xlsx-read"]
+    );
+}
+
+#[test]
+fn translation() {
+    use i18n::{translate_into, Lang};
+
+    assert_eq!(
+        translate_into(Lang::Japanese, "Index specification is out of range"),
+        "インデックスが範囲外です"
+    );
+    // The message embedded in the template is translated too
+    assert_eq!(
+        translate_into(
+            Lang::Japanese,
+            "the variable `x` is not defined at `x` (token 3)"
+        ),
+        "変数 `x` は定義されていません (`x` トークン 3)"
+    );
+    assert_eq!(
+        translate_into(Lang::Japanese, "message without translation"),
+        "message without translation"
+    );
+    assert_eq!(
+        translate_into(Lang::English, "the variable `x` is not defined"),
+        "the variable `x` is not defined"
+    );
+    assert_eq!(Lang::parse("JA"), Some(Lang::Japanese));
+}
+
+#[test]
+fn example_program() {
+    let mut executor = Executor::new(Mode::Script);
+    executor.strict = true;
+
+    // The embedded example must keep working as the commands change
+    executor
+        .evaluate_program(include_str!("../examples/fizzbuzz.stk").to_string())
+        .unwrap();
+    assert!(executor.stack.is_empty());
+}
+
+#[test]
+fn native_failure() {
+    let mut executor = Executor::new(Mode::Script);
+
+    // The invalid arguments for the native calls are error values instead of panics
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "-1 sleep (ab) 1e300 repeat [1] 5 (x) insert (x) 1 1e300 emit-after"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "error:sleep",
+            "error:repeat",
+            "error:index-out-range",
+            "error:emit-after"
+        ]
+    );
+}
+
+#[cfg(not(feature = "sysinfo"))]
+#[test]
+fn unavailable_command() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(os-type) sys-info".to_string())
+                .unwrap();
+            executor.pop_stack().display()
+        },
+        "error:unavailable".to_string()
+    );
+}
+
+#[test]
+fn load_plugin() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("(no-such-plugin.so) load-plugin no-such-command".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["error:load-plugin", "(no-such-command)"]
+    );
+}
+
+#[cfg(feature = "python")]
+#[test]
+fn python_bridge() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(1 + 2 * 3) py-eval ([1, 'a', True]) py-eval [16] (math.sqrt) py-call
+                    [5] (math.factorial) py-call (1/0) py-eval"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["7", "[1 (a) true]", "4", "120", "error:py-eval"]
+    );
+}
+
+#[cfg(all(feature = "ffi", target_os = "linux"))]
+#[test]
+fn foreign_function() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "(libc.so.6) ffi-load (strlen) (u64(str)) [(hello)] ffi-call
+                    (abs) (int(int)) [-5] ffi-call (abs) (int(int)) [] ffi-call"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["5", "5", "error:ffi-call"]
+    );
+}
+
+#[test]
+fn object_literal() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "{(name)(Alice) (age)30 (tags)[(a) {(x) 1}]} (person) var
+                    person.name person.age 1 add person (tags) property 1 get (x) property
+                    {(odd)}"
+                        .to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["(Alice)", "31", "1", "error:object-literal"]
+    );
+}
+
+#[test]
+fn stack_combinator() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program(
+                    "1 2 3 3 pack-n [4 5] unpack-all 10 20 (1 add) dip 9 pack-n".to_string(),
+                )
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.display())
+                .collect::<Vec<_>>()
+        },
+        vec!["[1 2 3]", "4", "5", "11", "20", "error:pack-n"]
+    );
+}
+
+#[test]
+fn clock_and_stopwatch() {
+    let mut executor = Executor::new(Mode::Script);
+
+    executor
+        .evaluate_program(
+            "(t) stopwatch-start now-utc (Asia/Tokyo) now-tz (Nowhere/City) now-tz
+            0.01 sleep (t) stopwatch-elapsed (u) stopwatch-elapsed"
+                .to_string(),
+        )
+        .unwrap();
+    let stack: Vec<String> = executor.stack.iter().map(|x| x.display()).collect();
+    assert!(stack[0].ends_with("Z)"));
+    assert!(stack[1].ends_with("+09:00)"));
+    assert_eq!(stack[2], "error:now-tz");
+    assert!(executor.stack[3].clone().get_number() >= 0.01);
+    assert_eq!(stack[4], "error:stopwatch");
+}
+
+#[test]
+fn graceful_shutdown() {
+    let mut executor = Executor::new(Mode::Script);
+    let shutdown = executor.shutdown_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        shutdown();
+    });
+
+    // The endless loop is left at the token boundary
+    assert_eq!(
+        executor.evaluate_program("(0.01 sleep) loop".to_string()),
+        Err(StackError::Exit(0))
+    );
+}
+
+#[test]
+fn check_script() {
+    assert!(check::check("(a [b) [1 {(k) (v)}] # comment #").is_empty());
+
+    let problems: Vec<(usize, usize, String)> = check::check("[1 2}\n)\n(open # still")
+        .into_iter()
+        .map(|x| (x.line, x.column, x.message))
+        .collect();
+    assert_eq!(
+        problems,
+        vec![
+            (1, 5, "`}` doesn't match `[`".to_string()),
+            (2, 1, "unexpected `)`".to_string()),
+            (3, 7, "the comment is not closed".to_string()),
+            (3, 1, "the string is not closed".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn format_script() {
+    let code =
+        "\n1 10 1 range (i) (\n  (odd) (\n i println\n) i 2 mod if\n)  for  \n\n\n[\n1 (a\\))\n]\n";
+    assert_eq!(
+        fmt::format(code),
+        "1 10 1 range (i) (\n    (odd) (\n        i println\n    ) i 2 mod if\n)  for\n\n[\n    1 (a\\))\n]\n"
+    );
+
+    // The formatted script means the same
+    let formatted = fmt::format(code);
+    assert_eq!(fmt::format(&formatted), formatted);
+    let mut executor = Executor::new(Mode::Script);
+    assert_eq!(
+        executor.analyze_syntax(formatted).len(),
+        executor.analyze_syntax(code.to_string()).len()
+    );
+}
+
+#[test]
+fn json_protocol() {
+    let mut executor = Executor::new(Mode::Script);
+
+    let request = protocol::parse_request(r#"{"code": "(hi) println [1 2] 5 get 1 2 add"}"#);
+    let response = protocol::evaluate(&mut executor, request);
+    assert_eq!(response.stdout, "hi\n");
+    assert_eq!(response.errors.len(), 1);
+    assert!(response.errors[0].contains("Index specification is out of range"));
+    assert_eq!(
+        response.to_json().split(",\"time\"").next().unwrap(),
+        r#"{"errors":["Error! Index specification is out of range at `get` (token 5)"],"exit":null,"stack":[{"error":"index-out-range"},3.0],"stdout":"hi\n""#
+    );
+
+    // The state is kept between the requests
+    let response = protocol::evaluate(&mut executor, protocol::parse_request("\"2 mul\""));
+    assert!(response.errors.is_empty());
+    assert_eq!(response.stack.last().unwrap().display(), "6");
+    assert_eq!(
+        protocol::evaluate(&mut executor, "3 exit".to_string()).exit,
+        Some(3)
+    );
+}
+
+#[test]
+fn stable_order() {
+    let mut executor = Executor::new(Mode::Script);
+
+    executor
+        .evaluate_program(
+            "1 (zeta) var 2 (alpha) var 3 (mid) var mem
+            {(b) 1 (c) 2 (a) 3} all
+            [(x) (c) (x) (a)] frequencies all
+            [3 1 4 2] (n) (n 2 mod) group-by all"
+                .to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        executor
+            .stack
+            .iter()
+            .map(|x| x.display())
+            .collect::<Vec<_>>(),
+        vec![
+            "[(zeta) (alpha) (mid)]",
+            "[(b) (c) (a)]",
+            "[(x) (c) (a)]",
+            "[(1) (0)]"
+        ]
+    );
+}
+
+#[test]
+fn literal_round_trip() {
+    let mut executor = Executor::new(Mode::Script);
+
+    executor
+        .evaluate_program(
+            "[1 -2.5 (a b) (x\\(y\\)z) (back\\\\slash) (hash\\#) true [(n) [3]] {(k) (v)}]
+            (line) 10 decode concat (tab) concat 9 decode concat
+            [(Person) [(name) (Bob)]] [] instance
+            (code) (message) error 5 with-payload"
+                .to_string(),
+        )
+        .unwrap();
+    let values = executor.stack.clone();
+    let literals: Vec<String> = values.iter().map(|x| x.literal()).collect();
+    assert_eq!(
+        literals,
+        vec![
+            "[1 -2.5 (a b) (x\\(y\\)z) (back\\\\slash) (hash\\#) true [(n) [3]] {(k) (v)}]",
+            "(line) 10 decode concat (tab) 9 decode concat concat",
+            "[(Person) [(name) (Bob)]] [] instance",
+            "(code) (message) error 5 with-payload",
+        ]
+    );
+
+    // Evaluating the literal gives the same value back
+    for (value, literal) in values.iter().zip(literals) {
+        let mut executor = Executor::new(Mode::Script);
+        executor
+            .evaluate_program(format!("{literal} to-literal"))
+            .unwrap();
+        assert_eq!(executor.stack.len(), 1);
+        assert_eq!(executor.stack[0].clone().get_string(), value.literal());
+    }
+}
+
+#[test]
+fn inspect_value() {
+    let mut executor = Executor::new(Mode::Script);
+
+    assert_eq!(
+        {
+            executor
+                .evaluate_program("[1 {(k) [2 3] (o) {}}] [4 5]".to_string())
+                .unwrap();
+            executor
+                .stack
+                .iter()
+                .map(|x| x.inspect())
+                .collect::<Vec<_>>()
+        },
+        vec![
+            "[\n    1\n    object {\n        k: [2 3]\n        o: object {}\n    }\n]",
+            "[4 5]"
+        ]
+    );
+}
+
+#[test]
+fn quote_and_escape() {
+    let mut executor = Executor::new(Mode::Script);
+
+    // The data with brackets and comments is kept by building the code
+    executor
+        .evaluate_program(
+            "(a \\(b\\] \\#c) (data) var
+            data quote ( \\(x\\) var) concat eval x
+            (\\() data escape-string concat (\\) \\(y\\) var) concat eval y
+            [data 1] quote eval"
+                .to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        executor
+            .stack
+            .iter()
+            .map(|x| x.display())
+            .collect::<Vec<_>>(),
+        vec!["(a (b] #c)", "(a (b] #c)", "[(a (b] #c) 1]"]
+    );
+}
+
+#[test]
+fn token_hooks() {
+    let mut executor = Executor::new(Mode::Script);
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = seen.clone();
+    executor.add_post_token_hook(move |executor, token| {
+        log.lock()
+            .unwrap()
+            .push(format!("{token}:{}", executor.stack.len()));
+        Ok(())
+    });
+    executor.add_pre_token_hook(|_, token| match token {
+        "rm" => Err("rm is not allowed".to_string()),
+        _ => Ok(()),
+    });
+
+    // The tokens of the block are not hooked
+    executor
+        .evaluate_program(
+            "[] (tokens) var (post) (tokens swap append (tokens) set!) on-token 1 2 add"
+                .to_string(),
+        )
+        .unwrap();
+    assert_eq!(executor.stack.last().unwrap().display(), "3");
+    executor.evaluate_program("tokens".to_string()).unwrap();
+    assert_eq!(
+        executor.stack.pop().unwrap().display(),
+        "[(on-token) (1) (2) (add)]"
+    );
+    assert_eq!(
+        seen.lock().unwrap()[..4],
+        ["[]:1", "(tokens):2", "var:0", "(post):1"]
+    );
+
+    assert_eq!(
+        executor.evaluate_program("(x) rm".to_string()),
+        Err(StackError::Runtime {
+            command: "rm".to_string(),
+            token: 2,
+            message: "rm is not allowed".to_string()
+        })
+    );
+}
@@ -0,0 +1,132 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use stack::{Executor, Snapshot, StackError};
+use std::io;
+
+/// Run the program and step through its states in the terminal
+pub fn run(executor: &mut Executor, code: String) -> io::Result<()> {
+    // Record every state first, so that the user can step backward too
+    executor.snapshots = Some(Vec::new());
+    let result = executor.evaluate_program(code);
+    let snapshots = executor.snapshots.take().unwrap_or_default();
+
+    let mut terminal = ratatui::try_init()?;
+    let result = step(&mut terminal, &snapshots, result.err());
+    ratatui::restore();
+    result
+}
+
+/// Handle the keyboard while drawing the current state
+fn step(
+    terminal: &mut DefaultTerminal,
+    snapshots: &[Snapshot],
+    error: Option<StackError>,
+) -> io::Result<()> {
+    let mut current = ListState::default();
+    current.select(if snapshots.is_empty() { None } else { Some(0) });
+
+    loop {
+        terminal.draw(|frame| draw(frame, snapshots, &mut current, &error))?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Right | KeyCode::Down | KeyCode::Char(' ') | KeyCode::Char('l') => {
+                    current.select_next()
+                }
+                KeyCode::Left | KeyCode::Up | KeyCode::Char('h') => current.select_previous(),
+                KeyCode::Home => current.select_first(),
+                KeyCode::End => current.select_last(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Draw the tokens, the stack and the memory
+fn draw(
+    frame: &mut Frame,
+    snapshots: &[Snapshot],
+    current: &mut ListState,
+    error: &Option<StackError>,
+) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+            Constraint::Percentage(30),
+        ])
+        .split(rows[0]);
+
+    let tokens: Vec<ListItem> = snapshots
+        .iter()
+        .map(|snapshot| ListItem::new(snapshot.token.clone()))
+        .collect();
+    frame.render_stateful_widget(
+        List::new(tokens)
+            .block(Block::default().borders(Borders::ALL).title("Tokens"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        columns[0],
+        current,
+    );
+
+    let index = current
+        .selected()
+        .map(|i| i.min(snapshots.len().saturating_sub(1)));
+    let snapshot = index.and_then(|i| snapshots.get(i));
+
+    // Show the stack's top value at the top
+    let stack: Vec<Line> = snapshot
+        .map(|s| {
+            s.stack
+                .iter()
+                .rev()
+                .map(|x| Line::from(x.display()))
+                .collect()
+        })
+        .unwrap_or_default();
+    frame.render_widget(
+        Paragraph::new(stack).block(Block::default().borders(Borders::ALL).title("Stack")),
+        columns[1],
+    );
+
+    let mut memory: Vec<(String, String)> = snapshot
+        .map(|s| {
+            s.memory
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.display()))
+                .collect()
+        })
+        .unwrap_or_default();
+    memory.sort();
+    let memory: Vec<Line> = memory
+        .into_iter()
+        .map(|(name, value)| Line::from(format!("{name}: {value}")))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(memory).block(Block::default().borders(Borders::ALL).title("Variables")),
+        columns[2],
+    );
+
+    let status = match (index, error) {
+        (_, Some(err)) => format!("Stopped: {err}"),
+        (Some(i), None) => format!("Step {} / {}", i + 1, snapshots.len()),
+        (None, None) => "No tokens".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(format!("{status}  (←/→ step, Home/End, q quit)")),
+        rows[1],
+    );
+}